@@ -0,0 +1,259 @@
+use crate::geometry::Hit;
+use crate::ray::Ray;
+use crate::vector::*;
+
+/// Axis-Aligned Bounding Box
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::new(Vec3f::from(f64::INFINITY), Vec3f::from(f64::NEG_INFINITY))
+    }
+}
+
+impl Aabb {
+    pub fn new(min: Vec3f, max: Vec3f) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: &[Vec3f]) -> Self {
+        points
+            .iter()
+            .fold(Self::default(), |bounds, &point| bounds.extend(point))
+    }
+
+    pub fn extend(&self, point: Vec3f) -> Self {
+        Self::new(Vec3f::min(self.min, point), Vec3f::max(self.max, point))
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(
+            Vec3f::min(self.min, other.min),
+            Vec3f::max(self.max, other.max),
+        )
+    }
+
+    pub fn center(&self) -> Vec3f {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Returns index of the longest axis
+    pub fn largest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x > d.y && d.x > d.z {
+            0
+        } else if d.y > d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test, returns distance at which ray enters the box
+    /// 'inv_dir' - Component-wise reciprocal of the ray direction
+    pub fn hit(&self, ray: &Ray, inv_dir: Vec3f, min_t: f64, max_t: f64) -> Option<f64> {
+        let mut t0 = min_t;
+        let mut t1 = max_t;
+
+        for axis in 0..3 {
+            let mut near = (self.min[axis] - ray.origin[axis]) * inv_dir[axis];
+            let mut far = (self.max[axis] - ray.origin[axis]) * inv_dir[axis];
+
+            if near > far {
+                (near, far) = (far, near);
+            }
+
+            // NaN (0 * inf) must not shrink the interval
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+
+            if t1 < t0 {
+                return None;
+            }
+        }
+
+        Some(t0)
+    }
+}
+
+/// Primitive with finite extent
+pub trait Bounded {
+    fn bounds(&self) -> Aabb;
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Node {
+    bounds: Aabb,
+    /// Index of first primitive for leaves, index of left child otherwise
+    offset: usize,
+    /// Number of primitives, zero for interior nodes
+    count: usize,
+}
+
+/// Bounding Volume Hierarchy
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+const MAX_LEAF_SIZE: usize = 4;
+
+impl Bvh {
+    /// Builds hierarchy over the bounds of primitives, splitting at the median
+    /// along the longest axis
+    pub fn new(bounds: &[Aabb]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: (0..bounds.len()).collect(),
+        };
+
+        if !bounds.is_empty() {
+            bvh.nodes.push(Node {
+                bounds: Aabb::default(),
+                offset: 0,
+                count: bounds.len(),
+            });
+            bvh.subdivide(0, bounds);
+        }
+
+        bvh
+    }
+
+    fn subdivide(&mut self, node: usize, bounds: &[Aabb]) {
+        let first = self.nodes[node].offset;
+        let count = self.nodes[node].count;
+        let indices = &mut self.indices[first..first + count];
+
+        let node_bounds = indices
+            .iter()
+            .fold(Aabb::default(), |b, &i| b.union(&bounds[i]));
+        self.nodes[node].bounds = node_bounds;
+
+        if count <= MAX_LEAF_SIZE {
+            return;
+        }
+
+        let centroids = indices
+            .iter()
+            .fold(Aabb::default(), |b, &i| b.extend(bounds[i].center()));
+        let axis = centroids.largest_axis();
+
+        let mid = count / 2;
+        indices.select_nth_unstable_by(mid, |&a, &b| {
+            let ca = bounds[a].center()[axis];
+            let cb = bounds[b].center()[axis];
+            ca.total_cmp(&cb)
+        });
+
+        let left = self.nodes.len();
+        self.nodes.push(Node {
+            bounds: Aabb::default(),
+            offset: first,
+            count: mid,
+        });
+        self.nodes.push(Node {
+            bounds: Aabb::default(),
+            offset: first + mid,
+            count: count - mid,
+        });
+
+        self.nodes[node].offset = left;
+        self.nodes[node].count = 0;
+
+        self.subdivide(left, bounds);
+        self.subdivide(left + 1, bounds);
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map(|n| n.bounds).unwrap_or_default()
+    }
+
+    /// Returns closest hit, 'hit_primitive' is called with the primitive index
+    /// and the current closest distance
+    pub fn hit<F>(&self, ray: &Ray, min_t: f64, max_t: f64, mut hit_primitive: F) -> Option<Hit>
+    where
+        F: FnMut(usize, f64) -> Option<Hit>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inv_dir = Vec3f::from(1.0) / ray.direction;
+        let mut closest: Option<Hit> = None;
+        let mut max_t = max_t;
+
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if node.bounds.hit(ray, inv_dir, min_t, max_t).is_none() {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &primitive in &self.indices[node.offset..node.offset + node.count] {
+                    if let Some(hit) = hit_primitive(primitive, max_t) {
+                        max_t = hit.t;
+                        closest = Some(hit);
+                    }
+                }
+            } else {
+                stack.push(node.offset);
+                stack.push(node.offset + 1);
+            }
+        }
+
+        closest
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bvh::*;
+
+    #[test]
+    fn test_aabb_hit() {
+        let aabb = Aabb::new(Vec3f::from(-1.0), Vec3f::from(1.0));
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        let inv_dir = Vec3f::from(1.0) / ray.direction;
+        assert_eq!(aabb.hit(&ray, inv_dir, 0.0, f64::INFINITY), Some(4.0));
+        assert_eq!(aabb.hit(&ray, inv_dir, 0.0, 3.0), None);
+
+        let ray = Ray::new(Vec3f::new(2.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(aabb.hit(&ray, inv_dir, 0.0, f64::INFINITY), None);
+    }
+
+    #[test]
+    fn test_bvh_closest() {
+        // row of unit boxes along the z axis
+        let bounds: Vec<Aabb> = (0..100)
+            .map(|i| {
+                let center = Vec3f::new(0.0, 0.0, i as f64 * 3.0);
+                Aabb::new(center - 0.5, center + 0.5)
+            })
+            .collect();
+
+        let bvh = Bvh::new(&bounds);
+        assert_eq!(bvh.bounds().min, Vec3f::new(-0.5, -0.5, -0.5));
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 100.0), Vec3f::new(0.0, 0.0, -1.0));
+        let inv_dir = Vec3f::from(1.0) / ray.direction;
+
+        let hit = bvh.hit(&ray, 0.0, f64::INFINITY, |i, max_t| {
+            bounds[i]
+                .hit(&ray, inv_dir, 0.0, max_t)
+                .map(|t| Hit::new(t, Vec3f::from(0.0), ray.point_at(t), i))
+        });
+
+        // box 33 is centered at z = 99
+        let hit = hit.unwrap();
+        assert_eq!(hit.idx, 33);
+        assert_eq!(hit.t, 0.5);
+    }
+}
@@ -36,6 +36,19 @@ impl Camera {
         }
     }
 
+    /// Camera as written in a scene file with 'fov' in degrees, pass its values to 'new' to render
+    pub fn description(position: Vec3f, target: Vec3f, fov: f64) -> Self {
+        Camera {
+            position,
+            target,
+            fov,
+            resolution: Vec2f::from(1.0),
+            aspect_ratio: 1.0,
+            focal_length: 3.0,
+            aperture: 0.001,
+        }
+    }
+
     pub fn get_ray(&self, pixel: (u32, u32)) -> Ray {
         let coord = Vec2f::from(pixel) / self.resolution;
 
@@ -0,0 +1,250 @@
+use crate::common::*;
+use crate::material::*;
+use crate::spectrum::*;
+use crate::vector::*;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Dielectric layer over the material, like varnish or the clearcoat of car paint
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Coat {
+    pub ior: f64,
+    /// GGX roughness of the coat surface, as for Transparent
+    pub roughness: f64,
+    /// Thickness of the layer in world units
+    pub thickness: f64,
+    /// Absorption coefficient per unit distance inside the layer
+    pub absorption: Vec3f,
+}
+
+impl Default for Coat {
+    fn default() -> Self {
+        Self {
+            ior: 1.5,
+            roughness: 0.0,
+            thickness: 0.0,
+            absorption: Vec3f::from(0.0),
+        }
+    }
+}
+
+// Weidlich and Wilkie 2007, "Arbitrarily Layered Micro-Facet Surfaces"
+// The base is evaluated for the outside directions, attenuated by the Fresnel transmission
+// into and out of the layer and by the absorption along the refracted paths
+impl Coat {
+    /// Coat with the absorption replaced by its spectrum at 'lambda' nanometers
+    pub fn at_wavelength(&self, lambda: f64) -> Coat {
+        Coat {
+            absorption: Vec3f::from(rgb_to_spectrum(self.absorption, lambda)),
+            ..*self
+        }
+    }
+
+    /// Smooth coats are sharp rough ones, so that they combine with the lobes of the base
+    fn alpha(&self) -> f64 {
+        f64::max(self.roughness, SMOOTH_ROUGHNESS)
+    }
+
+    /// Fraction of the light passing into the layer at 'cos_theta' and the path length factor
+    fn enter(&self, cos_theta: f64) -> (f64, f64) {
+        let transmitted = 1.0 - fresnel_dielectric(cos_theta, self.ior);
+        let sin2 = (1.0 - cos_theta * cos_theta) / (self.ior * self.ior);
+        (transmitted, 1.0 / f64::sqrt(1.0 - sin2))
+    }
+
+    /// Attenuation of the base for light entering along 'wo' and leaving along 'wi'
+    fn transmittance(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        let (t_o, length_o) = self.enter(Vec3f::dot(normal, wo).abs());
+        let (t_i, length_i) = self.enter(Vec3f::dot(normal, wi).abs());
+        let depth = self.thickness * (length_o + length_i);
+        let a = self.absorption;
+
+        Vec3f::new(
+            f64::exp(-a.x * depth),
+            f64::exp(-a.y * depth),
+            f64::exp(-a.z * depth),
+        ) * (t_o * t_i)
+    }
+
+    /// GGX reflection off the top of the layer
+    fn reflection(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        let cos_o = Vec3f::dot(normal, wo);
+        let cos_i = Vec3f::dot(normal, wi);
+
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return 0.0;
+        }
+
+        let halfway = Vec3f::normalize(wo + wi);
+        let distribution = distribution_ggx(normal, halfway, self.alpha());
+        let geometry = smith_g1_ggx(wo, halfway, normal, self.alpha())
+            * smith_g1_ggx(wi, halfway, normal, self.alpha());
+        let fresnel = fresnel_dielectric(Vec3f::dot(wo, halfway), self.ior);
+
+        fresnel * distribution * geometry / (4.0 * cos_o * cos_i)
+    }
+
+    fn reflection_pdf(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        let halfway = wo + wi;
+
+        if Vec3f::dot(normal, wi) <= 0.0 || halfway.length() == 0.0 {
+            return 0.0;
+        }
+
+        let halfway = halfway.normalize();
+        let oh = Vec3f::dot(wo, halfway);
+        visible_normal_pdf(normal, wo, halfway, self.alpha()) / (4.0 * oh)
+    }
+
+    /// Probability of sampling the coat instead of the base, by their share of reflected light
+    fn probability(&self, base: &Material, cos_o: f64) -> f64 {
+        let fresnel = fresnel_dielectric(cos_o, self.ior);
+        let albedo = (base.albedo.x + base.albedo.y + base.albedo.z) / 3.0;
+        let sum = fresnel + (1.0 - fresnel) * albedo;
+
+        if sum > 0.0 {
+            fresnel / sum
+        } else {
+            1.0
+        }
+    }
+
+    /// Light arriving from below the surface sees the base only
+    pub(crate) fn bsdf(&self, base: &Material, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        if Vec3f::dot(normal, wo) <= 0.0 {
+            return base.bsdf(normal, wo, wi);
+        }
+
+        Vec3f::from(self.reflection(normal, wo, wi))
+            + self.transmittance(normal, wo, wi) * base.bsdf(normal, wo, wi)
+    }
+
+    pub(crate) fn pdf(&self, base: &Material, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        let cos_o = Vec3f::dot(normal, wo);
+        if cos_o <= 0.0 {
+            return base.pdf(normal, wo, wi);
+        }
+
+        let p = self.probability(base, cos_o);
+        p * self.reflection_pdf(normal, wo, wi) + (1.0 - p) * base.pdf(normal, wo, wi)
+    }
+
+    pub(crate) fn sample_f(&self, base: &Material, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64) {
+        let cos_o = Vec3f::dot(normal, wo);
+        if cos_o <= 0.0 {
+            return base.sample_f(normal, wo);
+        }
+
+        let wi = if rand::thread_rng().gen_range(0.0..1.0) < self.probability(base, cos_o) {
+            reflect(-wo, sample_visible_normal(normal, wo, self.alpha()))
+        } else {
+            base.sample_f(normal, wo).0
+        };
+
+        (wi, self.pdf(base, normal, wo, wi))
+    }
+
+    pub(crate) fn sample(&self, base: &Material, normal: Vec3f, wo: Vec3f) -> (Vec3f, Vec3f) {
+        let (wi, pdf) = self.sample_f(base, normal, wo);
+        if pdf == 0.0 {
+            return (wi, Vec3f::from(0.0));
+        }
+        let cos_theta = Vec3f::dot(normal, wi).abs();
+        (wi, self.bsdf(base, normal, wo, wi) * cos_theta / pdf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::coat::*;
+
+    fn albedo(material: &Material, wo: Vec3f) -> Vec3f {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let n = 40000;
+        let mut sum = Vec3f::from(0.0);
+        for _ in 0..n {
+            sum += material.sample(normal, wo).1;
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn test_coat_energy() {
+        let coat = Coat {
+            roughness: 0.05,
+            ..Default::default()
+        };
+
+        // a coated white diffuse stays below one, and grazing light mostly reflects off the coat
+        for wo in [
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(0.5, 0.0, 1.0).normalize(),
+            Vec3f::new(1.0, 0.0, 0.1).normalize(),
+        ] {
+            let white = Material {
+                albedo: Vec3f::from(1.0),
+                roughness: 0.3,
+                coat: Some(coat),
+                ..Default::default()
+            };
+            let albedo = albedo(&white, wo);
+            assert!(albedo.x > 0.8 && albedo.x < 1.02, "{:?} {:?}", wo, albedo);
+        }
+
+        // a black base shows the Fresnel reflectance of the coat alone
+        let black = Material {
+            albedo: Vec3f::from(0.0),
+            roughness: 0.3,
+            coat: Some(coat),
+            ..Default::default()
+        };
+        let albedo = albedo(&black, Vec3f::new(0.0, 0.0, 1.0));
+        assert!((albedo.x - 0.04).abs() < 0.01, "{:?}", albedo);
+    }
+
+    #[test]
+    fn test_coat_absorption() {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let coat = Coat {
+            roughness: 0.2,
+            thickness: 0.1,
+            absorption: Vec3f::new(0.0, 5.0, 10.0),
+            ..Default::default()
+        };
+        let material = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.3,
+            coat: Some(coat),
+            ..Default::default()
+        };
+
+        // the base is tinted by the layer, the coat reflection is not
+        let wo = Vec3f::new(0.0, 0.0, 1.0);
+        let diffuse = material.bsdf(normal, wo, Vec3f::new(0.6, 0.0, 0.8));
+        assert!(diffuse.x > diffuse.y && diffuse.y > diffuse.z);
+
+        let specular = material.bsdf(normal, wo, wo);
+        assert!(specular.z > 0.04 / std::f64::consts::PI);
+    }
+
+    #[test]
+    fn test_coat_pdf() {
+        // sampled densities agree with the density evaluated for the same direction
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::new(0.3, 0.2, 1.0).normalize();
+        let material = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.3,
+            material: MaterialType::OrenNayar,
+            coat: Some(Coat::default()),
+            ..Default::default()
+        };
+
+        for _ in 0..100 {
+            let (wi, pdf) = material.sample_f(normal, wo);
+            assert!((pdf - material.pdf(normal, wo, wi)).abs() <= 1e-9 * pdf);
+        }
+    }
+}
@@ -5,6 +5,7 @@ use std::f64::consts::PI;
 
 use crate::camera::*;
 use crate::geometry::*;
+use crate::mesh::Mesh;
 use crate::vector::*;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,6 +14,13 @@ pub struct ConfigFile {
     pub camera: Camera,
 }
 
+impl ConfigFile {
+    /// Parses a scene file, meshes used several times are loaded once
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        Mesh::with_cache(|| serde_json::from_str(json))
+    }
+}
+
 #[allow(dead_code)]
 pub fn reflect(incident: Vec3f, normal: Vec3f) -> Vec3f {
     incident - normal * 2.0 * Vec3f::dot(incident, normal)
@@ -57,11 +65,16 @@ pub fn refract_glsl(incident: Vec3f, normal: Vec3f, eta: f64) -> Vec3f {
     }
 }
 
-///
+/// Returns fraction of light that is transmitted (1 - reflectance)
 pub fn fresnel(incident: Vec3f, normal: Vec3f, ior: f64) -> f64 {
     let mut cosi = Vec3f::dot(incident, normal);
-    let etai = 1.0;
-    let etat = ior;
+    let mut etai = 1.0;
+    let mut etat = ior;
+
+    if cosi > 0.0 {
+        // leaving the medium
+        (etai, etat) = (etat, etai);
+    }
 
     let sint = etai / etat * f64::sqrt(f64::max(0.0, 1.0 - cosi * cosi));
 
@@ -113,6 +126,41 @@ pub fn cosine_weighted_hemisphere() -> Vec3f {
     Vec3f::normalize(from_spherical(theta, phi))
 }
 
+/// Sample of the GGX normals visible from 'wo' around +z, with density G1(wo) max(0, wo.h) D(h) / wo.z
+/// 'alpha_x' and 'alpha_y' - roughness along the x and y axes
+// Heitz 2018, "Sampling the GGX Distribution of Visible Normals"
+pub fn ggx_visible_normal(wo: Vec3f, alpha_x: f64, alpha_y: f64) -> Vec3f {
+    let mut rng = rand::thread_rng();
+    let r1: f64 = rng.gen_range(0.0..1.0);
+    let r2 = rng.gen_range(0.0..1.0);
+
+    // stretch to the hemisphere configuration
+    let vh = Vec3f::normalize(Vec3f::new(alpha_x * wo.x, alpha_y * wo.y, wo.z));
+
+    let lensq = vh.x * vh.x + vh.y * vh.y;
+    let t1 = if lensq > 0.0 {
+        Vec3f::new(-vh.y, vh.x, 0.0) / lensq.sqrt()
+    } else {
+        Vec3f::new(1.0, 0.0, 0.0)
+    };
+    let t2 = Vec3f::cross(vh, t1);
+
+    // sample the projected area, warped toward the visible half of the disk
+    let r = r1.sqrt();
+    let phi = 2.0 * PI * r2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * f64::sqrt(1.0 - p1 * p1) + s * r * phi.sin();
+
+    let nh = t1 * p1 + t2 * p2 + vh * f64::sqrt(f64::max(0.0, 1.0 - p1 * p1 - p2 * p2));
+
+    Vec3f::normalize(Vec3f::new(
+        alpha_x * nh.x,
+        alpha_y * nh.y,
+        f64::max(0.0, nh.z),
+    ))
+}
+
 // https://agraphicsguy.wordpress.com/2015/11/01/sampling-microfacet-brdf/
 // https://computergraphics.stackexchange.com/questions/4979/what-is-importance-sampling
 // https://schuttejoe.github.io/post/ggximportancesamplingpart1/
@@ -192,7 +240,7 @@ mod test {
         height: usize,
         sample_hemisphere: impl Fn() -> Vec3f,
     ) -> RgbImage {
-        let mut buffer = vec![Vec3f::from(0.0); (width * height) as usize];
+        let mut buffer = vec![Vec3f::from(0.0); width * height];
 
         let samples = 10000;
         for _ in 0..samples {
@@ -206,7 +254,7 @@ mod test {
             let y = (sample.y.clamp(0.0, 0.999) * h) as usize;
             assert!(x < width && y < height);
 
-            let index = (y * width + x) as usize;
+            let index = y * width + x;
             let blue = Vec3::new(0.0, 0.0, 1.0);
             let red = Vec3::new(1.0, 0.0, 0.0);
             buffer[index] = Vec3::lerp(blue, red, vec.z.clamp(0.0, 1.0));
@@ -247,15 +295,29 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_fresnel_total_internal_reflection() {
+        let ior = 1.5;
+        let normal = Vec3f::new(0.0, 1.0, 0.0);
+
+        // leaving glass at a grazing angle, beyond the critical angle
+        let incident = Vec3::normalize(Vec3f::new(1.0, 0.5, 0.0));
+        assert_eq!(fresnel(incident, normal, ior), 0.0);
+        assert_eq!(refract(incident, normal, ior), Vec3f::from(0.0));
+
+        // entering glass at the same angle is mostly transmitted
+        assert!(fresnel(-incident, normal, ior) > 0.5);
+    }
+
     #[test]
     fn test_cosine() {
-        let image = create_image_from_distribution(200, 200, || cosine_weighted_hemisphere());
+        let image = create_image_from_distribution(200, 200, cosine_weighted_hemisphere);
         let _ = image.save("renders/cosine.png");
     }
 
     #[test]
     fn test_uniform_hemisphere() {
-        let image = create_image_from_distribution(200, 200, || uniform_hemisphere());
+        let image = create_image_from_distribution(200, 200, uniform_hemisphere);
         let _ = image.save("renders/uniform_v1.png");
     }
 
@@ -0,0 +1,131 @@
+use crate::spectrum::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+
+/// Complex index of refraction (eta + i k) of a metal, per color channel
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "ConductorDesc")]
+pub struct Conductor {
+    pub eta: Vec3f,
+    pub k: Vec3f,
+}
+
+/// Conductor as written in the scene file, a preset name or explicit values
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConductorDesc {
+    Preset(String),
+    Ior { eta: Vec3f, k: Vec3f },
+}
+
+impl TryFrom<ConductorDesc> for Conductor {
+    type Error = String;
+
+    fn try_from(desc: ConductorDesc) -> Result<Self, Self::Error> {
+        match desc {
+            ConductorDesc::Preset(name) => {
+                Conductor::preset(&name).ok_or(format!("unknown conductor {:?}", name))
+            }
+            ConductorDesc::Ior { eta, k } => Ok(Conductor::new(eta, k)),
+        }
+    }
+}
+
+/// Reflectance of a smooth conductor for one channel
+/// 'cos_i' - cosine of the incident direction, 'eta' and 'k' - relative to the outside medium
+fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = f64::sqrt(t0 * t0 + 4.0 * eta2 * k2);
+    let t1 = a2_plus_b2 + cos2;
+    let a = f64::sqrt(0.5 * (a2_plus_b2 + t0));
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3f, k: Vec3f) -> Self {
+        Self { eta, k }
+    }
+
+    /// Measured metals, by name or chemical symbol
+    pub fn preset(name: &str) -> Option<Conductor> {
+        let (eta, k) = match name.to_lowercase().as_str() {
+            "gold" | "au" => ([0.143119, 0.374957, 1.44248], [3.98316, 2.38572, 1.60322]),
+            "silver" | "ag" => ([0.155265, 0.116723, 0.138342], [4.82835, 3.12225, 2.14696]),
+            "copper" | "cu" => ([0.200438, 0.924033, 1.10221], [3.91295, 2.45285, 2.14219]),
+            "aluminium" | "aluminum" | "al" => {
+                ([1.65746, 0.880369, 0.521229], [9.22387, 6.26952, 4.837])
+            }
+            "chromium" | "cr" => ([4.36968, 2.9167, 1.6547], [5.20643, 4.23136, 3.75495]),
+            _ => return None,
+        };
+        Some(Conductor::new(Vec3f::from(eta), Vec3f::from(k)))
+    }
+
+    /// Exact Fresnel reflectance for light arriving at 'cos_i' to the normal
+    pub fn fresnel(&self, cos_i: f64) -> Vec3f {
+        let cos_i = cos_i.clamp(0.0, 1.0);
+        Vec3f::new(
+            fresnel_conductor(cos_i, self.eta.x, self.k.x),
+            fresnel_conductor(cos_i, self.eta.y, self.k.y),
+            fresnel_conductor(cos_i, self.eta.z, self.k.z),
+        )
+    }
+
+    /// Conductor with eta and k replaced by their spectra at 'lambda' nanometers
+    pub fn at_wavelength(&self, lambda: f64) -> Conductor {
+        Conductor::new(
+            Vec3f::from(rgb_to_spectrum(self.eta, lambda)),
+            Vec3f::from(rgb_to_spectrum(self.k, lambda)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::conductor::*;
+
+    #[test]
+    fn test_fresnel() {
+        let gold = Conductor::preset("Gold").unwrap();
+
+        // normal incidence has a closed form
+        let (n, k) = (gold.eta.x, gold.k.x);
+        let expected = ((n - 1.0) * (n - 1.0) + k * k) / ((n + 1.0) * (n + 1.0) + k * k);
+        assert!((gold.fresnel(1.0).x - expected).abs() < 1e-12);
+
+        // yellow tint, and every metal becomes a perfect mirror at grazing angles
+        let f = gold.fresnel(1.0);
+        assert!(f.x > f.y && f.y > f.z);
+        assert!((gold.fresnel(0.0) - Vec3f::from(1.0)).length() < 1e-9);
+
+        let silver = Conductor::preset("ag").unwrap().fresnel(1.0);
+        assert!(silver.x > 0.9 && silver.z > 0.9);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let preset: Conductor = serde_json::from_str(r#""copper""#).unwrap();
+        assert_eq!(preset, Conductor::preset("cu").unwrap());
+
+        let custom: Conductor =
+            serde_json::from_str(r#"{ "eta": [1, 1, 1], "k": [2, 2, 2] }"#).unwrap();
+        assert_eq!(custom.k, Vec3f::from(2.0));
+
+        let json = serde_json::to_string(&custom).unwrap();
+        assert_eq!(serde_json::from_str::<Conductor>(&json).unwrap(), custom);
+
+        assert!(serde_json::from_str::<Conductor>(r#""unobtainium""#).is_err());
+    }
+}
@@ -0,0 +1,501 @@
+use crate::geometry::*;
+use crate::ray::Ray;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+
+/// Interval along a ray that lies inside a solid
+/// Normals point out of the solid
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Span {
+    pub enter: f64,
+    pub enter_normal: Vec3f,
+    pub exit: f64,
+    pub exit_normal: Vec3f,
+}
+
+impl Span {
+    pub fn new(enter: f64, enter_normal: Vec3f, exit: f64, exit_normal: Vec3f) -> Self {
+        Self {
+            enter,
+            enter_normal,
+            exit,
+            exit_normal,
+        }
+    }
+
+    /// Span covering the whole ray
+    fn unbounded() -> Self {
+        let zero = Vec3f::from(0.0);
+        Self::new(f64::NEG_INFINITY, zero, f64::INFINITY, zero)
+    }
+
+    /// Overlap of two spans of convex solids
+    fn overlap(&self, other: &Span) -> Option<Span> {
+        let (enter, enter_normal) = if self.enter > other.enter {
+            (self.enter, self.enter_normal)
+        } else {
+            (other.enter, other.enter_normal)
+        };
+
+        let (exit, exit_normal) = if self.exit < other.exit {
+            (self.exit, self.exit_normal)
+        } else {
+            (other.exit, other.exit_normal)
+        };
+
+        if enter < exit {
+            Some(Span::new(enter, enter_normal, exit, exit_normal))
+        } else {
+            None
+        }
+    }
+}
+
+/// Closed geometry with a well-defined inside
+pub trait Intervals {
+    /// Returns sorted, disjoint spans where the (infinite) ray is inside the solid
+    fn spans(&self, ray: &Ray) -> Vec<Span>;
+}
+
+/// Returns the closest span boundary inside (min_t, max_t)
+fn closest_boundary(spans: &[Span], ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
+    for span in spans {
+        for (t, normal) in [
+            (span.enter, span.enter_normal),
+            (span.exit, span.exit_normal),
+        ] {
+            if max_t <= t {
+                return None;
+            }
+            if min_t < t {
+                return Some(Hit::new(t, normal, ray.point_at(t), 0));
+            }
+        }
+    }
+    None
+}
+
+/// Span of the slab 'min <= origin + t * direction <= max' along one axis
+fn slab(origin: f64, direction: f64, min: f64, max: f64, axis: Vec3f) -> Option<Span> {
+    if direction.abs() < f64::EPSILON {
+        return if min <= origin && origin <= max {
+            Some(Span::unbounded())
+        } else {
+            None
+        };
+    }
+
+    let t0 = (min - origin) / direction;
+    let t1 = (max - origin) / direction;
+
+    if t0 < t1 {
+        Some(Span::new(t0, -axis, t1, axis))
+    } else {
+        Some(Span::new(t1, axis, t0, -axis))
+    }
+}
+
+impl Intervals for Sphere {
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        let m = ray.origin - self.center;
+        let a = Vec3::dot(ray.direction, ray.direction);
+        let b = Vec3::dot(m, ray.direction);
+        let c = Vec3::dot(m, m) - self.radius * self.radius;
+
+        let discr = b * b - a * c;
+        if discr <= 0.0 {
+            return vec![];
+        }
+
+        let t0 = (-b - discr.sqrt()) / a;
+        let t1 = (-b + discr.sqrt()) / a;
+
+        let normal = |t| (ray.point_at(t) - self.center) / self.radius;
+        vec![Span::new(t0, normal(t0), t1, normal(t1))]
+    }
+}
+
+/// Axis-aligned box
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Cuboid {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl Cuboid {
+    pub fn new(min: Vec3f, max: Vec3f) -> Self {
+        Self { min, max }
+    }
+}
+
+impl Intervals for Cuboid {
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        let axes = [
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+        ];
+
+        let mut span = Span::unbounded();
+        for (i, axis) in axes.into_iter().enumerate() {
+            let overlap = slab(
+                ray.origin[i],
+                ray.direction[i],
+                self.min[i],
+                self.max[i],
+                axis,
+            )
+            .and_then(|s| span.overlap(&s));
+
+            match overlap {
+                Some(s) => span = s,
+                None => return vec![],
+            }
+        }
+
+        vec![span]
+    }
+}
+
+/// Capped cylinder, parallel to the y axis
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Cylinder {
+    pub center: Vec3f,
+    pub radius: f64,
+    pub height: f64,
+}
+
+impl Cylinder {
+    pub fn new(center: Vec3f, radius: f64, height: f64) -> Self {
+        Self {
+            center,
+            radius,
+            height,
+        }
+    }
+}
+
+impl Intervals for Cylinder {
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        let m = ray.origin - self.center;
+        let d = ray.direction;
+
+        // infinite cylinder around the axis
+        let a = d.x * d.x + d.z * d.z;
+        let b = m.x * d.x + m.z * d.z;
+        let c = m.x * m.x + m.z * m.z - self.radius * self.radius;
+
+        let tube = if a < f64::EPSILON {
+            if c < 0.0 {
+                Span::unbounded()
+            } else {
+                return vec![];
+            }
+        } else {
+            let discr = b * b - a * c;
+            if discr <= 0.0 {
+                return vec![];
+            }
+
+            let t0 = (-b - discr.sqrt()) / a;
+            let t1 = (-b + discr.sqrt()) / a;
+
+            let normal = |t: f64| {
+                let p = m + d * t;
+                Vec3f::new(p.x, 0.0, p.z) / self.radius
+            };
+            Span::new(t0, normal(t0), t1, normal(t1))
+        };
+
+        let half = self.height / 2.0;
+        let caps = slab(m.y, d.y, -half, half, Vec3f::new(0.0, 1.0, 0.0));
+
+        caps.and_then(|caps| tube.overlap(&caps))
+            .into_iter()
+            .collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Operation {
+    Union,
+    Intersection,
+    /// Left minus right
+    Difference,
+}
+
+impl Operation {
+    fn inside(&self, left: bool, right: bool) -> bool {
+        match self {
+            Operation::Union => left || right,
+            Operation::Intersection => left && right,
+            Operation::Difference => left && !right,
+        }
+    }
+}
+
+/// Solid that can be used as an operand of constructive solid geometry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Solid {
+    CSG(Csg),
+    CYLINDER(Cylinder),
+    SPHERE(Sphere),
+    CUBOID(Cuboid),
+}
+
+/// Constructive Solid Geometry node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Csg {
+    pub operation: Operation,
+    pub left: Box<Solid>,
+    pub right: Box<Solid>,
+}
+
+impl Csg {
+    pub fn new(operation: Operation, left: Solid, right: Solid) -> Self {
+        Self {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+}
+
+struct Event {
+    t: f64,
+    normal: Vec3f,
+    enter: bool,
+    left: bool,
+}
+
+/// Combines the spans of both operands by sweeping over their boundaries
+fn combine(operation: Operation, left: &[Span], right: &[Span]) -> Vec<Span> {
+    let mut events = Vec::with_capacity(2 * (left.len() + right.len()));
+
+    for (spans, is_left) in [(left, true), (right, false)] {
+        for span in spans {
+            events.push(Event {
+                t: span.enter,
+                normal: span.enter_normal,
+                enter: true,
+                left: is_left,
+            });
+            events.push(Event {
+                t: span.exit,
+                normal: span.exit_normal,
+                enter: false,
+                left: is_left,
+            });
+        }
+    }
+
+    events.sort_by(|a, b| a.t.total_cmp(&b.t));
+
+    let mut spans = Vec::new();
+    let mut start: Option<(f64, Vec3f)> = None;
+    let (mut in_left, mut in_right) = (false, false);
+
+    for event in events {
+        let was_inside = operation.inside(in_left, in_right);
+
+        if event.left {
+            in_left = event.enter;
+        } else {
+            in_right = event.enter;
+        }
+
+        let is_inside = operation.inside(in_left, in_right);
+
+        // surface of the subtracted solid faces the other way
+        let normal = if operation == Operation::Difference && !event.left {
+            -event.normal
+        } else {
+            event.normal
+        };
+
+        if !was_inside && is_inside {
+            start = Some((event.t, normal));
+        } else if was_inside && !is_inside {
+            if let Some((enter, enter_normal)) = start.take() {
+                if enter < event.t {
+                    spans.push(Span::new(enter, enter_normal, event.t, normal));
+                }
+            }
+        }
+    }
+
+    spans
+}
+
+impl Intervals for Csg {
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        let left = self.left.spans(ray);
+
+        // nothing to subtract from or intersect with
+        if left.is_empty() && self.operation != Operation::Union {
+            return left;
+        }
+
+        let right = self.right.spans(ray);
+        combine(self.operation, &left, &right)
+    }
+}
+
+impl Intervals for Solid {
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        match self {
+            Solid::CSG(s) => s.spans(ray),
+            Solid::CYLINDER(s) => s.spans(ray),
+            Solid::SPHERE(s) => s.spans(ray),
+            Solid::CUBOID(s) => s.spans(ray),
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
+        closest_boundary(&self.spans(ray), ray, min_t, max_t)
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
+        closest_boundary(&self.spans(ray), ray, min_t, max_t)
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
+        closest_boundary(&self.spans(ray), ray, min_t, max_t)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::csg::*;
+
+    fn assert_near(a: Vec3f, b: Vec3f) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    fn lens() -> Csg {
+        // biconvex lens, 0.5 thick, centered at z = 5
+        Csg::new(
+            Operation::Intersection,
+            Solid::SPHERE(Sphere::new(Vec3f::new(0.0, 0.0, 6.75), 2.0)),
+            Solid::SPHERE(Sphere::new(Vec3f::new(0.0, 0.0, 3.25), 2.0)),
+        )
+    }
+
+    #[test]
+    fn test_cuboid_hit() {
+        let cuboid = Cuboid::new(Vec3f::new(-1.0, -1.0, 4.0), Vec3f::new(1.0, 1.0, 6.0));
+        let ray = Ray::new(Vec3f::new(0.5, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+
+        let hit = cuboid.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit.t, 4.0);
+        assert_eq!(hit.normal, Vec3f::new(0.0, 0.0, -1.0));
+
+        // from the inside, the exit normal still points outward
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 5.0), Vec3f::new(1.0, 0.0, 0.0));
+        let hit = cuboid.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert_eq!(hit.normal, Vec3f::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_cylinder_hit() {
+        let cylinder = Cylinder::new(Vec3f::new(0.0, 0.0, 5.0), 1.0, 2.0);
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        let hit = cylinder.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit.t, 4.0);
+        assert_eq!(hit.normal, Vec3f::new(0.0, 0.0, -1.0));
+
+        let ray = Ray::new(Vec3f::new(0.5, 5.0, 5.0), Vec3f::new(0.0, -1.0, 0.0));
+        let hit = cylinder.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit.t, 4.0);
+        assert_eq!(hit.normal, Vec3f::new(0.0, 1.0, 0.0));
+
+        let ray = Ray::new(Vec3f::new(0.0, 1.5, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(cylinder.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_intersection() {
+        let lens = lens();
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+
+        let spans = lens.spans(&ray);
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].enter - 4.75).abs() < 1e-9);
+        assert!((spans[0].exit - 5.25).abs() < 1e-9);
+        assert_near(spans[0].enter_normal, Vec3f::new(0.0, 0.0, -1.0));
+        assert_near(spans[0].exit_normal, Vec3f::new(0.0, 0.0, 1.0));
+
+        // refracted ray continues from inside the lens
+        let inside = Ray::new(Vec3f::new(0.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, 1.0));
+        let hit = lens.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 0.25).abs() < 1e-9);
+        assert_near(hit.normal, Vec3f::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_difference() {
+        // cube with a spherical bite taken out of its front face
+        let csg = Csg::new(
+            Operation::Difference,
+            Solid::CUBOID(Cuboid::new(Vec3f::from(-1.0), Vec3f::from(1.0))),
+            Solid::SPHERE(Sphere::new(Vec3f::new(0.0, 0.0, -1.0), 0.5)),
+        );
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        let hit = csg.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-9);
+        // inner surface of the bite faces the hole
+        assert_near(hit.normal, Vec3f::new(0.0, 0.0, -1.0));
+
+        let ray = Ray::new(Vec3f::new(0.9, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        let hit = csg.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit.t, 4.0);
+    }
+
+    #[test]
+    fn test_union() {
+        let csg = Csg::new(
+            Operation::Union,
+            Solid::SPHERE(Sphere::new(Vec3f::new(0.0, 0.0, 5.0), 1.0)),
+            Solid::SPHERE(Sphere::new(Vec3f::new(0.0, 0.0, 6.0), 1.0)),
+        );
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        let spans = csg.spans(&ray);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].enter, 4.0);
+        assert_eq!(spans[0].exit, 7.0);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let json = r#"{
+            "operation": "Difference",
+            "left": { "min": [-1, -1, -1], "max": [1, 1, 1] },
+            "right": {
+                "operation": "Union",
+                "left": { "center": [0, 0, 0], "radius": 0.5, "height": 3 },
+                "right": { "center": [0, 0, 0], "radius": 1.2 }
+            }
+        }"#;
+        let csg: Csg = serde_json::from_str(json).unwrap();
+        assert!(matches!(*csg.left, Solid::CUBOID(_)));
+        match *csg.right {
+            Solid::CSG(ref inner) => {
+                assert!(matches!(*inner.left, Solid::CYLINDER(_)));
+                assert!(matches!(*inner.right, Solid::SPHERE(_)));
+            }
+            _ => panic!("expected csg"),
+        }
+    }
+}
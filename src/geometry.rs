@@ -1,21 +1,38 @@
+use crate::common::*;
+use crate::csg::*;
 use crate::material::*;
+use crate::medium::Medium;
+use crate::mesh::*;
+use crate::onb::Onb;
 use crate::ray::Ray;
+use crate::transform::Transform;
 use crate::vector::*;
-use crate::common::*;
 
 use serde::de;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::f64::consts::PI;
-use std::fs::File;
-use std::io;
-use std::io::{BufRead, BufReader};
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct Hit {
     pub t: f64,
+    /// Shading normal
     pub normal: Vec3f,
     pub point: Vec3f,
     pub idx: usize,
+    /// Normal of the actual surface, used to offset rays
+    pub geometric_normal: Vec3f,
+    /// Weights of the second and third triangle vertex
+    pub barycentric: Vec2f,
+    /// Texture coordinates
+    pub uv: Vec2f,
+    /// Derivatives of the point along u and v, zero when the surface has no parameterization
+    pub tangent: Vec3f,
+    pub bitangent: Vec3f,
+    /// Index into the materials of the mesh, overrides the object material
+    pub material: Option<usize>,
+    /// Interpolated vertex color tinting the albedo, white without vertex colors
+    pub color: Vec3f,
 }
 
 impl Default for Hit {
@@ -25,6 +42,13 @@ impl Default for Hit {
             normal: Vec3f::from(0.0),
             point: Vec3f::from(0.0),
             idx: 0,
+            geometric_normal: Vec3f::from(0.0),
+            barycentric: Vec2f::from(0.0),
+            uv: Vec2f::from(0.0),
+            tangent: Vec3f::from(0.0),
+            bitangent: Vec3f::from(0.0),
+            material: None,
+            color: Vec3f::from(1.0),
         }
     }
 }
@@ -36,11 +60,48 @@ impl Hit {
             normal,
             point,
             idx,
+            geometric_normal: normal,
+            barycentric: Vec2f::from(0.0),
+            uv: Vec2f::from(0.0),
+            tangent: Vec3f::from(0.0),
+            bitangent: Vec3f::from(0.0),
+            material: None,
+            color: Vec3f::from(1.0),
+        }
+    }
+
+    /// Orthonormal tangent and bitangent around the shading normal
+    /// Follows the texture parameterization when known, otherwise an arbitrary frame
+    pub fn tangent_frame(&self) -> (Vec3f, Vec3f) {
+        let tangent = self.tangent - self.normal * Vec3f::dot(self.normal, self.tangent);
+
+        if tangent.length() < 1e-12 {
+            let onb = Onb::new(self.normal);
+            return (onb.u(), onb.v());
+        }
+
+        let tangent = tangent.normalize();
+        let bitangent = Vec3f::cross(self.normal, tangent);
+
+        // mirrored texture coordinates flip the handedness
+        if Vec3f::dot(bitangent, self.bitangent) < 0.0 {
+            (tangent, -bitangent)
+        } else {
+            (tangent, bitangent)
         }
     }
 
     pub fn get_point(&self) -> Vec3f {
-        self.point + self.normal * 0.001
+        self.point + self.geometric_normal * 0.001
+    }
+
+    /// Returns hit point moved off the surface, to the side 'direction' points to
+    pub fn get_point_towards(&self, direction: Vec3f) -> Vec3f {
+        if Vec3f::dot(direction, self.geometric_normal) < 0.0 {
+            self.point - self.geometric_normal * 0.001
+        } else {
+            self.get_point()
+        }
     }
 }
 
@@ -56,97 +117,110 @@ impl Sphere {
         Sphere { center, radius }
     }
 
-    pub fn surface_area(&self) -> f64 {
-        4.0 * PI * self.radius * self.radius
+    /// Spherical coordinates of the surface point with outward 'normal'
+    /// v is 1 at the top (-y, as y points down in the scene)
+    pub fn uv(&self, normal: Vec3f) -> Vec2f {
+        let u = 0.5 + f64::atan2(normal.z, normal.x) / (2.0 * PI);
+        let v = 0.5 - f64::asin(normal.y.clamp(-1.0, 1.0)) / PI;
+        Vec2f::new(u, v)
     }
-}
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
-pub struct Triangle(pub Vec3f, pub Vec3f, pub Vec3f);
-
-/// Convex polygon mesh
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Mesh {
-    triangles: Vec<Triangle>,
-}
+    /// Derivatives of the surface point along u and v, zero at the poles
+    pub fn derivatives(&self, normal: Vec3f) -> (Vec3f, Vec3f) {
+        let rho = f64::hypot(normal.x, normal.z);
 
-impl Mesh {
-    pub fn new() -> Self {
-        Self { triangles: vec![] }
-    }
-
-    fn parse_indices(tokens: &[&str]) -> (usize, usize, usize) {
-        assert!(tokens.len() == 3);
-        let mut tmp: Vec<usize> = Vec::new();
-        for token in tokens {
-            let indices: Vec<&str> = token.split('/').collect();
-            assert!(indices.len() == 3);
-
-            let vertex_index = indices[0].parse::<usize>().unwrap();
-            tmp.push(vertex_index - 1);
+        if rho < 1e-9 {
+            return (Vec3f::from(0.0), Vec3f::from(0.0));
         }
 
-        (tmp[0], tmp[1], tmp[2])
+        let r = self.radius;
+        let dpdu = Vec3f::new(-normal.z, 0.0, normal.x) * (2.0 * PI * r);
+        let dpdv =
+            Vec3f::new(normal.y * normal.x / rho, -rho, normal.y * normal.z / rho) * (PI * r);
+        (dpdu, dpdv)
     }
 
-    fn parse_vertex(tokens: &[&str]) -> Vec3f {
-        assert!(tokens.len() == 3);
-        let x = tokens[0].parse::<f64>().unwrap();
-        let y = tokens[1].parse::<f64>().unwrap();
-        let z = tokens[2].parse::<f64>().unwrap();
-        Vec3::new(x, y, z)
+    pub fn surface_area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
     }
+}
 
-    pub fn from_obj(path: &str) -> io::Result<Mesh> {
-        let mut mesh = Mesh::new();
-
-        let mut vertices: Vec<Vec3f> = Vec::new();
-
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let line = line?;
-
-            if line.starts_with('#') || line.is_empty() {
-                continue;
-            }
+/// Shared mesh placed in the scene with its own transform
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "InstanceDesc")]
+pub struct Instance {
+    pub mesh: Arc<Mesh>,
+    pub transform: Transform,
+}
 
-            let tokens: Vec<&str> = line.split_whitespace().collect();
+/// Instance as written in the scene file
+#[derive(Deserialize)]
+struct InstanceDesc {
+    mesh: String,
+    group: Option<String>,
+    #[serde(default)]
+    transform: Transform,
+}
 
-            match tokens[0] {
-                "v" => {
-                    let vertex_tokens = &tokens[1..];
-                    vertices.push(Self::parse_vertex(vertex_tokens));
-                }
-                "f" => {
-                    let index_tokens = &tokens[1..];
-                    let (v0, v1, v2) = Self::parse_indices(index_tokens);
-                    mesh.triangles
-                        .push(Triangle(vertices[v0], vertices[v1], vertices[v2]));
-                }
-                _ => (),
-            }
-        }
+impl TryFrom<InstanceDesc> for Instance {
+    type Error = std::io::Error;
 
-        Ok(mesh)
+    fn try_from(desc: InstanceDesc) -> Result<Self, Self::Error> {
+        let mesh = Mesh::load_group(&desc.mesh, desc.group.as_deref())?;
+        Ok(Instance::new(mesh, desc.transform))
     }
 }
 
-fn load_from_obj<'de, D>(deserializer: D) -> Result<Mesh, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let obj_path: &str = Deserialize::deserialize(deserializer)?;
-    Mesh::from_obj(obj_path).map_err(de::Error::custom)
+impl Instance {
+    pub fn new(mesh: Arc<Mesh>, transform: Transform) -> Self {
+        Self { mesh, transform }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum Geometry {
-    #[serde(deserialize_with = "load_from_obj")]
-    MESH(Mesh),
+    MESH(Arc<Mesh>),
+    CSG(Csg),
+    CYLINDER(Cylinder),
     SPHERE(Sphere),
+    CUBOID(Cuboid),
+    INSTANCE(Instance),
+}
+
+/// Picks the variant by its keys, so errors loading it are reported instead of a mismatch
+/// A mesh is given by its path, an instance by 'mesh', a CSG solid by 'operation',
+/// a cylinder by 'height', a sphere by 'radius' and a cuboid by 'min'
+impl<'de> Deserialize<'de> for Geometry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde_json::Value;
+
+        let value = Value::deserialize(deserializer)?;
+        let has = |key: &str| value.get(key).is_some();
+
+        let geometry = if let Value::String(path) = &value {
+            return Mesh::load(path)
+                .map(Geometry::MESH)
+                .map_err(de::Error::custom);
+        } else if has("mesh") {
+            serde_json::from_value(value).map(Geometry::INSTANCE)
+        } else if has("operation") {
+            serde_json::from_value(value).map(Geometry::CSG)
+        } else if has("height") {
+            serde_json::from_value(value).map(Geometry::CYLINDER)
+        } else if has("radius") {
+            serde_json::from_value(value).map(Geometry::SPHERE)
+        } else if has("min") {
+            serde_json::from_value(value).map(Geometry::CUBOID)
+        } else {
+            return Err(de::Error::custom(format!("unknown geometry {}", value)));
+        };
+
+        geometry.map_err(de::Error::custom)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -177,6 +251,12 @@ pub struct Scene {
     pub background: Vec3f,
     pub lights: Vec<Light>,
     pub objects: Vec<Object>,
+    /// Trace a single wavelength per path, so dispersion splits light into colors
+    #[serde(default)]
+    pub spectral: bool,
+    /// Medium filling the space outside all objects, like fog
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medium: Option<Medium>,
 }
 
 impl Scene {
@@ -185,12 +265,34 @@ impl Scene {
             background,
             objects: Vec::new(),
             lights: Vec::new(),
+            spectral: false,
+            medium: None,
         }
     }
 
     pub fn add(&mut self, object: Object) {
         self.objects.push(object);
     }
+
+    /// Returns material at the hit, mesh materials take precedence over the object material
+    pub fn material(&self, hit: &Hit) -> &Material {
+        let object = &self.objects[hit.idx];
+
+        hit.material
+            .and_then(|index| object.geometry.materials().get(index))
+            .unwrap_or(&object.material)
+    }
+
+    /// Problems noted while loading the meshes, each listed once
+    pub fn warnings(&self) -> Vec<&String> {
+        let mut warnings: Vec<&String> = Vec::new();
+        for warning in self.objects.iter().flat_map(|o| o.geometry.warnings()) {
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+        warnings
+    }
 }
 
 pub trait Hittable {
@@ -221,71 +323,46 @@ impl Hittable for Sphere {
 
         if min_t < t && t < max_t {
             let point = ray.point_at(t);
-            Some(Hit::new(t, (point - self.center) / self.radius, point, 0))
+            let mut hit = Hit::new(t, (point - self.center) / self.radius, point, 0);
+            hit.uv = self.uv(hit.normal);
+            (hit.tangent, hit.bitangent) = self.derivatives(hit.normal);
+            Some(hit)
         } else {
             None
         }
     }
 }
 
-impl Hittable for Triangle {
+impl Hittable for Instance {
     fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
-        let v0v1 = self.1 - self.0;
-        let v0v2 = self.2 - self.0;
-        let v1v2 = self.2 - self.1;
-        let v2v0 = self.0 - self.2;
-
-        let normal = Vec3f::normalize(Vec3f::cross(v0v1, v0v2));
-        let ndot = Vec3f::dot(normal, ray.direction);
-
-        if f64::abs(ndot) < f64::EPSILON {
-            return None;
-        }
-
-        let d = -Vec3f::dot(normal, self.0);
-
-        let t = -(Vec3f::dot(normal, ray.origin) + d) / ndot;
-        if t < min_t && t > max_t {
-            return None;
-        }
-
-        let point = ray.point_at(t);
-
-        let mut c: Vec3f;
-
-        let vp0 = point - self.0;
-        c = Vec3f::cross(v0v1, vp0);
-        if Vec3f::dot(normal, c) < 0.0 {
-            return None;
-        }
-
-        let vp1 = point - self.1;
-        c = Vec3f::cross(v1v2, vp1);
-        if Vec3f::dot(normal, c) < 0.0 {
-            return None;
-        }
+        let local = self.transform.inverse_ray(ray);
+        let mut hit = self.mesh.hit(&local, min_t, max_t)?;
+        hit.point = ray.point_at(hit.t);
+        hit.normal = self.transform.normal(hit.normal);
+        hit.geometric_normal = self.transform.normal(hit.geometric_normal);
+        hit.tangent = self.transform.vector(hit.tangent);
+        hit.bitangent = self.transform.vector(hit.bitangent);
+        Some(hit)
+    }
+}
 
-        let vp2 = point - self.2;
-        c = Vec3f::cross(v2v0, vp2);
-        if Vec3f::dot(normal, c) < 0.0 {
-            return None;
+impl Geometry {
+    /// Materials loaded along with the mesh, empty for analytic shapes
+    pub fn materials(&self) -> &[Material] {
+        match self {
+            Geometry::MESH(g) => g.materials(),
+            Geometry::INSTANCE(g) => g.mesh.materials(),
+            _ => &[],
         }
-
-        Some(Hit::new(t, normal, point, 0))
     }
-}
 
-impl Hittable for Mesh {
-    fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
-        let mut closest = max_t;
-        let mut hit: Option<Hit> = None;
-        for triangle in &self.triangles {
-            if let Some(tmp) = triangle.hit(ray, min_t, closest) {
-                closest = tmp.t;
-                hit = Some(tmp);
-            }
+    /// Problems noted while loading the mesh, empty for analytic shapes
+    pub fn warnings(&self) -> &[String] {
+        match self {
+            Geometry::MESH(g) => g.warnings(),
+            Geometry::INSTANCE(g) => g.mesh.warnings(),
+            _ => &[],
         }
-        hit
     }
 }
 
@@ -293,15 +370,21 @@ impl Hittable for Geometry {
     fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
         match self {
             Geometry::MESH(g) => g.hit(ray, min_t, max_t),
+            Geometry::CSG(g) => g.hit(ray, min_t, max_t),
+            Geometry::CYLINDER(g) => g.hit(ray, min_t, max_t),
             Geometry::SPHERE(g) => g.hit(ray, min_t, max_t),
+            Geometry::CUBOID(g) => g.hit(ray, min_t, max_t),
+            Geometry::INSTANCE(g) => g.hit(ray, min_t, max_t),
         }
     }
 }
 
 impl Hittable for Scene {
     fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
-        let mut closest = Hit::default();
-        closest.t = max_t;
+        let mut closest = Hit {
+            t: max_t,
+            ..Default::default()
+        };
 
         for (i, object) in self.objects.iter().enumerate() {
             if let Some(hit) = object.geometry.hit(ray, min_t, closest.t) {
@@ -323,6 +406,7 @@ mod test {
     use crate::common::*;
     use crate::geometry::*;
     use crate::ray::*;
+    use crate::transform::*;
 
     #[test]
     fn test_sphere_hit() {
@@ -333,10 +417,45 @@ mod test {
             assert_eq!(hit.point, Vec3f::new(0.0, 0.0, 4.0));
             assert_eq!(hit.normal, Vec3f::new(0.0, 0.0, -1.0));
         } else {
-            assert!(false);
+            panic!("expected hit");
         }
     }
 
+    #[test]
+    fn test_sphere_uv() {
+        let sphere = Sphere::new(Vec3f::from(0.0), 2.0);
+
+        // top of the sphere is at -y
+        let ray = Ray::new(Vec3f::new(0.0, -5.0, 0.0), Vec3f::new(0.0, 1.0, 0.0));
+        let hit = sphere.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.uv.y - 1.0).abs() < 1e-9);
+
+        let ray = Ray::new(Vec3f::new(5.0, 0.0, 0.0), Vec3f::new(-1.0, 0.0, 0.0));
+        let hit = sphere.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.uv.x - 0.5).abs() < 1e-9 && (hit.uv.y - 0.5).abs() < 1e-9);
+
+        let uv = sphere.uv(Vec3f::new(0.0, 0.0, 1.0));
+        assert!((uv.x - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_sphere_derivatives() {
+        let sphere = Sphere::new(Vec3f::from(0.0), 2.0);
+        let normal = Vec3f::new(0.3, -0.5, 0.6).normalize();
+        let (dpdu, dpdv) = sphere.derivatives(normal);
+
+        // stepping along the derivatives moves uv by the same amount
+        let uv = sphere.uv(normal);
+        let eps = 1e-6;
+        let du = sphere.uv((normal * 2.0 + dpdu * eps).normalize()) - uv;
+        let dv = sphere.uv((normal * 2.0 + dpdv * eps).normalize()) - uv;
+        assert!((du.x - eps).abs() < 1e-9 && du.y.abs() < 1e-9, "{:?}", du);
+        assert!(dv.x.abs() < 1e-9 && (dv.y - eps).abs() < 1e-9, "{:?}", dv);
+
+        // frame faces outward
+        assert!(Vec3f::dot(Vec3f::cross(dpdu, dpdv), normal) > 0.0);
+    }
+
     #[test]
     fn test_sphere_hit_inside() {
         let sphere = Sphere::new(Vec3f::from(0.0), 3.0);
@@ -347,52 +466,10 @@ mod test {
             // what should the normal be in this case?
             assert_eq!(hit.normal, Vec3f::new(0.0, 0.0, 1.0));
         } else {
-            assert!(false);
+            panic!("expected hit");
         }
     }
 
-    #[test]
-    fn test_triangle_hit() {
-        let triangle = Triangle(
-            Vec3f::new(-0.5, 0.0, 5.0),
-            Vec3f::new(0.0, 1.0, 5.0),
-            Vec3f::new(0.5, 0.0, 5.0),
-        );
-        let ray = Ray::new(Vec3f::new(0.0, 0.5, 0.0), Vec3f::new(0.0, 0.0, 1.0));
-        let hit = triangle.hit(&ray, 0.0, f64::INFINITY).unwrap();
-        assert_eq!(hit.t, 5.0);
-        assert_eq!(hit.point, Vec3f::new(0.0, 0.5, 5.0));
-        assert_eq!(hit.normal, Vec3f::new(0.0, 0.0, -1.0));
-    }
-
-    #[test]
-    fn test_mesh_hit() {
-        let s = 0.5;
-        let quad = Mesh {
-            triangles: vec![
-                Triangle(
-                    Vec3f::new(-s, -s, -s), // bottom left
-                    Vec3f::new(-s, s, -s),  // top left
-                    Vec3f::new(s, -s, -s),  // bottom right
-                ),
-                Triangle(
-                    Vec3f::new(-s, s, -s), // top left
-                    Vec3f::new(s, s, -s),  // top right
-                    Vec3f::new(s, -s, -s), // bottom right
-                ),
-            ],
-        };
-
-        let ray = Ray::new(Vec3f::new(0.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
-
-        let possible_hit = quad.hit(&ray, 0.0, f64::INFINITY);
-        assert!(possible_hit.is_some());
-
-        let hit = possible_hit.unwrap();
-        assert_eq!(hit.point, Vec3f::new(0.0, 0.0, -s));
-        assert_eq!(hit.normal, Vec3f::new(0.0, 0.0, -1.0));
-    }
-
     #[test]
     fn test_misc() {
         let sphere = Sphere::new(Vec3f::new(0.0, 0.0, 5.0), 1.0);
@@ -512,21 +589,65 @@ mod test {
     }
 
     #[test]
-    fn test_load_obj() {
-        if let Ok(mesh) = Mesh::from_obj("scenes/cube.obj") {
-            println!("{:?}", mesh);
-            assert_eq!(mesh.triangles.len(), 6 * 2);
-        }
+    fn test_geometry_errors() {
+        // the error of the variant given by the keys reaches the user
+        let error = |json: &str| {
+            serde_json::from_str::<Geometry>(json)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert!(error(r#""scenes/missing.obj""#).contains("No such file"));
+        let json = r#"{ "mesh": "scenes/cube.obj", "transform": { "scale": 0 } }"#;
+        assert!(error(json).contains("not invertible"));
+        assert!(error(r#"{ "center": [0, 0, 0], "radius": "one" }"#).contains("invalid type"));
+        assert!(error(r#"{ "size": 1 }"#).contains("unknown geometry"));
     }
 
     #[test]
-    fn test_hit_cube() {
-        let mesh = Mesh::from_obj("scenes/cube.obj").unwrap();
+    fn test_instance_hit() {
+        let mesh = Mesh::load("scenes/cube.obj").unwrap();
+        let transform = Transform::from_trs(
+            Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(0.0, 45.0, 0.0),
+            Vec3::from(2.0),
+        );
+        let instance = Instance::new(mesh, transform);
 
-        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = instance.hit(&ray, 0.001, f64::INFINITY).unwrap();
 
-        if let Some(hit) = mesh.hit(&ray, 0.001, f64::INFINITY) {
-            println!("{:?}", hit);
-        }
+        // rotated cube presents its edge, which lies at 2 * sqrt(2) from the center
+        let expected = 10.0 - 2.0 * f64::sqrt(2.0);
+        assert!((hit.t - expected).abs() < 1e-9);
+        assert!((hit.point - Vec3::new(0.0, 0.0, expected)).length() < 1e-9);
+        assert!((hit.normal.length() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_instance_shared() {
+        let json = r#"[
+                { "mesh": "scenes/cube.obj", "transform": { "translation": [-2, 0, 5] } },
+                { "mesh": "scenes/cube.obj", "transform": { "translation": [2, 0, 5], "scale": 0.5 } },
+                "scenes/cube.obj"
+            ]"#;
+        let geometry: Vec<Geometry> = Mesh::with_cache(|| serde_json::from_str(json)).unwrap();
+
+        let meshes: Vec<&Arc<Mesh>> = geometry
+            .iter()
+            .map(|g| match g {
+                Geometry::INSTANCE(instance) => &instance.mesh,
+                Geometry::MESH(mesh) => mesh,
+                _ => panic!("expected mesh"),
+            })
+            .collect();
+
+        assert!(Arc::ptr_eq(meshes[0], meshes[1]));
+        assert!(Arc::ptr_eq(meshes[0], meshes[2]));
+
+        let ray = Ray::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = geometry[1].hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit.point, Vec3::new(2.0, 0.0, 4.5));
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
     }
 }
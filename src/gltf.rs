@@ -0,0 +1,1163 @@
+use crate::camera::*;
+use crate::common::*;
+use crate::geometry::*;
+use crate::material::*;
+use crate::mesh::*;
+use crate::spectrum::Dispersion;
+use crate::transform::*;
+use crate::vector::*;
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("glTF: {}", message))
+}
+
+/// Radius of the spheres standing in for point and spot lights
+const POINT_LIGHT_RADIUS: f64 = 0.05;
+
+/// Directional lights become distant spheres, as seen from the scene origin
+const DIRECTIONAL_LIGHT_DISTANCE: f64 = 1.0e4;
+
+/// Angular radius of directional lights in radians, roughly that of the sun
+const DIRECTIONAL_LIGHT_ANGLE: f64 = 0.005;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_JSON: u32 = 0x4E4F_534A;
+const GLB_BIN: u32 = 0x004E_4942;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Document {
+    scene: Option<usize>,
+    scenes: Vec<SceneDesc>,
+    nodes: Vec<NodeDesc>,
+    meshes: Vec<MeshDesc>,
+    materials: Vec<MaterialDesc>,
+    cameras: Vec<CameraDesc>,
+    accessors: Vec<Accessor>,
+    buffer_views: Vec<BufferView>,
+    buffers: Vec<Buffer>,
+    extensions: DocumentExtensions,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SceneDesc {
+    nodes: Vec<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct NodeDesc {
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    camera: Option<usize>,
+    /// Column-major 4x4 matrix
+    matrix: Option<[f64; 16]>,
+    translation: Option<[f64; 3]>,
+    /// Unit quaternion (x, y, z, w)
+    rotation: Option<[f64; 4]>,
+    scale: Option<[f64; 3]>,
+    extensions: NodeExtensions,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct NodeExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    light: Option<NodeLight>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeLight {
+    light: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DocumentExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    lights: Option<Lights>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Lights {
+    lights: Vec<LightDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LightDesc {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default = "white")]
+    color: [f64; 3],
+    #[serde(default = "one")]
+    intensity: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct MeshDesc {
+    primitives: Vec<Primitive>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Primitive {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    #[serde(default = "triangles")]
+    mode: u32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct MaterialDesc {
+    name: Option<String>,
+    pbr_metallic_roughness: PbrDesc,
+    emissive_factor: [f64; 3],
+    extensions: MaterialExtensions,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct PbrDesc {
+    base_color_factor: [f64; 4],
+    metallic_factor: f64,
+    roughness_factor: f64,
+}
+
+impl Default for PbrDesc {
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0; 4],
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_ior")]
+    ior: Option<IorDesc>,
+    #[serde(rename = "KHR_materials_transmission")]
+    transmission: Option<TransmissionDesc>,
+    #[serde(rename = "KHR_materials_emissive_strength")]
+    emissive_strength: Option<EmissiveStrengthDesc>,
+    #[serde(rename = "KHR_materials_volume")]
+    volume: Option<VolumeDesc>,
+    #[serde(rename = "KHR_materials_dispersion")]
+    dispersion: Option<DispersionDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IorDesc {
+    #[serde(default = "default_ior")]
+    ior: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransmissionDesc {
+    #[serde(default)]
+    transmission_factor: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmissiveStrengthDesc {
+    #[serde(default = "one")]
+    emissive_strength: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VolumeDesc {
+    #[serde(default = "infinity")]
+    attenuation_distance: f64,
+    #[serde(default = "white")]
+    attenuation_color: [f64; 3],
+}
+
+#[derive(Debug, Deserialize)]
+struct DispersionDesc {
+    /// 20 over the Abbe number
+    #[serde(default)]
+    dispersion: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CameraDesc {
+    perspective: Option<Perspective>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Perspective {
+    yfov: f64,
+    aspect_ratio: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: Option<usize>,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    #[serde(default)]
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    kind: String,
+    sparse: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Buffer {
+    uri: Option<String>,
+    byte_length: usize,
+}
+
+fn white() -> [f64; 3] {
+    [1.0; 3]
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn default_ior() -> f64 {
+    1.5
+}
+
+fn infinity() -> f64 {
+    f64::INFINITY
+}
+
+fn triangles() -> u32 {
+    4
+}
+
+/// Decodes standard base64 as used in data URIs
+fn decode_base64(data: &str) -> Result<Vec<u8>, String> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut count = 0;
+
+    for c in data
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+    {
+        let v = value(c).ok_or_else(|| format!("invalid base64 character {:?}", c as char))?;
+        bits = (bits << 6) | v as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Resolves data URIs and paths relative to the glTF file
+fn load_uri(uri: &str, directory: &Path) -> io::Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, payload) = data
+            .split_once(";base64,")
+            .ok_or_else(|| invalid_data("only base64 data URIs are supported".to_string()))?;
+        decode_base64(payload).map_err(invalid_data)
+    } else {
+        fs::read(directory.join(uri.replace("%20", " ")))
+    }
+}
+
+/// Number of components for an accessor type
+fn components(kind: &str) -> Result<usize, String> {
+    match kind {
+        "SCALAR" => Ok(1),
+        "VEC2" => Ok(2),
+        "VEC3" => Ok(3),
+        "VEC4" | "MAT2" => Ok(4),
+        "MAT3" => Ok(9),
+        "MAT4" => Ok(16),
+        _ => Err(format!("unknown accessor type {:?}", kind)),
+    }
+}
+
+/// Converts a node's matrix or TRS properties
+fn node_transform(node: &NodeDesc) -> Option<Transform> {
+    let (matrix, translation) = match node.matrix {
+        Some(m) => {
+            let matrix = Mat3f::from([m[0], m[4], m[8], m[1], m[5], m[9], m[2], m[6], m[10]]);
+            (matrix, Vec3f::new(m[12], m[13], m[14]))
+        }
+        None => {
+            let [x, y, z, w] = node.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+            let rotation = Mat3f::from([
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+            ]);
+            let [sx, sy, sz] = node.scale.unwrap_or([1.0; 3]);
+            let scale = Mat3f::from([sx, 0.0, 0.0, 0.0, sy, 0.0, 0.0, 0.0, sz]);
+            let translation = Vec3f::from(node.translation.unwrap_or([0.0; 3]));
+            (rotation * scale, translation)
+        }
+    };
+
+    // nodes scaled to zero are hidden
+    Transform::try_new(matrix, translation)
+}
+
+/// Material used by primitives without one, as defined by the specification
+fn default_material() -> Material {
+    MaterialDesc::default().to_material()
+}
+
+impl MaterialDesc {
+    fn to_material(&self) -> Material {
+        let pbr = &self.pbr_metallic_roughness;
+        let [r, g, b, _] = pbr.base_color_factor;
+
+        let strength = self
+            .extensions
+            .emissive_strength
+            .as_ref()
+            .map_or(1.0, |e| e.emissive_strength);
+        let emission = Vec3f::from(self.emissive_factor) * strength;
+
+        let transmission = self
+            .extensions
+            .transmission
+            .as_ref()
+            .map_or(0.0, |t| t.transmission_factor);
+
+        let absorption = self
+            .extensions
+            .volume
+            .as_ref()
+            .map_or(Vec3f::from(0.0), |v| {
+                absorption_coefficient(Vec3f::from(v.attenuation_color), v.attenuation_distance)
+            });
+
+        let ior = self.extensions.ior.as_ref().map_or(1.5, |i| i.ior);
+        let dispersion = self
+            .extensions
+            .dispersion
+            .as_ref()
+            .filter(|d| d.dispersion > 0.0)
+            .map(|d| Dispersion::from_abbe(ior, 20.0 / d.dispersion));
+
+        Material {
+            albedo: Vec3f::new(r, g, b),
+            roughness: pbr.roughness_factor,
+            ior,
+            metallic: pbr.metallic_factor,
+            material: if transmission > 0.0 {
+                MaterialType::Transparent
+            } else {
+                MaterialType::Physical
+            },
+            absorption,
+            dispersion,
+            ..Default::default()
+        }
+        .with_emission(emission)
+    }
+}
+
+/// Objects, lights and cameras of a glTF file
+#[derive(Debug)]
+pub struct Gltf {
+    pub scene: Scene,
+    pub cameras: Vec<Camera>,
+    /// Unsupported items that were skipped, like orthographic cameras
+    pub warnings: Vec<String>,
+}
+
+/// Parsed document together with its binary buffers
+struct Importer {
+    document: Document,
+    buffers: Vec<Vec<u8>>,
+    materials: Vec<Material>,
+    material_names: Vec<String>,
+    meshes: Vec<Option<Arc<Mesh>>>,
+    warnings: Vec<String>,
+}
+
+impl Importer {
+    fn new(json: &[u8], bin: Option<Vec<u8>>, directory: &Path) -> io::Result<Self> {
+        let document: Document =
+            serde_json::from_slice(json).map_err(|e| invalid_data(e.to_string()))?;
+
+        let mut bin = bin;
+        let mut buffers = Vec::new();
+        for buffer in &document.buffers {
+            let data = match &buffer.uri {
+                Some(uri) => load_uri(uri, directory)?,
+                None => bin
+                    .take()
+                    .ok_or_else(|| invalid_data("buffer without uri or GLB chunk".to_string()))?,
+            };
+            if data.len() < buffer.byte_length {
+                return Err(invalid_data(
+                    "buffer is shorter than its byteLength".to_string(),
+                ));
+            }
+            buffers.push(data);
+        }
+
+        let materials: Vec<Material> = document.materials.iter().map(|m| m.to_material()).collect();
+        let material_names = document
+            .materials
+            .iter()
+            .enumerate()
+            .map(|(i, m)| m.name.clone().unwrap_or_else(|| format!("material{}", i)))
+            .collect();
+
+        Ok(Self {
+            meshes: vec![None; document.meshes.len()],
+            document,
+            buffers,
+            materials,
+            material_names,
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Reads accessor as 'count' elements of 'n' components each
+    fn read(&self, index: usize) -> io::Result<(Vec<f64>, usize)> {
+        let accessor = self
+            .document
+            .accessors
+            .get(index)
+            .ok_or_else(|| invalid_data(format!("accessor {} out of range", index)))?;
+
+        if accessor.sparse.is_some() {
+            return Err(invalid_data(
+                "sparse accessors are not supported".to_string(),
+            ));
+        }
+
+        let n = components(&accessor.kind).map_err(invalid_data)?;
+        let exceeds = || invalid_data(format!("accessor {} exceeds its buffer", index));
+
+        let (size, max) = match accessor.component_type {
+            5120 => (1, i8::MAX as f64),
+            5121 => (1, u8::MAX as f64),
+            5122 => (2, i16::MAX as f64),
+            5123 => (2, u16::MAX as f64),
+            5125 => (4, u32::MAX as f64),
+            5126 => (4, 1.0),
+            other => return Err(invalid_data(format!("unknown component type {}", other))),
+        };
+
+        let view = match accessor.buffer_view {
+            Some(view) => self
+                .document
+                .buffer_views
+                .get(view)
+                .ok_or_else(|| invalid_data(format!("buffer view {} out of range", view)))?,
+            // accessors without buffer view are all zeros
+            None => {
+                let length = accessor.count.checked_mul(n).ok_or_else(exceeds)?;
+                return Ok((vec![0.0; length], n));
+            }
+        };
+
+        let buffer = self
+            .buffers
+            .get(view.buffer)
+            .ok_or_else(|| invalid_data(format!("buffer {} out of range", view.buffer)))?;
+
+        // offsets come from the file, so they may overflow
+        let stride = view.byte_stride.unwrap_or(size * n);
+        if stride < size * n {
+            return Err(invalid_data(format!("accessor {} overlaps itself", index)));
+        }
+        let start = view.byte_offset.checked_add(accessor.byte_offset);
+        let end = view.byte_offset.checked_add(view.byte_length);
+        let last = match accessor.count {
+            0 => start,
+            count => stride
+                .checked_mul(count - 1)
+                .and_then(|offset| offset.checked_add(start?))
+                .and_then(|offset| offset.checked_add(size * n)),
+        };
+
+        let (start, end, last) = match (start, end, last) {
+            (Some(start), Some(end), Some(last)) => (start, end, last),
+            _ => return Err(exceeds()),
+        };
+        if last > end.min(buffer.len()) {
+            return Err(exceeds());
+        }
+
+        let mut values = Vec::with_capacity(accessor.count * n);
+        for i in 0..accessor.count {
+            for k in 0..n {
+                let offset = start + i * stride + k * size;
+                let bytes = &buffer[offset..offset + size];
+                let value = match accessor.component_type {
+                    5120 => bytes[0] as i8 as f64,
+                    5121 => bytes[0] as f64,
+                    5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5125 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                };
+
+                let value = if accessor.normalized {
+                    f64::max(value / max, -1.0)
+                } else {
+                    value
+                };
+                values.push(value);
+            }
+        }
+
+        Ok((values, n))
+    }
+
+    /// Reads vertex attribute with at least 'min' components per element
+    fn attribute(
+        &self,
+        primitive: &Primitive,
+        name: &str,
+        min: usize,
+    ) -> io::Result<Option<Vec<f64>>> {
+        let accessor = match primitive.attributes.get(name) {
+            Some(&accessor) => accessor,
+            None => return Ok(None),
+        };
+
+        let (values, n) = self.read(accessor)?;
+        if n < min {
+            return Err(invalid_data(format!("{} needs {} components", name, min)));
+        }
+
+        Ok(Some(
+            values
+                .chunks_exact(n)
+                .flat_map(|v| v[..min].to_vec())
+                .collect(),
+        ))
+    }
+
+    /// Combines all primitives of a mesh into one 'Mesh'
+    fn mesh(&mut self, index: usize) -> io::Result<Option<Arc<Mesh>>> {
+        if let Some(mesh) = &self.meshes[index] {
+            return Ok(Some(Arc::clone(mesh)));
+        }
+
+        let mut mesh = Mesh::new();
+        mesh.groups.push("default".to_string());
+        mesh.materials = self.materials.clone();
+        mesh.material_names = self.material_names.clone();
+
+        for primitive in &self.document.meshes[index].primitives {
+            let positions = match self.attribute(primitive, "POSITION", 3)? {
+                Some(positions) => positions,
+                None => continue,
+            };
+            let normals = self.attribute(primitive, "NORMAL", 3)?;
+            let uvs = self.attribute(primitive, "TEXCOORD_0", 2)?;
+            let colors = self.attribute(primitive, "COLOR_0", 3)?;
+
+            let count = positions.len() / 3;
+            let vertex_offset = mesh.vertices.len();
+            let normal_offset = mesh.normals.len();
+            let uv_offset = mesh.uvs.len();
+
+            for p in positions.chunks_exact(3) {
+                mesh.vertices.push(Vec3f::new(p[0], p[1], p[2]));
+            }
+            for n in normals.iter().flat_map(|n| n.chunks_exact(3)) {
+                mesh.normals.push(Vec3f::new(n[0], n[1], n[2]));
+            }
+            for uv in uvs.iter().flat_map(|uv| uv.chunks_exact(2)) {
+                mesh.uvs.push(Vec2f::new(uv[0], uv[1]));
+            }
+
+            // colors are per vertex, so they are either given for all primitives or none
+            if colors.is_some() || !mesh.colors.is_empty() {
+                mesh.colors.resize(vertex_offset, Vec3f::from(1.0));
+                match &colors {
+                    Some(colors) => {
+                        for c in colors.chunks_exact(3) {
+                            mesh.colors.push(Vec3f::new(c[0], c[1], c[2]));
+                        }
+                    }
+                    None => mesh.colors.resize(vertex_offset + count, Vec3f::from(1.0)),
+                }
+            }
+
+            let indices: Vec<usize> = match primitive.indices {
+                Some(accessor) => self.read(accessor)?.0.iter().map(|&i| i as usize).collect(),
+                None => (0..count).collect(),
+            };
+
+            if let Some(&i) = indices.iter().find(|&&i| i >= count) {
+                return Err(invalid_data(format!("index {} out of range", i)));
+            }
+
+            let triangles: Vec<[usize; 3]> = match primitive.mode {
+                4 => indices
+                    .chunks_exact(3)
+                    .map(|t| [t[0], t[1], t[2]])
+                    .collect(),
+                5 => (2..indices.len())
+                    .map(|i| {
+                        if i % 2 == 0 {
+                            [indices[i - 2], indices[i - 1], indices[i]]
+                        } else {
+                            [indices[i - 1], indices[i - 2], indices[i]]
+                        }
+                    })
+                    .collect(),
+                6 => (2..indices.len())
+                    .map(|i| [indices[0], indices[i - 1], indices[i]])
+                    .collect(),
+                mode => {
+                    let warning = format!("skipped primitive with mode {} (points or lines)", mode);
+                    self.warnings.push(warning);
+                    continue;
+                }
+            };
+
+            let material = primitive
+                .material
+                .filter(|&material| material < self.materials.len());
+
+            for t in triangles {
+                mesh.faces.push(Face {
+                    vertices: t.map(|i| i + vertex_offset),
+                    uvs: uvs.as_ref().map(|_| t.map(|i| i + uv_offset)),
+                    normals: normals.as_ref().map(|_| t.map(|i| i + normal_offset)),
+                    group: 0,
+                    material,
+                });
+            }
+        }
+
+        if mesh.faces.is_empty() {
+            return Ok(None);
+        }
+
+        mesh.build();
+        let mesh = Arc::new(mesh);
+        self.meshes[index] = Some(Arc::clone(&mesh));
+        Ok(Some(mesh))
+    }
+
+    fn light(&mut self, index: usize, transform: &Transform) -> io::Result<Option<Light>> {
+        let light = self
+            .document
+            .extensions
+            .lights
+            .as_ref()
+            .and_then(|lights| lights.lights.get(index))
+            .ok_or_else(|| invalid_data(format!("light {} out of range", index)))?;
+
+        let intensity = Vec3f::from(light.color) * light.intensity;
+        let position = transform.point(Vec3f::from(0.0));
+        let direction = transform.vector(Vec3f::new(0.0, 0.0, -1.0)).normalize();
+
+        // emission is radiance, chosen so the sphere has the light's intensity
+        let light = match light.kind.as_str() {
+            // spot cones are not supported, they shine in all directions
+            "point" | "spot" => {
+                let radius = POINT_LIGHT_RADIUS;
+                Light {
+                    geometry: Sphere::new(position, radius),
+                    emission: intensity / (PI * radius * radius),
+                }
+            }
+            // intensity is illuminance in lux
+            "directional" => {
+                let sin = DIRECTIONAL_LIGHT_ANGLE.sin();
+                Light {
+                    geometry: Sphere::new(
+                        -direction * DIRECTIONAL_LIGHT_DISTANCE,
+                        DIRECTIONAL_LIGHT_DISTANCE * sin,
+                    ),
+                    emission: intensity / (PI * sin * sin),
+                }
+            }
+            kind => {
+                let warning = format!("skipped light of type {:?}", kind);
+                self.warnings.push(warning);
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(light))
+    }
+
+    fn camera(&mut self, index: usize, transform: &Transform) -> io::Result<Option<Camera>> {
+        let camera = self
+            .document
+            .cameras
+            .get(index)
+            .ok_or_else(|| invalid_data(format!("camera {} out of range", index)))?;
+
+        let perspective = match &camera.perspective {
+            Some(perspective) => perspective,
+            None => {
+                let warning = "skipped orthographic camera".to_string();
+                self.warnings.push(warning);
+                return Ok(None);
+            }
+        };
+
+        // glTF gives the vertical field of view, the camera uses the horizontal one
+        let aspect_ratio = perspective.aspect_ratio.unwrap_or(1.0);
+        let fov = 2.0 * f64::atan(f64::tan(perspective.yfov / 2.0) * aspect_ratio);
+
+        let position = transform.point(Vec3f::from(0.0));
+        let forward = transform.vector(Vec3f::new(0.0, 0.0, -1.0)).normalize();
+
+        Ok(Some(Camera::description(
+            position,
+            position + forward,
+            fov.to_degrees(),
+        )))
+    }
+
+    fn import(mut self) -> io::Result<Gltf> {
+        let mut gltf = Gltf {
+            scene: Scene::new(Vec3f::from(0.0)),
+            cameras: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        let nodes = &self.document.nodes;
+        let scene = self.document.scene.unwrap_or(0);
+        let roots: Vec<usize> = match self.document.scenes.get(scene) {
+            Some(scene) => scene.nodes.clone(),
+            // without scenes every node that is not a child is a root
+            None => (0..nodes.len())
+                .filter(|&i| !nodes.iter().any(|n| n.children.contains(&i)))
+                .collect(),
+        };
+
+        // glTF is y-up, while scene files point y down
+        let root = Transform::new(
+            Mat3f::from([1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 1.0]),
+            Vec3f::from(0.0),
+        );
+
+        let mut stack: Vec<(usize, Transform, usize)> = roots
+            .into_iter()
+            .rev()
+            .map(|node| (node, root, 0))
+            .collect();
+
+        while let Some((index, parent, depth)) = stack.pop() {
+            if depth > self.document.nodes.len() {
+                return Err(invalid_data("node hierarchy contains a cycle".to_string()));
+            }
+
+            let node = self
+                .document
+                .nodes
+                .get(index)
+                .ok_or_else(|| invalid_data(format!("node {} out of range", index)))?;
+
+            let transform = match node_transform(node) {
+                Some(local) => parent * local,
+                None => continue,
+            };
+
+            let (mesh, camera, light) = (
+                node.mesh,
+                node.camera,
+                node.extensions.light.as_ref().map(|l| l.light),
+            );
+            for &child in node.children.iter().rev() {
+                stack.push((child, transform, depth + 1));
+            }
+
+            if let Some(mesh) = mesh {
+                if mesh >= self.document.meshes.len() {
+                    return Err(invalid_data(format!("mesh {} out of range", mesh)));
+                }
+                if let Some(mesh) = self.mesh(mesh)? {
+                    gltf.scene.add(Object {
+                        geometry: Geometry::INSTANCE(Instance::new(mesh, transform)),
+                        material: default_material(),
+                    });
+                }
+            }
+
+            if let Some(camera) = camera {
+                gltf.cameras.extend(self.camera(camera, &transform)?);
+            }
+
+            if let Some(light) = light {
+                gltf.scene.lights.extend(self.light(light, &transform)?);
+            }
+        }
+
+        gltf.warnings = self.warnings;
+        Ok(gltf)
+    }
+}
+
+impl Gltf {
+    /// Loads '.gltf' or '.glb' file, external buffers are resolved relative to it
+    pub fn load(path: &str) -> io::Result<Gltf> {
+        let data = fs::read(path)?;
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        if data.starts_with(b"glTF") {
+            Self::read_glb(&data, directory)
+        } else {
+            Self::read_gltf(&data, directory)
+        }
+    }
+
+    /// Imports JSON glTF
+    pub fn read_gltf(json: &[u8], directory: &Path) -> io::Result<Gltf> {
+        Importer::new(json, None, directory)?.import()
+    }
+
+    /// Imports binary glTF, a container with a JSON chunk and an optional binary chunk
+    pub fn read_glb(data: &[u8], directory: &Path) -> io::Result<Gltf> {
+        let word = |offset: usize| -> io::Result<u32> {
+            data.get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or_else(|| invalid_data("truncated GLB".to_string()))
+        };
+
+        if word(0)? != GLB_MAGIC {
+            return Err(invalid_data("missing GLB magic number".to_string()));
+        }
+        if word(4)? != 2 {
+            return Err(invalid_data(format!(
+                "unsupported GLB version {}",
+                word(4)?
+            )));
+        }
+
+        let length = (word(8)? as usize).min(data.len());
+        let mut offset = 12;
+        let mut json = None;
+        let mut bin = None;
+
+        while offset + 8 <= length {
+            let chunk_length = word(offset)? as usize;
+            let chunk_type = word(offset + 4)?;
+            let chunk = data
+                .get(offset + 8..offset + 8 + chunk_length)
+                .ok_or_else(|| invalid_data("truncated GLB chunk".to_string()))?;
+
+            match chunk_type {
+                GLB_JSON => json = Some(chunk),
+                GLB_BIN => bin = Some(chunk.to_vec()),
+                _ => (),
+            }
+
+            offset += 8 + chunk_length;
+        }
+
+        let json = json.ok_or_else(|| invalid_data("missing GLB JSON chunk".to_string()))?;
+        Importer::new(json, bin, directory)?.import()
+    }
+
+    /// Scene file with the first camera, or one looking down -z from the origin
+    pub fn into_config(self) -> ConfigFile {
+        let camera = self.cameras.first().copied().unwrap_or_else(|| {
+            Camera::description(Vec3f::from(0.0), Vec3f::new(0.0, 0.0, -1.0), 60.0)
+        });
+
+        ConfigFile {
+            scene: self.scene,
+            camera,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::gltf::*;
+    use crate::ray::Ray;
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let b = [
+                chunk[0],
+                *chunk.get(1).unwrap_or(&0),
+                *chunk.get(2).unwrap_or(&0),
+            ];
+            let bits = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+            for k in 0..4 {
+                if k <= chunk.len() {
+                    text.push(TABLE[(bits >> (18 - 6 * k)) as usize & 63] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+        text
+    }
+
+    /// Triangle positions followed by u16 indices
+    fn buffer() -> Vec<u8> {
+        let mut data = Vec::new();
+        for x in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        for i in [0u16, 1, 2] {
+            data.extend_from_slice(&i.to_le_bytes());
+        }
+        data.extend_from_slice(&[0, 0]);
+        data
+    }
+
+    fn document(uri: Option<String>) -> String {
+        let uri = uri.map_or(String::new(), |uri| format!(r#""uri": "{}","#, uri));
+        format!(
+            r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0, 2, 3] }}],
+            "nodes": [
+                {{ "translation": [0, 0, -5], "children": [1] }},
+                {{ "mesh": 0, "scale": [2, 2, 2] }},
+                {{ "camera": 0, "rotation": [0, 1, 0, 0] }},
+                {{ "extensions": {{ "KHR_lights_punctual": {{ "light": 0 }} }}, "translation": [0, 3, 0] }}
+            ],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }}] }}],
+            "materials": [{{
+                "name": "gold",
+                "pbrMetallicRoughness": {{ "baseColorFactor": [1, 0.8, 0.3, 1], "metallicFactor": 1, "roughnessFactor": 0.25 }}
+            }}],
+            "cameras": [{{ "type": "perspective", "perspective": {{ "yfov": 0.8, "znear": 0.1 }} }}],
+            "extensions": {{ "KHR_lights_punctual": {{ "lights": [{{ "type": "point", "intensity": 10 }}] }} }},
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+            ],
+            "buffers": [{{ {} "byteLength": 44 }}]
+        }}"#,
+            uri
+        )
+    }
+
+    fn check(gltf: &Gltf) {
+        assert_eq!(gltf.scene.objects.len(), 1);
+        assert_eq!(gltf.scene.lights.len(), 1);
+        assert_eq!(gltf.cameras.len(), 1);
+        assert!(gltf.warnings.is_empty());
+
+        // the triangle is scaled by two, moved to z = -5 and mirrored in y
+        let ray = Ray::new(Vec3f::new(0.5, -0.5, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        let hit = gltf.scene.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-9);
+
+        let material = gltf.scene.material(&hit);
+        assert_eq!(material.material, MaterialType::Physical);
+        assert_eq!(material.albedo, Vec3f::new(1.0, 0.8, 0.3));
+        assert_eq!(material.roughness, 0.25);
+
+        let ray = Ray::new(Vec3f::new(1.5, -1.5, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(gltf.scene.hit(&ray, 0.001, f64::INFINITY).is_none());
+
+        // camera rotated by 180 degrees around y looks down +z
+        let camera = gltf.cameras[0];
+        assert!((camera.target - Vec3f::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!((camera.fov - 0.8f64.to_degrees()).abs() < 1e-9);
+
+        let light = gltf.scene.lights[0];
+        assert_eq!(light.geometry.center, Vec3f::new(0.0, -3.0, 0.0));
+    }
+
+    #[test]
+    fn test_gltf() {
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            encode_base64(&buffer())
+        );
+        let gltf = Gltf::read_gltf(document(Some(uri)).as_bytes(), Path::new("")).unwrap();
+        check(&gltf);
+    }
+
+    #[test]
+    fn test_glb() {
+        let mut json = document(None).into_bytes();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let bin = buffer();
+
+        let mut data = Vec::new();
+        let total = 12 + 8 + json.len() + 8 + bin.len();
+        for word in [GLB_MAGIC, 2, total as u32, json.len() as u32, GLB_JSON] {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data.extend_from_slice(&json);
+        for word in [bin.len() as u32, GLB_BIN] {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data.extend_from_slice(&bin);
+
+        let gltf = Gltf::read_glb(&data, Path::new("")).unwrap();
+        check(&gltf);
+    }
+
+    #[test]
+    fn test_volume() {
+        let material: MaterialDesc = serde_json::from_str(
+            r#"{
+                "extensions": {
+                    "KHR_materials_transmission": { "transmissionFactor": 1 },
+                    "KHR_materials_volume": { "attenuationDistance": 2, "attenuationColor": [1, 0.5, 0.5] }
+                }
+            }"#,
+        )
+        .unwrap();
+        let material = material.to_material();
+        assert_eq!(material.material, MaterialType::Transparent);
+        assert_eq!(material.absorption.x, 0.0);
+        assert!((f64::exp(-material.absorption.y * 2.0) - 0.5).abs() < 1e-12);
+
+        assert_eq!(default_material().absorption, Vec3f::from(0.0));
+        assert!(material.dispersion.is_none());
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let material: MaterialDesc = serde_json::from_str(
+            r#"{
+                "extensions": {
+                    "KHR_materials_ior": { "ior": 1.6 },
+                    "KHR_materials_dispersion": { "dispersion": 0.5 }
+                }
+            }"#,
+        )
+        .unwrap();
+        let dispersion = material.to_material().dispersion.unwrap();
+        assert!((dispersion.ior(587.56) - 1.6).abs() < 1e-9);
+        assert!(dispersion.ior(450.0) > dispersion.ior(650.0));
+    }
+
+    #[test]
+    fn test_emission() {
+        let material: MaterialDesc = serde_json::from_str(
+            r#"{
+                "emissiveFactor": [1, 0.5, 0.25],
+                "extensions": {
+                    "KHR_materials_emissive_strength": { "emissiveStrength": 4 }
+                }
+            }"#,
+        )
+        .unwrap();
+        let material = material.to_material();
+        assert_eq!(material.emittance, 4.0);
+        assert_eq!(
+            material.emission(&Hit::default()),
+            Vec3f::new(4.0, 2.0, 1.0)
+        );
+
+        assert_eq!(default_material().emittance, 0.0);
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(decode_base64("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(decode_base64(&encode_base64(&buffer())).unwrap(), buffer());
+        assert!(decode_base64("a*b").is_err());
+    }
+
+    #[test]
+    fn test_warnings() {
+        let json = r#"{
+            "nodes": [
+                { "camera": 0 },
+                { "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+            ],
+            "cameras": [{ "type": "orthographic" }],
+            "extensions": { "KHR_lights_punctual": { "lights": [{ "type": "area" }] } }
+        }"#;
+        let gltf = Gltf::read_gltf(json.as_bytes(), Path::new("")).unwrap();
+        assert!(gltf.cameras.is_empty() && gltf.scene.lights.is_empty());
+        assert_eq!(gltf.warnings.len(), 2);
+        assert!(gltf.warnings[1].contains("area"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Gltf::read_gltf(b"{ \"nodes\": [{ \"mesh\": 3 }] }", Path::new("")).is_err());
+        assert!(Gltf::read_gltf(b"not json", Path::new("")).is_err());
+        assert!(Gltf::read_glb(b"glTF", Path::new("")).is_err());
+
+        // offsets overflowing the address space are rejected, not wrapped around
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            encode_base64(&buffer())
+        );
+        for (from, to) in [
+            (
+                r#""byteOffset": 36,"#,
+                r#""byteOffset": 18446744073709551615,"#,
+            ),
+            (
+                r#""count": 3, "type": "VEC3""#,
+                r#""count": 18446744073709551615, "type": "VEC3""#,
+            ),
+            (
+                r#""byteLength": 36"#,
+                r#""byteLength": 36, "byteStride": 2"#,
+            ),
+        ] {
+            let json = document(Some(uri.clone()));
+            assert!(json.contains(from));
+            let json = json.replace(from, to);
+            let error = Gltf::read_gltf(json.as_bytes(), Path::new("")).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", to);
+        }
+
+        let cycle = r#"{ "nodes": [{ "children": [1] }, { "children": [0] }], "scenes": [{ "nodes": [0] }] }"#;
+        assert!(Gltf::read_gltf(cycle.as_bytes(), Path::new("")).is_err());
+    }
+}
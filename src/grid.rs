@@ -0,0 +1,375 @@
+use crate::geometry::Hit;
+use crate::procedural::Procedural;
+use crate::ray::Ray;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Grid: {}", message))
+}
+
+/// Little endian x, y and z resolution as u32 before the densities
+const HEADER_SIZE: usize = 12;
+
+/// Densities on a lattice of voxels spanning a box in world space, zero outside of it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "GridDesc", into = "GridDesc")]
+pub struct Grid {
+    resolution: [usize; 3],
+    /// Voxel values with x varying fastest, then y, then z
+    densities: Arc<Vec<f32>>,
+    min: Vec3f,
+    max: Vec3f,
+    /// Largest density, bounding the medium for delta and ratio tracking
+    max_density: f64,
+    /// File or pattern the densities came from
+    source: GridSource,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum GridSource {
+    Path {
+        path: String,
+    },
+    Procedural {
+        #[serde(flatten)]
+        procedural: Procedural,
+        resolution: [usize; 3],
+    },
+}
+
+/// Grid as written in the scene file, a raw file or a pattern baked at the given resolution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GridDesc {
+    #[serde(flatten)]
+    source: GridSource,
+    min: Vec3f,
+    max: Vec3f,
+}
+
+impl TryFrom<GridDesc> for Grid {
+    type Error = io::Error;
+
+    fn try_from(desc: GridDesc) -> io::Result<Self> {
+        if let GridSource::Procedural { resolution, .. } = &desc.source {
+            if resolution.contains(&0) {
+                let message = format!("resolution {:?} has no voxels", resolution);
+                return Err(invalid_data(message));
+            }
+
+            let count = resolution[0]
+                .checked_mul(resolution[1])
+                .and_then(|n| n.checked_mul(resolution[2]));
+            if count.is_none() {
+                let message = format!("resolution {:?} has too many voxels", resolution);
+                return Err(invalid_data(message));
+            }
+        }
+
+        let mut grid = match &desc.source {
+            GridSource::Path { path } => Grid::load(path, desc.min, desc.max)?,
+            GridSource::Procedural {
+                procedural,
+                resolution,
+            } => Grid::from_procedural(procedural, *resolution, desc.min, desc.max)?,
+        };
+        grid.source = desc.source;
+        Ok(grid)
+    }
+}
+
+impl From<Grid> for GridDesc {
+    fn from(grid: Grid) -> Self {
+        GridDesc {
+            source: grid.source,
+            min: grid.min,
+            max: grid.max,
+        }
+    }
+}
+
+impl Grid {
+    /// Panics if 'densities' does not match the resolution or the resolution has no voxels
+    pub fn new(resolution: [usize; 3], densities: Vec<f32>, min: Vec3f, max: Vec3f) -> Self {
+        assert!(!resolution.contains(&0), "Grid has no voxels");
+        assert_eq!(
+            densities.len(),
+            resolution[0] * resolution[1] * resolution[2],
+            "Grid size does not match"
+        );
+        let max_density = densities.iter().fold(0.0, |a: f64, &d| a.max(d as f64));
+        Self {
+            resolution,
+            densities: Arc::new(densities),
+            min,
+            max,
+            max_density,
+            source: GridSource::Path {
+                path: String::new(),
+            },
+        }
+    }
+
+    /// Reads a raw grid, the resolution as three little endian u32 followed by one little
+    /// endian f32 per voxel, x varying fastest
+    pub fn load(path: &str, min: Vec3f, max: Vec3f) -> io::Result<Self> {
+        Self::read(&fs::read(path)?, min, max)
+    }
+
+    fn read(data: &[u8], min: Vec3f, max: Vec3f) -> io::Result<Self> {
+        if data.len() < HEADER_SIZE {
+            return Err(invalid_data("missing header".to_string()));
+        }
+
+        let word = |offset: usize| data[offset..offset + 4].try_into().unwrap();
+        let resolution = [0, 4, 8].map(|offset| u32::from_le_bytes(word(offset)) as usize);
+        let count = resolution[0]
+            .checked_mul(resolution[1])
+            .and_then(|n| n.checked_mul(resolution[2]));
+        let size = count
+            .and_then(|n| n.checked_mul(4))
+            .and_then(|n| n.checked_add(HEADER_SIZE));
+
+        if count == Some(0) || size != Some(data.len()) {
+            let message = format!("{} bytes do not hold {:?} voxels", data.len(), resolution);
+            return Err(invalid_data(message));
+        }
+
+        let densities: Vec<f32> = data[HEADER_SIZE..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        if let Some(index) = densities.iter().position(|d| !d.is_finite()) {
+            let message = format!(
+                "density {} of voxel {} is not finite",
+                densities[index], index
+            );
+            return Err(invalid_data(message));
+        }
+
+        let densities = densities.into_iter().map(|d| d.max(0.0)).collect();
+        Ok(Self::new(resolution, densities, min, max))
+    }
+
+    /// Bakes the first channel of the pattern, evaluated at the voxel centers in world space
+    pub fn from_procedural(
+        procedural: &Procedural,
+        resolution: [usize; 3],
+        min: Vec3f,
+        max: Vec3f,
+    ) -> io::Result<Self> {
+        let size = max - min;
+        let mut densities = Vec::with_capacity(resolution[0] * resolution[1] * resolution[2]);
+
+        for z in 0..resolution[2] {
+            for y in 0..resolution[1] {
+                for x in 0..resolution[0] {
+                    let cell = Vec3f::new(x as f64, y as f64, z as f64) + Vec3f::from(0.5);
+                    let resolution = Vec3f::new(
+                        resolution[0] as f64,
+                        resolution[1] as f64,
+                        resolution[2] as f64,
+                    );
+                    let hit = Hit {
+                        point: min + size * cell / resolution,
+                        ..Default::default()
+                    };
+                    let density = procedural.color(&hit).x as f32;
+                    if !density.is_finite() {
+                        let message =
+                            format!("density {} at {:?} is not finite", density, hit.point);
+                        return Err(invalid_data(message));
+                    }
+                    densities.push(density.max(0.0));
+                }
+            }
+        }
+
+        let mut grid = Self::new(resolution, densities, min, max);
+        grid.source = GridSource::Procedural {
+            procedural: procedural.clone(),
+            resolution,
+        };
+        Ok(grid)
+    }
+
+    pub fn max_density(&self) -> f64 {
+        self.max_density
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.densities[(z * ny + y) * nx + x] as f64
+    }
+
+    /// Trilinearly interpolated density at 'p', voxel values sit at the voxel centers
+    pub fn density(&self, p: Vec3f) -> f64 {
+        let local = (p - self.min) / (self.max - self.min);
+        if [local.x, local.y, local.z]
+            .iter()
+            .any(|&t| !(0.0..=1.0).contains(&t))
+        {
+            return 0.0;
+        }
+
+        let mut index = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let x = (local[axis] * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            index[axis] = usize::min(x as usize, n.saturating_sub(2));
+            fraction[axis] = if n > 1 { x - index[axis] as f64 } else { 0.0 };
+        }
+
+        let [x, y, z] = index;
+        let [fx, fy, fz] = fraction;
+        let (x1, y1, z1) = (
+            usize::min(x + 1, self.resolution[0] - 1),
+            usize::min(y + 1, self.resolution[1] - 1),
+            usize::min(z + 1, self.resolution[2] - 1),
+        );
+
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let plane = |z: usize| {
+            lerp(
+                lerp(self.voxel(x, y, z), self.voxel(x1, y, z), fx),
+                lerp(self.voxel(x, y1, z), self.voxel(x1, y1, z), fx),
+                fy,
+            )
+        };
+        lerp(plane(z), plane(z1), fz)
+    }
+
+    /// Distances along the ray where it enters and leaves the box of the grid
+    pub fn clip(&self, ray: &Ray, max_t: f64) -> Option<(f64, f64)> {
+        let (mut t0, mut t1) = (0.0, max_t);
+
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let mut near = (self.min[axis] - ray.origin[axis]) * inverse;
+            let mut far = (self.max[axis] - ray.origin[axis]) * inverse;
+            if near > far {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // rays parallel to the slab give NaN bounds when they start on it
+            if !near.is_nan() {
+                t0 = f64::max(t0, near);
+            }
+            if !far.is_nan() {
+                t1 = f64::min(t1, far);
+            }
+        }
+
+        if t0 < t1 {
+            Some((t0, t1))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::grid::*;
+    use crate::procedural::Pattern;
+
+    fn bytes(resolution: [u32; 3], densities: &[f32]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for n in resolution {
+            data.extend_from_slice(&n.to_le_bytes());
+        }
+        for d in densities {
+            data.extend_from_slice(&d.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_grid_density() {
+        let data = bytes([2, 1, 1], &[0.0, 2.0]);
+        let grid = Grid::read(&data, Vec3f::from(0.0), Vec3f::new(2.0, 1.0, 1.0)).unwrap();
+        assert_eq!(grid.max_density(), 2.0);
+
+        // voxel centers return the voxel, halfway interpolates, outside is empty
+        assert_eq!(grid.density(Vec3f::new(0.5, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density(Vec3f::new(1.5, 0.5, 0.5)), 2.0);
+        assert_eq!(grid.density(Vec3f::new(1.0, 0.2, 0.9)), 1.0);
+        assert_eq!(grid.density(Vec3f::new(1.9, 0.5, 0.5)), 2.0);
+        assert_eq!(grid.density(Vec3f::new(2.5, 0.5, 0.5)), 0.0);
+
+        assert!(Grid::read(&data[..10], Vec3f::from(0.0), Vec3f::from(1.0)).is_err());
+        assert!(Grid::read(&data[..16], Vec3f::from(0.0), Vec3f::from(1.0)).is_err());
+
+        // sizes overflowing the voxel count are rejected instead of wrapping
+        let huge = bytes([u32::MAX, u32::MAX, u32::MAX], &[1.0]);
+        assert!(Grid::read(&huge, Vec3f::from(0.0), Vec3f::from(1.0)).is_err());
+
+        // densities that would stall tracking against an infinite majorant
+        for density in [f32::INFINITY, f32::NAN] {
+            let data = bytes([2, 1, 1], &[1.0, density]);
+            assert!(Grid::read(&data, Vec3f::from(0.0), Vec3f::from(1.0)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_grid_clip() {
+        let grid = Grid::new([1, 1, 1], vec![1.0], Vec3f::from(-1.0), Vec3f::from(1.0));
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(grid.clip(&ray, f64::INFINITY), Some((4.0, 6.0)));
+        assert_eq!(grid.clip(&ray, 5.0), Some((4.0, 5.0)));
+        assert_eq!(grid.clip(&ray, 3.0), None);
+
+        let inside = Ray::new(Vec3f::from(0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert_eq!(grid.clip(&inside, f64::INFINITY), Some((0.0, 1.0)));
+
+        let miss = Ray::new(Vec3f::new(0.0, 2.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(grid.clip(&miss, f64::INFINITY), None);
+    }
+
+    #[test]
+    fn test_grid_deserialize() {
+        let path = std::env::temp_dir().join("raytracer_grid.vol");
+        fs::write(&path, bytes([1, 1, 2], &[0.5, 1.5])).unwrap();
+
+        let json = format!(
+            r#"{{ "path": {:?}, "min": [0, 0, 0], "max": [1, 1, 2] }}"#,
+            path
+        );
+        let grid: Grid = serde_json::from_str(&json).unwrap();
+        assert_eq!(grid.resolution, [1, 1, 2]);
+        assert_eq!(grid.density(Vec3f::new(0.5, 0.5, 1.5)), 1.5);
+
+        let json = r#"{ "pattern": "Noise", "resolution": [4, 4, 4], "min": [0, 0, 0], "max": [1, 1, 1] }"#;
+        let grid: Grid = serde_json::from_str(json).unwrap();
+        assert_eq!(grid.densities.len(), 64);
+        assert!(grid.max_density() > 0.0 && grid.max_density() <= 1.0);
+
+        // the source is written back instead of the voxels
+        let written = serde_json::to_string(&grid).unwrap();
+        assert!(written.contains("Noise") && written.contains("resolution"));
+        let read: Grid = serde_json::from_str(&written).unwrap();
+        assert_eq!(read, grid);
+
+        let json = r#"{ "path": "missing.vol", "min": [0, 0, 0], "max": [1, 1, 1] }"#;
+        assert!(serde_json::from_str::<Grid>(json).is_err());
+
+        let json = r#"{ "pattern": "Noise", "resolution": [4, 0, 4], "min": [0, 0, 0], "max": [1, 1, 1] }"#;
+        assert!(serde_json::from_str::<Grid>(json).is_err());
+
+        let json = r#"{ "pattern": "Noise", "resolution": [4294967296, 4294967296, 4294967296], "min": [0, 0, 0], "max": [1, 1, 1] }"#;
+        assert!(serde_json::from_str::<Grid>(json).is_err());
+
+        let procedural = Procedural::new(Pattern::Gradient);
+        let grid =
+            Grid::from_procedural(&procedural, [4, 1, 1], Vec3f::from(0.0), Vec3f::from(1.0))
+                .unwrap();
+        assert_eq!(grid.voxel(0, 0, 0), 0.125);
+        assert_eq!(grid.voxel(3, 0, 0), 0.875);
+    }
+}
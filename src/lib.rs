@@ -1,17 +1,56 @@
+mod bvh;
 mod camera;
+mod coat;
 mod common;
+mod conductor;
+mod csg;
 mod geometry;
+mod gltf;
+mod grid;
 mod material;
+mod medium;
+mod mesh;
+mod mix;
+mod mtl;
+mod obj;
 mod onb;
+mod ply;
+mod principled;
+mod procedural;
 mod ray;
 mod renderer;
+mod sheen;
+mod spectrum;
+mod stl;
+mod subsurface;
+mod texture;
+mod thin_film;
+mod transform;
 mod vector;
 
+pub use bvh::*;
 pub use camera::*;
+pub use coat::*;
 pub use common::*;
+pub use conductor::*;
+pub use csg::*;
 pub use geometry::*;
+pub use gltf::*;
+pub use grid::*;
 pub use material::*;
+pub use medium::*;
+pub use mesh::*;
+pub use mix::*;
+pub use mtl::*;
 pub use onb::*;
+pub use principled::*;
+pub use procedural::*;
 pub use ray::*;
 pub use renderer::*;
+pub use sheen::*;
+pub use spectrum::*;
+pub use subsurface::*;
+pub use texture::*;
+pub use thin_film::*;
+pub use transform::*;
 pub use vector::*;
@@ -46,16 +46,32 @@ pub fn main() {
     let bounces = parse(&args[4]);
     let scene_path = &args[5];
 
-    let json = match fs::read_to_string(scene_path) {
-        Ok(value) => value,
-        Err(error) => panic!("Failed to read {:?} ({:?})", scene_path, error),
-    };
+    let config = if scene_path.ends_with(".gltf") || scene_path.ends_with(".glb") {
+        match Gltf::load(scene_path) {
+            Ok(gltf) => {
+                for warning in &gltf.warnings {
+                    eprintln!("Warning: {}", warning);
+                }
+                gltf.into_config()
+            }
+            Err(error) => panic!("Failed to import {:?} ({:?})", scene_path, error),
+        }
+    } else {
+        let json = match fs::read_to_string(scene_path) {
+            Ok(value) => value,
+            Err(error) => panic!("Failed to read {:?} ({:?})", scene_path, error),
+        };
 
-    let config: ConfigFile = match serde_json::from_str(&json) {
-        Ok(value) => value,
-        Err(error) => panic!("Failed to parse scene: {:?}", error),
+        match ConfigFile::from_json(&json) {
+            Ok(value) => value,
+            Err(error) => panic!("Failed to parse scene: {:?}", error),
+        }
     };
 
+    for warning in config.scene.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+
     let camera = Camera::new(
         config.camera.position,
         config.camera.target,
@@ -1,5 +1,16 @@
+use crate::coat::Coat;
 use crate::common::*;
+use crate::conductor::Conductor;
+use crate::geometry::Hit;
+use crate::medium::Medium;
+use crate::mix::Mix;
 use crate::onb::Onb;
+use crate::principled::Principled;
+use crate::sheen::*;
+use crate::spectrum::*;
+use crate::subsurface::Subsurface;
+use crate::texture::Texture;
+use crate::thin_film::ThinFilm;
 use crate::vector::*;
 
 use rand::Rng;
@@ -8,12 +19,15 @@ use std::f64::consts::PI;
 
 /// Bidirectional Scattering Distribution Function (BSDF)
 pub trait BSDF {
-    ///
+    /// Evaluates BSDF for the pair of directions
     fn bsdf(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f;
 
     /// Returns a outgoing direction and the corresponding PDF
     fn sample_f(&self, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64);
 
+    /// Density of 'sample_f' returning 'wi', zero for specular materials
+    fn pdf(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64;
+
     /// Returns outgoing vector and brdf multiplier
     /// 'normal' - Normal vector at hit point
     /// 'wo' - Direction vector toward camera
@@ -28,14 +42,30 @@ pub enum MaterialType {
     Uniform,
     /// Cosine-weighted Hemisphere Sampling (perfectly diffuse)
     Lambert,
+    /// Rough diffuse after Oren-Nayar, with 'roughness' as the slope deviation
+    OrenNayar,
     /// Cook-Torrance Reflection Model
     Physical,
-    ///
+    /// Dielectric (reflection and refraction), rough above 'SMOOTH_ROUGHNESS'
     Transparent,
+    /// Metal with a complex index of refraction, tinted by albedo, rough above 'SMOOTH_ROUGHNESS'
+    Conductor(Conductor),
+    /// Disney principled BSDF, albedo is the base color and roughness is squared into alpha
+    Principled(Principled),
+    /// Velvet with only the sheen of its fibers, tinted by albedo with 'roughness' as its alpha
+    Sheen,
+    /// Invisible boundary of the medium inside, rays pass straight through
+    Interface,
+    /// Dielectric over a scattering interior colored by albedo, refracted light random walks
+    /// through it until leaving diffusely, rough above 'SMOOTH_ROUGHNESS'
+    Subsurface(Subsurface),
 }
 
+/// Roughness below which dielectrics are treated as perfectly smooth
+pub const SMOOTH_ROUGHNESS: f64 = 1e-3;
+
 /// Material Properties
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Material {
     pub albedo: Vec3f,
     pub emittance: f64,
@@ -43,6 +73,400 @@ pub struct Material {
     pub ior: f64,
     pub metallic: f64,
     pub material: MaterialType,
+    /// Emitted color, replaces albedo as tint of 'emittance'
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emission_color: Option<Vec3f>,
+    /// Absorption coefficient per unit distance inside Transparent objects
+    #[serde(default)]
+    pub absorption: Vec3f,
+    /// Scattering medium inside Transparent and Interface objects, replaces 'absorption'
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medium: Option<Medium>,
+    /// Index of refraction per wavelength in spectral mode, replaces 'ior' there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispersion: Option<Dispersion>,
+    #[serde(default)]
+    pub textures: Textures,
+    /// Surface tangent at the shading point, orients anisotropic lobes
+    #[serde(skip)]
+    pub tangent: Vec3f,
+    /// Dielectric layer over the material, ignored over a Mirror or Interface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coat: Option<Coat>,
+    /// Second material blended in, the coat and shading normal apply to this one only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mix: Option<Box<Mix>>,
+    /// Separate roughness along and across the tangent for Conductor and Physical
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anisotropy: Option<Anisotropy>,
+    /// Interference film changing the Fresnel term of Transparent and Physical
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thin_film: Option<ThinFilm>,
+    /// Sheen lobe over the material, for cloth, ignored over a Mirror or Interface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sheen: Option<Sheen>,
+}
+
+/// Gray Lambert, the starting point for materials built in code
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            albedo: Vec3f::from(0.8),
+            emittance: 0.0,
+            roughness: 0.5,
+            ior: 1.5,
+            metallic: 0.0,
+            material: MaterialType::Lambert,
+            emission_color: None,
+            absorption: Vec3f::from(0.0),
+            medium: None,
+            dispersion: None,
+            textures: Textures::default(),
+            tangent: Vec3f::from(0.0),
+            coat: None,
+            mix: None,
+            anisotropy: None,
+            thin_film: None,
+            sheen: None,
+        }
+    }
+}
+
+/// Anisotropic GGX roughness, replacing 'roughness' for brushed metals
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Anisotropy {
+    /// Roughness along the tangent
+    pub alpha_x: f64,
+    /// Roughness along the bitangent
+    pub alpha_y: f64,
+    /// World space direction of the tangent, instead of the direction of increasing u
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tangent: Option<Vec3f>,
+}
+
+/// Absorption coefficient leaving 'transmittance' of the light after traveling 'distance'
+pub fn absorption_coefficient(transmittance: Vec3f, distance: f64) -> Vec3f {
+    let coefficient = |t: f64| -f64::max(t, 1e-6).ln() / distance;
+    Vec3f::new(
+        coefficient(transmittance.x),
+        coefficient(transmittance.y),
+        coefficient(transmittance.z),
+    )
+}
+
+/// Textures scaling the constant material parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Textures {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub albedo: Option<Texture>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roughness: Option<Texture>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metallic: Option<Texture>,
+    /// Emitted color, replaces 'emission_color' and albedo as tint of 'emittance'
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emission: Option<Texture>,
+    /// Tangent space normal map, with +v as green
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normal: Option<Texture>,
+    /// Height map displacing the shading normal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bump: Option<Texture>,
+    /// Height in world units of a bump value of 1
+    #[serde(default = "default_bump_scale")]
+    pub bump_scale: f64,
+}
+
+fn default_bump_scale() -> f64 {
+    1.0
+}
+
+impl Default for Textures {
+    fn default() -> Self {
+        Self {
+            albedo: None,
+            roughness: None,
+            metallic: None,
+            emission: None,
+            normal: None,
+            bump: None,
+            bump_scale: default_bump_scale(),
+        }
+    }
+}
+
+impl Material {
+    /// Returns material with the textures applied at the hit point
+    pub fn at(&self, hit: &Hit) -> Material {
+        let textures = &self.textures;
+        let value = |texture: &Option<Texture>| texture.as_ref().map_or(1.0, |t| t.value(hit));
+
+        let mut material = Material {
+            albedo: self.albedo(hit),
+            roughness: self.roughness * value(&textures.roughness),
+            metallic: self.metallic * value(&textures.metallic),
+            textures: Textures::default(),
+            tangent: self.tangent(hit),
+            medium: self.medium.clone(),
+            mix: None,
+            anisotropy: self.anisotropy.map(|a| Anisotropy {
+                alpha_x: a.alpha_x * value(&textures.roughness),
+                alpha_y: a.alpha_y * value(&textures.roughness),
+                ..a
+            }),
+            ..*self
+        };
+
+        if let Some(mix) = &self.mix {
+            let other = mix.material.at(hit);
+            let weight = mix.weight(hit);
+
+            // delta lobes cannot be blended, pick one of the materials instead
+            if material.is_specular() || other.is_specular() {
+                if rand::thread_rng().gen_range(0.0..1.0) < weight {
+                    return other;
+                }
+            } else {
+                material.mix = Some(Box::new(Mix::new(other, weight)));
+            }
+        }
+
+        material
+    }
+
+    /// Tangent orienting anisotropic lobes, the world space hint or the uv tangent
+    fn tangent(&self, hit: &Hit) -> Vec3f {
+        match self.anisotropy.and_then(|a| a.tangent) {
+            Some(tangent) => tangent,
+            None => hit.tangent_frame().0,
+        }
+    }
+
+    /// GGX roughness along the tangent and the bitangent
+    fn alphas(&self) -> (f64, f64) {
+        match self.anisotropy {
+            Some(a) => (
+                f64::max(a.alpha_x, SMOOTH_ROUGHNESS),
+                f64::max(a.alpha_y, SMOOTH_ROUGHNESS),
+            ),
+            None => (self.roughness, self.roughness),
+        }
+    }
+
+    /// Largest roughness in any direction
+    fn max_roughness(&self) -> f64 {
+        match self.anisotropy {
+            Some(a) => f64::max(a.alpha_x, a.alpha_y),
+            None => self.roughness,
+        }
+    }
+
+    /// Returns material with the colors replaced by their spectra at 'lambda' nanometers
+    pub fn at_wavelength(&self, lambda: f64) -> Material {
+        Material {
+            albedo: Vec3f::from(rgb_to_spectrum(self.albedo, lambda)),
+            absorption: Vec3f::from(rgb_to_spectrum(self.absorption, lambda)),
+            medium: self.medium.as_ref().map(|medium| medium.at_wavelength(lambda)),
+            ior: self.dispersion.map_or(self.ior, |d| d.ior(lambda)),
+            material: match self.material {
+                MaterialType::Conductor(conductor) => {
+                    MaterialType::Conductor(conductor.at_wavelength(lambda))
+                }
+                MaterialType::Subsurface(subsurface) => {
+                    MaterialType::Subsurface(subsurface.at_wavelength(lambda))
+                }
+                material => material,
+            },
+            coat: self.coat.map(|coat| coat.at_wavelength(lambda)),
+            textures: self.textures.clone(),
+            mix: self.mix.as_ref().map(|mix| Box::new(mix.at_wavelength(lambda))),
+            thin_film: self.thin_film.map(|film| film.at_wavelength(lambda)),
+            sheen: self.sheen.map(|sheen| sheen.at_wavelength(lambda)),
+            ..*self
+        }
+    }
+
+    /// True for materials scattering into a single direction, which light sampling cannot hit
+    pub fn is_specular(&self) -> bool {
+        match self.material {
+            MaterialType::Mirror | MaterialType::Interface => true,
+            // a boundary that does not refract has no microfacets to spread light
+            MaterialType::Transparent if self.dielectric_ior() == 1.0 => true,
+            MaterialType::Transparent | MaterialType::Subsurface(_) => {
+                self.roughness < SMOOTH_ROUGHNESS && self.coat.is_none() && self.sheen.is_none()
+            }
+            MaterialType::Conductor(_) => {
+                self.max_roughness() < SMOOTH_ROUGHNESS
+                    && self.coat.is_none()
+                    && self.sheen.is_none()
+            }
+            _ => false,
+        }
+    }
+
+    /// Medium inside the object, absorbing only unless a scattering medium is given
+    pub fn interior(&self) -> Medium {
+        if let MaterialType::Subsurface(subsurface) = self.material {
+            return subsurface.medium(self.albedo);
+        }
+
+        self.medium
+            .clone()
+            .unwrap_or_else(|| Medium::absorbing(self.absorption))
+    }
+
+    /// Uncolored dielectric boundary of a Subsurface material, the medium inside gives the color
+    fn boundary(&self) -> Material {
+        Material {
+            albedo: Vec3f::from(1.0),
+            material: MaterialType::Transparent,
+            textures: self.textures.clone(),
+            medium: None,
+            mix: None,
+            ..*self
+        }
+    }
+
+    /// Coat and the material beneath it, whose smooth lobes become sharp rough ones
+    fn coated(&self) -> Option<(Coat, Material)> {
+        let coat = self.coat?;
+        if matches!(self.material, MaterialType::Mirror | MaterialType::Interface) {
+            return None;
+        }
+
+        let base = Material {
+            roughness: f64::max(self.roughness, SMOOTH_ROUGHNESS),
+            coat: None,
+            textures: self.textures.clone(),
+            medium: self.medium.clone(),
+            mix: None,
+            ..*self
+        };
+        Some((coat, base))
+    }
+
+    /// Sheen and the material beneath it, whose smooth lobes become sharp rough ones
+    fn sheened(&self) -> Option<(Sheen, Material)> {
+        let sheen = self.sheen?;
+        if matches!(self.material, MaterialType::Mirror | MaterialType::Interface) {
+            return None;
+        }
+
+        let base = Material {
+            roughness: f64::max(self.roughness, SMOOTH_ROUGHNESS),
+            sheen: None,
+            textures: self.textures.clone(),
+            medium: self.medium.clone(),
+            mix: None,
+            ..*self
+        };
+        Some((sheen, base))
+    }
+
+    /// Mix and the material it blends into
+    fn mixed(&self) -> Option<(&Mix, Material)> {
+        let mix = self.mix.as_deref()?;
+        let base = Material {
+            textures: self.textures.clone(),
+            medium: self.medium.clone(),
+            mix: None,
+            ..*self
+        };
+        Some((mix, base))
+    }
+
+    /// Returns the shading normal perturbed by the normal and bump maps
+    pub fn shading_normal(&self, hit: &Hit) -> Vec3f {
+        let mut normal = hit.normal;
+
+        if let Some(texture) = &self.textures.normal {
+            let (tangent, bitangent) = hit.tangent_frame();
+            let n = texture.vector(hit) * 2.0 - Vec3f::from(1.0);
+            let perturbed = tangent * n.x + bitangent * n.y + normal * n.z;
+
+            if perturbed.length() > 0.0 {
+                normal = perturbed.normalize();
+            }
+        }
+
+        if let Some(texture) = &self.textures.bump {
+            normal = self.bump(texture, hit, normal);
+        }
+
+        normal
+    }
+
+    /// Displaces the surface along 'normal' by the height texture and returns the new normal
+    fn bump(&self, texture: &Texture, hit: &Hit, normal: Vec3f) -> Vec3f {
+        const DELTA: f64 = 0.0005;
+
+        let (dpdu, dpdv) = if hit.tangent.length() > 0.0 {
+            (hit.tangent, hit.bitangent)
+        } else {
+            hit.tangent_frame()
+        };
+
+        // finite differences, shifting both uv and the point for world mapped patterns
+        let height = |du: f64, dv: f64| {
+            let shifted = Hit {
+                point: hit.point + dpdu * du + dpdv * dv,
+                uv: hit.uv + Vec2f::new(du, dv),
+                ..*hit
+            };
+            texture.value(&shifted) * self.textures.bump_scale
+        };
+
+        let h = height(0.0, 0.0);
+        let dhdu = (height(DELTA, 0.0) - h) / DELTA;
+        let dhdv = (height(0.0, DELTA) - h) / DELTA;
+
+        let perturbed = Vec3f::cross(dpdu + normal * dhdu, dpdv + normal * dhdv);
+        if perturbed.length() == 0.0 {
+            return normal;
+        }
+
+        // keep the original orientation
+        let perturbed = perturbed.normalize();
+        if Vec3f::dot(perturbed, normal) < 0.0 {
+            -perturbed
+        } else {
+            perturbed
+        }
+    }
+
+    pub fn albedo(&self, hit: &Hit) -> Vec3f {
+        let albedo = self.albedo * hit.color;
+        match &self.textures.albedo {
+            Some(texture) => albedo * texture.color(hit),
+            None => albedo,
+        }
+    }
+
+    /// Emits 'radiance', its brightest channel as 'emittance' and its color as the tint
+    pub fn with_emission(mut self, radiance: Vec3f) -> Self {
+        let emittance = f64::max(radiance.x, f64::max(radiance.y, radiance.z));
+        if emittance > 0.0 {
+            self.emittance = emittance;
+            self.emission_color = Some(radiance / emittance);
+        }
+        self
+    }
+
+    /// Emitted radiance at the hit point
+    pub fn emission(&self, hit: &Hit) -> Vec3f {
+        let emission = match &self.textures.emission {
+            Some(texture) => texture.color(hit) * self.emittance,
+            None => self.emission_color.unwrap_or_else(|| self.albedo(hit)) * self.emittance,
+        };
+
+        match &self.mix {
+            Some(mix) => {
+                let weight = mix.weight(hit);
+                emission * (1.0 - weight) + mix.material.emission(hit) * weight
+            }
+            None => emission,
+        }
+    }
 }
 
 /// Schlick's Fresnel Approximation
@@ -50,7 +474,7 @@ fn fresnel_schlick(f0: Vec3f, cos_theta: f64) -> Vec3f {
     f0 + (Vec3f::from(1.0) - f0) * f64::powf((1.0 - cos_theta).clamp(0.0, 1.0), 5.0)
 }
 
-fn distribution_ggx(normal: Vec3f, halfway: Vec3f, roughness: f64) -> f64 {
+pub(crate) fn distribution_ggx(normal: Vec3f, halfway: Vec3f, roughness: f64) -> f64 {
     let a2 = roughness * roughness;
     let ndoth = f64::max(Vec3f::dot(normal, halfway), 0.0);
     let ndoth2 = ndoth * ndoth;
@@ -75,8 +499,424 @@ fn geometry_smith(normal: Vec3f, wo: Vec3f, wi: Vec3f, roughness: f64) -> f64 {
     ggx1 * ggx2
 }
 
+// Fujii 2012, "A tiny improvement of Oren-Nayar reflectance model"
+/// Rough diffuse with retroreflection, equal to Lambert at zero roughness
+fn oren_nayar(albedo: Vec3f, normal: Vec3f, wo: Vec3f, wi: Vec3f, roughness: f64) -> Vec3f {
+    let cos_o = Vec3f::dot(normal, wo);
+    let cos_i = Vec3f::dot(normal, wi);
+
+    if cos_o <= 0.0 || cos_i <= 0.0 {
+        return Vec3f::from(0.0);
+    }
+
+    let sigma = roughness.clamp(0.0, 1.0);
+    let s = Vec3f::dot(wo, wi) - cos_o * cos_i;
+    let t = if s > 0.0 { f64::max(cos_o, cos_i) } else { 1.0 };
+    let a = 1.0 / (PI + (PI / 2.0 - 2.0 / 3.0) * sigma);
+    let b = sigma * a;
+
+    albedo * (a + b * s / t)
+}
+
+/// Reflectance of a smooth dielectric boundary
+/// 'cos_i' - cosine of the incident direction, 'eta' - ior on the far side over the incident side
+pub(crate) fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+
+    if sin2_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (rs * rs + rp * rp) / 2.0
+}
+
+/// Exact Smith masking for GGX, zero if 'v' sees the back of the microfacet
+pub(crate) fn smith_g1_ggx(v: Vec3f, halfway: Vec3f, normal: Vec3f, roughness: f64) -> f64 {
+    let cos_v = Vec3f::dot(v, normal);
+
+    if Vec3f::dot(v, halfway) * cos_v <= 0.0 {
+        return 0.0;
+    }
+
+    let cos2 = cos_v * cos_v;
+    let tan2 = (1.0 - cos2) / cos2;
+    2.0 / (1.0 + f64::sqrt(1.0 + roughness * roughness * tan2))
+}
+
+/// Anisotropic GGX distribution of the local microfacet normal 'h'
+pub(crate) fn ggx_anisotropic(h: Vec3f, alpha_x: f64, alpha_y: f64) -> f64 {
+    if h.z <= 0.0 {
+        return 0.0;
+    }
+    let (x, y) = (h.x / alpha_x, h.y / alpha_y);
+    let t = x * x + y * y + h.z * h.z;
+    1.0 / (PI * alpha_x * alpha_y * t * t)
+}
+
+/// Smith masking of the anisotropic GGX distribution for the local direction 'w'
+pub(crate) fn ggx_anisotropic_g1(w: Vec3f, alpha_x: f64, alpha_y: f64) -> f64 {
+    if w.z <= 0.0 {
+        return 0.0;
+    }
+    let tan2 = (alpha_x * alpha_x * w.x * w.x + alpha_y * alpha_y * w.y * w.y) / (w.z * w.z);
+    2.0 / (1.0 + f64::sqrt(1.0 + tan2))
+}
+
+/// Density of sampling 'halfway' among the GGX normals visible from 'wo'
+pub(crate) fn visible_normal_pdf(normal: Vec3f, wo: Vec3f, halfway: Vec3f, roughness: f64) -> f64 {
+    smith_g1_ggx(wo, halfway, normal, roughness)
+        * f64::max(0.0, Vec3f::dot(wo, halfway))
+        * distribution_ggx(normal, halfway, roughness)
+        / Vec3f::dot(normal, wo)
+}
+
+/// GGX normal visible from 'wo', which must lie above the surface
+pub(crate) fn sample_visible_normal(normal: Vec3f, wo: Vec3f, roughness: f64) -> Vec3f {
+    let onb = Onb::new(normal);
+    let local = onb.to_local(wo);
+    onb.transform(ggx_visible_normal(local, roughness, roughness))
+}
+
+// Rough dielectric after Walter et al. 2007, "Microfacet Models for Refraction through Rough Surfaces"
+
+/// Normal on the side of 'wo' and the ratio of the ior across the boundary
+/// An ior of zero or below is not physical and is taken as 1, a boundary that does not refract
+pub(crate) fn dielectric_side(normal: Vec3f, wo: Vec3f, ior: f64) -> (Vec3f, f64) {
+    let ior = if ior > 0.0 { ior } else { 1.0 };
+    if Vec3f::dot(normal, wo) >= 0.0 {
+        (normal, ior)
+    } else {
+        (-normal, 1.0 / ior)
+    }
+}
+
+/// Microfacet normal scattering 'wo' into 'wi', facing the side of 'wo'
+fn dielectric_halfway(normal: Vec3f, wo: Vec3f, wi: Vec3f, eta: f64) -> Option<Vec3f> {
+    let halfway = if Vec3f::dot(normal, wi) > 0.0 {
+        wo + wi
+    } else {
+        wo + wi * eta
+    };
+
+    if halfway.length() == 0.0 {
+        return None;
+    }
+
+    let halfway = halfway.normalize();
+    if Vec3f::dot(halfway, normal) < 0.0 {
+        Some(-halfway)
+    } else {
+        Some(halfway)
+    }
+}
+
+/// Reflectance of a dielectric boundary per channel, under the thin film if any
+fn dielectric_reflectance(cos_i: f64, eta: f64, film: Option<ThinFilm>) -> Vec3f {
+    match film {
+        Some(film) => film.dielectric(cos_i, eta),
+        None => Vec3f::from(fresnel_dielectric(cos_i, eta)),
+    }
+}
+
+fn mean(v: Vec3f) -> f64 {
+    (v.x + v.y + v.z) / 3.0
+}
+
+/// Untinted BSDF of a rough boundary of relative index 'ior'
+pub(crate) fn rough_dielectric(
+    normal: Vec3f,
+    wo: Vec3f,
+    wi: Vec3f,
+    roughness: f64,
+    ior: f64,
+    film: Option<ThinFilm>,
+) -> Vec3f {
+    let (normal, eta) = dielectric_side(normal, wo, ior);
+    let cos_o = Vec3f::dot(normal, wo);
+    let cos_i = Vec3f::dot(normal, wi);
+
+    let halfway = match dielectric_halfway(normal, wo, wi, eta) {
+        Some(halfway) if cos_o != 0.0 && cos_i != 0.0 => halfway,
+        _ => return Vec3f::from(0.0),
+    };
+
+    let (oh, ih) = (Vec3f::dot(wo, halfway), Vec3f::dot(wi, halfway));
+    let distribution = distribution_ggx(normal, halfway, roughness);
+    let geometry =
+        smith_g1_ggx(wo, halfway, normal, roughness) * smith_g1_ggx(wi, halfway, normal, roughness);
+    let fresnel = dielectric_reflectance(oh, eta, film);
+
+    if cos_i > 0.0 {
+        fresnel * (distribution * geometry / (4.0 * cos_o * cos_i))
+    } else {
+        let denom = oh + eta * ih;
+        let jacobian = (ih * oh).abs() / (cos_i * cos_o * denom * denom).abs();
+        (Vec3f::from(1.0) - fresnel) * (distribution * geometry * jacobian)
+    }
+}
+
+pub(crate) fn rough_dielectric_pdf(
+    normal: Vec3f,
+    wo: Vec3f,
+    wi: Vec3f,
+    roughness: f64,
+    ior: f64,
+    film: Option<ThinFilm>,
+) -> f64 {
+    let (normal, eta) = dielectric_side(normal, wo, ior);
+
+    let halfway = match dielectric_halfway(normal, wo, wi, eta) {
+        Some(halfway) => halfway,
+        None => return 0.0,
+    };
+
+    let (oh, ih) = (Vec3f::dot(wo, halfway), Vec3f::dot(wi, halfway));
+    if oh <= 0.0 {
+        return 0.0;
+    }
+
+    let pdf_halfway = visible_normal_pdf(normal, wo, halfway, roughness);
+    let fresnel = mean(dielectric_reflectance(oh, eta, film));
+
+    if Vec3f::dot(normal, wi) > 0.0 {
+        pdf_halfway * fresnel / (4.0 * oh)
+    } else {
+        let denom = oh + eta * ih;
+        pdf_halfway * (1.0 - fresnel) * eta * eta * ih.abs() / (denom * denom)
+    }
+}
+
+/// Samples a visible microfacet normal, then reflects or refracts by its Fresnel term
+pub(crate) fn sample_rough_dielectric(
+    normal: Vec3f,
+    wo: Vec3f,
+    roughness: f64,
+    ior: f64,
+    film: Option<ThinFilm>,
+) -> (Vec3f, f64) {
+    let (side, eta) = dielectric_side(normal, wo, ior);
+    if Vec3f::dot(side, wo) <= 0.0 {
+        return (reflect(-wo, side), 0.0);
+    }
+
+    let halfway = sample_visible_normal(side, wo, roughness);
+    let oh = Vec3f::dot(wo, halfway);
+
+    if oh <= 0.0 {
+        return (reflect(-wo, halfway), 0.0);
+    }
+
+    let fresnel = mean(dielectric_reflectance(oh, eta, film));
+    let wi = if rand::thread_rng().gen_range(0.0..1.0) < fresnel {
+        reflect(-wo, halfway)
+    } else {
+        let cos_t = f64::sqrt(1.0 - (1.0 - oh * oh) / (eta * eta));
+        (-wo / eta + halfway * (oh / eta - cos_t)).normalize()
+    };
+
+    (wi, rough_dielectric_pdf(normal, wo, wi, roughness, ior, film))
+}
+
+impl Material {
+    fn rough_dielectric(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        let film = self.thin_film;
+        self.albedo * rough_dielectric(normal, wo, wi, self.roughness, self.ior, film)
+    }
+
+    fn sample_rough_dielectric(&self, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64) {
+        sample_rough_dielectric(normal, wo, self.roughness, self.ior, self.thin_film)
+    }
+
+    /// Ior of the boundary, non-positive values are taken as 1 like in 'dielectric_side'
+    fn dielectric_ior(&self) -> f64 {
+        if self.ior > 0.0 {
+            self.ior
+        } else {
+            1.0
+        }
+    }
+
+    /// Fraction of light the smooth boundary transmits per channel, and its mean
+    fn smooth_transmission(&self, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64) {
+        match self.thin_film {
+            Some(film) => {
+                let cos_o = Vec3f::dot(normal, wo);
+                let eta = if cos_o >= 0.0 {
+                    self.dielectric_ior()
+                } else {
+                    1.0 / self.dielectric_ior()
+                };
+                let transmitted = Vec3f::from(1.0) - film.dielectric(cos_o.abs(), eta);
+                (transmitted, mean(transmitted))
+            }
+            None => {
+                let fr = fresnel(-wo, normal, self.dielectric_ior());
+                (Vec3f::from(fr), fr)
+            }
+        }
+    }
+
+    /// Schlick's Fresnel of the Cook-Torrance model, or the reflectance under the thin film
+    fn physical_fresnel(&self, f0: Vec3f, cos_theta: f64) -> Vec3f {
+        match self.thin_film {
+            Some(film) => {
+                // base index matching the reflectance at normal incidence
+                let ior = |f0: f64| {
+                    let r = f64::sqrt(f0.clamp(0.0, 0.9999));
+                    (1.0 + r) / (1.0 - r)
+                };
+                let inside = Vec3f::new(ior(f0.x), ior(f0.y), ior(f0.z));
+                film.reflectance(cos_theta, 1.0, inside)
+            }
+            None => fresnel_schlick(f0, cos_theta),
+        }
+    }
+}
+
+impl Material {
+    /// Distribution and masking of the anisotropic GGX normals, in the tangent frame
+    fn anisotropic_ggx(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> (f64, f64) {
+        let frame = Onb::from_tangent(normal, self.tangent);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        let halfway = Vec3f::normalize(wo + wi);
+        let (alpha_x, alpha_y) = self.alphas();
+
+        let distribution = ggx_anisotropic(halfway, alpha_x, alpha_y);
+        let geometry =
+            ggx_anisotropic_g1(wo, alpha_x, alpha_y) * ggx_anisotropic_g1(wi, alpha_x, alpha_y);
+        (distribution, geometry)
+    }
+
+    /// GGX microfacet reflection with the exact Fresnel term of the conductor
+    fn rough_conductor(&self, conductor: &Conductor, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        let cos_o = Vec3f::dot(normal, wo);
+        let cos_i = Vec3f::dot(normal, wi);
+
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return Vec3f::from(0.0);
+        }
+
+        let halfway = Vec3f::normalize(wo + wi);
+        let (distribution, geometry) = self.anisotropic_ggx(normal, wo, wi);
+        let fresnel = conductor.fresnel(Vec3f::dot(wo, halfway));
+
+        self.albedo * fresnel * (distribution * geometry / (4.0 * cos_o * cos_i))
+    }
+
+    fn rough_conductor_pdf(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        let frame = Onb::from_tangent(normal, self.tangent);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        let halfway = wo + wi;
+
+        if wo.z <= 0.0 || halfway.length() == 0.0 {
+            return 0.0;
+        }
+
+        // visible normal density G1(wo) max(0, wo.h) D(h) / wo.z over the reflection jacobian 4 wo.h
+        let halfway = halfway.normalize();
+        if Vec3f::dot(wo, halfway) <= 0.0 {
+            return 0.0;
+        }
+        let (alpha_x, alpha_y) = self.alphas();
+        ggx_anisotropic_g1(wo, alpha_x, alpha_y) * ggx_anisotropic(halfway, alpha_x, alpha_y)
+            / (4.0 * wo.z)
+    }
+
+    fn sample_rough_conductor(&self, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64) {
+        if Vec3f::dot(normal, wo) <= 0.0 {
+            return (reflect(-wo, normal), 0.0);
+        }
+
+        let frame = Onb::from_tangent(normal, self.tangent);
+        let (alpha_x, alpha_y) = self.alphas();
+        let local = frame.to_local(wo);
+        let halfway = ggx_visible_normal(local, alpha_x, alpha_y);
+        let wi = frame.transform(reflect(-local, halfway));
+        (wi, self.rough_conductor_pdf(normal, wo, wi))
+    }
+
+    /// Distribution and geometry terms of the Cook-Torrance model
+    fn physical_ggx(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> (f64, f64) {
+        if self.anisotropy.is_some() {
+            return self.anisotropic_ggx(normal, wo, wi);
+        }
+
+        let halfway = Vec3::normalize(wo + wi);
+        (
+            distribution_ggx(normal, halfway, self.roughness),
+            geometry_smith(normal, wo, wi, self.roughness),
+        )
+    }
+
+    /// Probability of sampling the specular lobe, its share of the light reflected at 'cos_o'
+    fn physical_probability(&self, cos_o: f64) -> f64 {
+        let f0 = Vec3::lerp(Vec3::from(0.04), self.albedo, self.metallic);
+        let fresnel = fresnel_schlick(f0, cos_o);
+        let specular = (fresnel.x + fresnel.y + fresnel.z) / 3.0;
+        let albedo = (self.albedo.x + self.albedo.y + self.albedo.z) / 3.0;
+        let sum = specular + (1.0 - specular) * (1.0 - self.metallic) * albedo;
+
+        if sum > 0.0 {
+            specular / sum
+        } else {
+            1.0
+        }
+    }
+
+    /// Mixture of the visible GGX normals and the cosine weighted diffuse lobe
+    fn physical_pdf(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        let cos_o = Vec3f::dot(normal, wo);
+        let cos_i = Vec3f::dot(normal, wi);
+        let halfway = wo + wi;
+
+        if cos_o <= 0.0 || cos_i <= 0.0 || halfway.length() == 0.0 {
+            return 0.0;
+        }
+
+        let halfway = halfway.normalize();
+        let roughness = f64::max(self.roughness, SMOOTH_ROUGHNESS);
+        let specular =
+            visible_normal_pdf(normal, wo, halfway, roughness) / (4.0 * Vec3f::dot(wo, halfway));
+
+        let p = self.physical_probability(cos_o);
+        p * specular + (1.0 - p) * cos_i / PI
+    }
+
+    fn sample_physical(&self, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64) {
+        let cos_o = Vec3f::dot(normal, wo);
+        if cos_o <= 0.0 {
+            return (reflect(-wo, normal), 0.0);
+        }
+
+        let mut rng = rand::thread_rng();
+        let wi = if rng.gen_range(0.0..1.0) < self.physical_probability(cos_o) {
+            let roughness = f64::max(self.roughness, SMOOTH_ROUGHNESS);
+            reflect(-wo, sample_visible_normal(normal, wo, roughness))
+        } else {
+            Onb::local_to_world(normal, cosine_weighted_hemisphere())
+        };
+        (wi, self.physical_pdf(normal, wo, wi))
+    }
+}
+
 impl BSDF for Material {
     fn sample_f(&self, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64) {
+        if let Some((mix, base)) = self.mixed() {
+            return mix.sample_f(&base, normal, wo);
+        }
+
+        if let Some((sheen, base)) = self.sheened() {
+            return sheen.sample_f(&base, normal, wo);
+        }
+
+        if let Some((coat, base)) = self.coated() {
+            return coat.sample_f(&base, normal, wo);
+        }
+
         match self.material {
             MaterialType::Lambert => {
                 let wi = Onb::local_to_world(normal, cosine_weighted_hemisphere());
@@ -89,54 +929,211 @@ impl BSDF for Material {
                 let pdf = 1.0;
                 (wi, pdf)
             }
+            MaterialType::Interface => (-wo, 1.0),
+            MaterialType::Subsurface(_) => self.boundary().sample_f(normal, wo),
+            MaterialType::Transparent if !self.is_specular() => {
+                self.sample_rough_dielectric(normal, wo)
+            }
+            MaterialType::Conductor(_) if !self.is_specular() => {
+                self.sample_rough_conductor(normal, wo)
+            }
+            MaterialType::Conductor(_) => (reflect(-wo, normal), 1.0),
+            MaterialType::Principled(principled) => principled.sample_f(self, normal, wo),
             MaterialType::Transparent => {
                 let mut rng = rand::thread_rng();
                 let r = rng.gen_range(0.0..1.0);
 
-                let fr = fresnel(-wo, normal, self.ior);
+                let (_, fr) = self.smooth_transmission(normal, wo);
 
                 if r <= fr {
-                    let wi = refract(-wo, normal, self.ior);
+                    let wi = refract(-wo, normal, self.dielectric_ior());
                     (wi, 1.0)
                 } else {
                     let wi = reflect(-wo, normal);
                     (wi, 1.0)
                 }
             }
-            _ => panic!("not implemented"),
+            MaterialType::Uniform => {
+                let wi = Onb::local_to_world(normal, uniform_hemisphere());
+                (wi, 1.0 / (2.0 * PI))
+            }
+            MaterialType::Sheen => sample_sheen(normal),
+            MaterialType::Physical => self.sample_physical(normal, wo),
+            MaterialType::OrenNayar => {
+                let wi = Onb::local_to_world(normal, cosine_weighted_hemisphere());
+                let cos_theta = Vec3f::dot(normal, wi).abs();
+                (wi, cos_theta / PI)
+            }
         }
     }
 
-    fn bsdf(&self, normal: Vec3f, _wo: Vec3f, wi: Vec3f) -> Vec3f {
+    fn pdf(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        if let Some((mix, base)) = self.mixed() {
+            return mix.pdf(&base, normal, wo, wi);
+        }
+
+        if let Some((sheen, base)) = self.sheened() {
+            return sheen.pdf(&base, normal, wo, wi);
+        }
+
+        if let Some((coat, base)) = self.coated() {
+            return coat.pdf(&base, normal, wo, wi);
+        }
+
+        if self.is_specular() {
+            return 0.0;
+        }
+
+        let cos_theta = Vec3f::dot(normal, wi);
+        match self.material {
+            MaterialType::Lambert | MaterialType::OrenNayar => f64::max(cos_theta, 0.0) / PI,
+            MaterialType::Physical => self.physical_pdf(normal, wo, wi),
+            MaterialType::Uniform if cos_theta > 0.0 => 1.0 / (2.0 * PI),
+            MaterialType::Transparent => {
+                rough_dielectric_pdf(normal, wo, wi, self.roughness, self.ior, self.thin_film)
+            }
+            MaterialType::Subsurface(_) => self.boundary().pdf(normal, wo, wi),
+            MaterialType::Conductor(_) => self.rough_conductor_pdf(normal, wo, wi),
+            MaterialType::Principled(principled) => principled.pdf(self, normal, wo, wi),
+            MaterialType::Sheen => sheen_pdf(normal, wi),
+            _ => 0.0,
+        }
+    }
+
+    fn bsdf(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        if let Some((mix, base)) = self.mixed() {
+            return mix.bsdf(&base, normal, wo, wi);
+        }
+
+        if let Some((sheen, base)) = self.sheened() {
+            return sheen.bsdf(&base, normal, wo, wi);
+        }
+
+        if let Some((coat, base)) = self.coated() {
+            return coat.bsdf(&base, normal, wo, wi);
+        }
+
         match self.material {
             MaterialType::Lambert => self.albedo / PI,
             MaterialType::Mirror => {
                 let cos_theta = Vec3::dot(normal, wi).abs();
                 self.albedo / cos_theta
             }
+            MaterialType::Interface => {
+                let cos_theta = Vec3::dot(normal, wi).abs();
+                Vec3f::from(1.0 / cos_theta)
+            }
+            MaterialType::Subsurface(_) => self.boundary().bsdf(normal, wo, wi),
+            MaterialType::Transparent if !self.is_specular() => {
+                self.rough_dielectric(normal, wo, wi)
+            }
+            MaterialType::Conductor(conductor) if !self.is_specular() => {
+                self.rough_conductor(&conductor, normal, wo, wi)
+            }
+            MaterialType::Conductor(conductor) => {
+                let cos_theta = Vec3::dot(normal, wi).abs();
+                self.albedo * conductor.fresnel(cos_theta) / cos_theta
+            }
             MaterialType::Transparent => {
                 let cos_theta = Vec3::dot(normal, wi).abs();
-                self.albedo / cos_theta
+
+                // colored film reflectance over the chance 'sample_f' picked the direction by
+                let weight = match self.thin_film {
+                    Some(_) => {
+                        let (transmitted, fr) = self.smooth_transmission(normal, wo);
+                        if Vec3f::dot(normal, wi) * Vec3f::dot(normal, wo) < 0.0 {
+                            transmitted / fr
+                        } else {
+                            (Vec3f::from(1.0) - transmitted) / (1.0 - fr)
+                        }
+                    }
+                    None => Vec3f::from(1.0),
+                };
+                self.albedo * weight / cos_theta
+            }
+            MaterialType::Principled(principled) => principled.bsdf(self, normal, wo, wi),
+            MaterialType::Uniform => self.albedo / PI,
+            MaterialType::OrenNayar => oren_nayar(self.albedo, normal, wo, wi, self.roughness),
+            MaterialType::Sheen => self.albedo * sheen_lobe(normal, wo, wi, self.roughness),
+            MaterialType::Physical => {
+                // Cook-Torrance specular with Lambertian diffuse for the non-metallic part
+                let ndotl = Vec3f::dot(normal, wi);
+                let ndotv = Vec3f::dot(normal, wo);
+
+                if ndotl <= 0.0 || ndotv <= 0.0 {
+                    return Vec3f::from(0.0);
+                }
+
+                let halfway = Vec3::normalize(wo + wi);
+                let f0 = Vec3::lerp(Vec3::from(0.04), self.albedo, self.metallic);
+                let fresnel = self.physical_fresnel(f0, Vec3f::dot(halfway, wo));
+                let (distribution, geometry) = self.physical_ggx(normal, wo, wi);
+
+                let specular = fresnel * distribution * geometry / (4.0 * ndotl * ndotv);
+                let diffuse =
+                    (Vec3f::from(1.0) - fresnel) * self.albedo * ((1.0 - self.metallic) / PI);
+
+                specular + diffuse
             }
-            _ => panic!("not implemented"),
         }
     }
 
     fn sample(&self, normal: Vec3f, wo: Vec3f) -> (Vec3f, Vec3f) {
+        if let Some((mix, base)) = self.mixed() {
+            return mix.sample(&base, normal, wo);
+        }
+
+        if let Some((sheen, base)) = self.sheened() {
+            return sheen.sample(&base, normal, wo);
+        }
+
+        if let Some((coat, base)) = self.coated() {
+            return coat.sample(&base, normal, wo);
+        }
+
         match self.material {
             MaterialType::Mirror => (reflect(-wo, normal), self.albedo),
+            MaterialType::Interface => (-wo, Vec3f::from(1.0)),
+            MaterialType::Subsurface(_) => self.boundary().sample(normal, wo),
+            MaterialType::Conductor(conductor) if self.is_specular() => {
+                let cos_theta = Vec3f::dot(normal, wo);
+                (
+                    reflect(-wo, normal),
+                    self.albedo * conductor.fresnel(cos_theta),
+                )
+            }
+            MaterialType::Conductor(conductor) => {
+                let (wi, pdf) = self.sample_rough_conductor(normal, wo);
+                if pdf == 0.0 {
+                    return (wi, Vec3f::from(0.0));
+                }
+                let cos_theta = Vec3f::dot(normal, wi).abs();
+                (
+                    wi,
+                    self.rough_conductor(&conductor, normal, wo, wi) * cos_theta / pdf,
+                )
+            }
+            MaterialType::Principled(principled) => principled.sample(self, normal, wo),
+            MaterialType::Transparent if !self.is_specular() => {
+                let (wi, pdf) = self.sample_rough_dielectric(normal, wo);
+                if pdf == 0.0 {
+                    return (wi, Vec3f::from(0.0));
+                }
+                let cos_theta = Vec3f::dot(normal, wi).abs();
+                (wi, self.rough_dielectric(normal, wo, wi) * cos_theta / pdf)
+            }
             MaterialType::Transparent => {
                 let mut rng = rand::thread_rng();
                 let r = rng.gen_range(0.0..1.0);
 
-                let fr = fresnel(-wo, normal, self.ior);
+                let (transmitted, fr) = self.smooth_transmission(normal, wo);
 
                 if r <= fr {
-                    let wi = refract(-wo, normal, self.ior);
-                    (wi, self.albedo * fr)
+                    let wi = refract(-wo, normal, self.dielectric_ior());
+                    (wi, self.albedo * transmitted)
                 } else {
                     let wi = reflect(-wo, normal);
-                    (wi, self.albedo * (1.0 - fr))
+                    (wi, self.albedo * (Vec3f::from(1.0) - transmitted))
                 }
             }
             MaterialType::Lambert => {
@@ -147,6 +1144,19 @@ impl BSDF for Material {
                 let brdf = self.albedo / PI;
                 (wi, brdf * cos_theta / pdf)
             }
+            MaterialType::OrenNayar => {
+                // Cosine-weighted hemisphere sampling, pdf cos / PI cancels the cosine
+                let wi = Onb::local_to_world(normal, cosine_weighted_hemisphere());
+                let brdf = oren_nayar(self.albedo, normal, wo, wi, self.roughness);
+                (wi, brdf * PI)
+            }
+            MaterialType::Sheen => {
+                // Uniform hemisphere sampling
+                let (wi, pdf) = sample_sheen(normal);
+                let cos_theta = Vec3f::dot(normal, wi);
+                let brdf = self.albedo * sheen_lobe(normal, wo, wi, self.roughness);
+                (wi, brdf * cos_theta / pdf)
+            }
             MaterialType::Uniform => {
                 // Uniform hemisphere sampling
                 let pdf = 1.0 / (2.0 * PI);
@@ -156,43 +1166,452 @@ impl BSDF for Material {
                 (wi, brdf * cos_theta / pdf)
             }
             MaterialType::Physical => {
-                // Cook-Torrance Reflection Model
+                let (wi, pdf) = self.sample_physical(normal, wo);
+                if pdf == 0.0 {
+                    return (wi, Vec3f::from(0.0));
+                }
+                let cos_theta = Vec3f::dot(normal, wi).abs();
+                (wi, self.bsdf(normal, wo, wi) * cos_theta / pdf)
+            }
+        }
+    }
+}
 
-                // let wi = Onb::local_to_world(normal, uniform_hemisphere());
-                // let cos_theta = Vec3f::dot(normal, wi);
-                // let pdf = 1.0 / (2.0 * PI);
+#[cfg(test)]
+mod test {
+    use crate::material::*;
 
-                // let (sample, pdf) = ggx_hemisphere(-wo,normal, self.roughness);
-                // let wi = Onb::local_to_world(normal, sample);
-                // let cos_theta = Vec3f::dot(normal, wi);
+    #[test]
+    fn test_physical_energy() {
+        // estimate of the directional albedo must not exceed one
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::normalize(Vec3f::new(1.0, 0.0, 1.0));
 
-                let wi = Onb::local_to_world(normal, cosine_weighted_hemisphere());
-                let cos_theta = Vec3f::dot(normal, wi);
-                let pdf = cos_theta / PI;
+        for (metallic, roughness) in [(0.0, 0.5), (1.0, 0.5), (0.0, 0.05), (1.0, 0.05)] {
+            let material = Material {
+                metallic,
+                roughness,
+                material: MaterialType::Physical,
+                ..Default::default()
+            };
 
-                // Halfway vector between wo and wi
-                let halfway = Vec3::normalize(wo + wi);
+            let n = 20000;
+            let mut sum = Vec3f::from(0.0);
+            for _ in 0..n {
+                let (wi, pdf) = material.sample_f(normal, wo);
+                if pdf > 0.0 {
+                    assert!((pdf - material.pdf(normal, wo, wi)).abs() <= 1e-9 * pdf);
+                    let cos_theta = Vec3f::dot(normal, wi);
+                    sum += material.bsdf(normal, wo, wi) * cos_theta / pdf;
+                }
+            }
+            let albedo = sum / n as f64;
 
-                let f0 = Vec3::lerp(Vec3::from(0.04), self.albedo, self.metallic);
+            assert!(albedo.x > 0.0 && albedo.x < 1.0, "{:?}", albedo);
+        }
+    }
+
+    #[test]
+    fn test_physical_sampling() {
+        // a glossy metal samples near the mirror direction with bounded weights
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::normalize(Vec3f::new(1.0, 0.0, 1.0));
+        let material = Material {
+            albedo: Vec3f::from(0.9),
+            metallic: 1.0,
+            roughness: 0.05,
+            material: MaterialType::Physical,
+            ..Default::default()
+        };
 
-                // Schlick's Fresnel Approximation
-                let fresnel = fresnel_schlick(f0, cos_theta);
+        let mirror = reflect(-wo, normal);
+        let n = 1000;
+        let mut near = 0;
+        for _ in 0..n {
+            let (wi, weight) = material.sample(normal, wo);
+            assert!(weight.x.is_finite() && weight.x < 2.0, "{:?}", weight);
+            if Vec3f::dot(wi, mirror) > 0.95 {
+                near += 1;
+            }
+        }
+        assert!(near > 9 * n / 10, "{}", near);
+    }
 
-                // Normal Distribution Function
-                let distribution = distribution_ggx(normal, halfway, self.roughness);
+    #[test]
+    fn test_oren_nayar() {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::new(0.8, 0.0, 0.3).normalize();
+        let back = wo;
+        let forward = Vec3f::new(-wo.x, 0.0, wo.z);
 
-                // Geometry Function
-                let geometry = geometry_smith(normal, wo, wi, self.roughness);
+        // zero roughness is Lambert
+        let smooth = Material {
+            roughness: 0.0,
+            material: MaterialType::OrenNayar,
+            ..Default::default()
+        };
+        assert_eq!(smooth.bsdf(normal, wo, forward), smooth.albedo / PI);
 
-                // DFG / (4 dot(wo, n) dot(wi, n))
-                let bsdf =
-                    (fresnel * distribution * geometry) / (4.0 * cos_theta * Vec3::dot(normal, wo));
+        // rough surfaces scatter back toward grazing light
+        let rough = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 1.0,
+            ..smooth
+        };
+        assert!(rough.bsdf(normal, wo, back).x > rough.bsdf(normal, wo, forward).x);
 
-                (wi, bsdf * cos_theta / pdf)
+        for wo in [normal, wo] {
+            let n = 20000;
+            let mut sum = Vec3f::from(0.0);
+            for _ in 0..n {
+                sum += rough.sample(normal, wo).1;
             }
+            let albedo = sum / n as f64;
+            assert!(albedo.x > 0.7 && albedo.x < 1.0, "{:?}", albedo);
         }
     }
-}
 
-#[cfg(test)]
-mod test {}
+    #[test]
+    fn test_rough_dielectric_energy() {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let material = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.3,
+            material: MaterialType::Transparent,
+            ..Default::default()
+        };
+        assert!(!material.is_specular());
+
+        // from outside and from inside the medium, including total internal reflection
+        for wo in [
+            Vec3f::new(0.3, 0.0, 1.0).normalize(),
+            Vec3f::new(0.3, 0.0, -1.0).normalize(),
+            Vec3f::new(1.0, 0.0, -0.5).normalize(),
+        ] {
+            let n = 20000;
+            let mut sum = Vec3f::from(0.0);
+            for _ in 0..n {
+                let (wi, pdf) = material.sample_f(normal, wo);
+                if pdf > 0.0 {
+                    // transmitted radiance is scaled by the squared ior ratio, undo it for flux
+                    let eta = dielectric_side(normal, wo, material.ior).1;
+                    let transmitted = Vec3f::dot(normal, wi) * Vec3f::dot(normal, wo) < 0.0;
+                    let scale = if transmitted { eta * eta } else { 1.0 };
+
+                    let cos_theta = Vec3f::dot(normal, wi).abs();
+                    sum += material.bsdf(normal, wo, wi) * cos_theta * scale / pdf;
+                }
+            }
+            let albedo = sum / n as f64;
+
+            // single scattering loses some energy, most at grazing angles
+            assert!(albedo.x > 0.75 && albedo.x < 1.02, "{:?} {:?}", wo, albedo);
+        }
+    }
+
+    #[test]
+    fn test_conductor() {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::new(0.5, 0.0, 1.0).normalize();
+        let gold = Conductor::preset("gold").unwrap();
+
+        let smooth = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.0,
+            material: MaterialType::Conductor(gold),
+            ..Default::default()
+        };
+        assert!(smooth.is_specular());
+        let (wi, weight) = smooth.sample(normal, wo);
+        assert!((wi - Vec3f::new(-wo.x, 0.0, wo.z)).length() < 1e-12);
+        assert_eq!(weight, gold.fresnel(wo.z));
+
+        // rough gold reflects less than one and keeps its tint
+        let rough = Material {
+            roughness: 0.2,
+            ..smooth
+        };
+        let n = 20000;
+        let mut sum = Vec3f::from(0.0);
+        for _ in 0..n {
+            let (wi, pdf) = rough.sample_f(normal, wo);
+            if pdf > 0.0 {
+                sum += rough.bsdf(normal, wo, wi) * Vec3f::dot(normal, wi) / pdf;
+            }
+        }
+        let albedo = sum / n as f64;
+        assert!(albedo.x < 1.0 && albedo.x > 0.85, "{:?}", albedo);
+        assert!(albedo.x > albedo.z);
+    }
+
+    #[test]
+    fn test_anisotropic_conductor() {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::new(0.3, 0.1, 1.0).normalize();
+        let aluminium = Conductor::preset("al").unwrap();
+        let isotropic = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.3,
+            tangent: Vec3f::new(1.0, 0.0, 0.0),
+            material: MaterialType::Conductor(aluminium),
+            ..Default::default()
+        };
+
+        // equal roughness matches the isotropic model
+        let round = Material {
+            anisotropy: Some(Anisotropy {
+                alpha_x: 0.3,
+                alpha_y: 0.3,
+                tangent: None,
+            }),
+            ..isotropic.clone()
+        };
+        let wi = Vec3f::new(-0.5, 0.4, 1.0).normalize();
+        let (a, b) = (isotropic.bsdf(normal, wo, wi), round.bsdf(normal, wo, wi));
+        assert!((a - b).length() < 1e-12 * a.length());
+
+        // brushed along x, the highlight spreads across the brushing
+        let brushed = Material {
+            anisotropy: Some(Anisotropy {
+                alpha_x: 0.05,
+                alpha_y: 0.4,
+                tangent: None,
+            }),
+            ..isotropic
+        };
+        let up = Vec3f::new(0.0, 0.0, 1.0);
+        let along = Vec3f::new(0.3, 0.0, 1.0).normalize();
+        let across = Vec3f::new(0.0, 0.3, 1.0).normalize();
+        assert!(brushed.bsdf(normal, up, across).x > 10.0 * brushed.bsdf(normal, up, along).x);
+
+        let n = 20000;
+        let mut sum = Vec3f::from(0.0);
+        for _ in 0..n {
+            let (wi, pdf) = brushed.sample_f(normal, wo);
+            if pdf > 0.0 {
+                assert!((pdf - brushed.pdf(normal, wo, wi)).abs() <= 1e-9 * pdf);
+                sum += brushed.bsdf(normal, wo, wi) * Vec3f::dot(normal, wi) / pdf;
+            }
+        }
+        let albedo = sum / n as f64;
+        assert!(albedo.x > 0.8 && albedo.x < 1.0, "{:?}", albedo);
+    }
+
+    #[test]
+    fn test_anisotropy_tangent() {
+        // the world space hint replaces the uv tangent
+        let mut material = Material {
+            material: MaterialType::Conductor(Conductor::preset("al").unwrap()),
+            ..Default::default()
+        };
+        let hit = Hit {
+            normal: Vec3f::new(0.0, 0.0, 1.0),
+            tangent: Vec3f::new(1.0, 0.0, 0.0),
+            ..Default::default()
+        };
+        assert_eq!(material.at(&hit).tangent, Vec3f::new(1.0, 0.0, 0.0));
+
+        material.anisotropy = Some(Anisotropy {
+            alpha_x: 0.1,
+            alpha_y: 0.2,
+            tangent: Some(Vec3f::new(0.0, 1.0, 0.0)),
+        });
+        assert_eq!(material.at(&hit).tangent, Vec3f::new(0.0, 1.0, 0.0));
+        assert!(!material.is_specular());
+    }
+
+    #[test]
+    fn test_rough_dielectric_reciprocity() {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let material = Material {
+            material: MaterialType::Transparent,
+            ..Default::default()
+        };
+        let outside = Vec3f::new(0.4, 0.1, 1.0).normalize();
+        let inside = Vec3f::new(-0.2, 0.3, -1.0).normalize();
+        let reflected = Vec3f::new(-0.5, 0.2, 1.0).normalize();
+
+        // f(o, i) / eta_o^2 = f(i, o) / eta_i^2
+        let f = material.bsdf(normal, outside, inside).x;
+        let b = material.bsdf(normal, inside, outside).x;
+        assert!(f > 0.0);
+        assert!((f - b / (material.ior * material.ior)).abs() < 1e-9 * f);
+
+        let f = material.bsdf(normal, outside, reflected).x;
+        let b = material.bsdf(normal, reflected, outside).x;
+        assert!(f > 0.0 && (f - b).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_rough_dielectric_invalid_ior() {
+        // a non-positive ior is taken as index matched, light passes straight through
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::new(0.3, 0.0, 1.0).normalize();
+        assert_eq!(dielectric_side(normal, -wo, 0.0), (-normal, 1.0));
+
+        let material = Material {
+            roughness: 0.2,
+            ior: 0.0,
+            material: MaterialType::Transparent,
+            ..Default::default()
+        };
+        assert!(material.is_specular());
+        for _ in 0..100 {
+            let (wi, pdf) = material.sample_f(normal, wo);
+            assert!(pdf.is_finite());
+            assert!((wi + wo).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_absorption_coefficient() {
+        let absorption = absorption_coefficient(Vec3f::new(1.0, 0.5, 0.25), 2.0);
+        assert_eq!(absorption.x, 0.0);
+        assert!((f64::exp(-absorption.y * 2.0) - 0.5).abs() < 1e-12);
+        assert!((f64::exp(-absorption.z * 4.0) - 0.0625).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_at_wavelength() {
+        let mut material = Material {
+            albedo: Vec3f::new(1.0, 0.0, 0.0),
+            material: MaterialType::Transparent,
+            ..Default::default()
+        };
+
+        let red = material.at_wavelength(650.0);
+        assert!(red.albedo.x > 0.9 && red.albedo.x == red.albedo.z);
+        assert!(material.at_wavelength(450.0).albedo.x < 0.1);
+        assert_eq!(red.ior, material.ior);
+
+        material.dispersion = Some(Dispersion::BK7);
+        assert!(material.at_wavelength(450.0).ior > material.at_wavelength(650.0).ior);
+    }
+
+    #[test]
+    fn test_textures() {
+        use crate::texture::*;
+        use std::sync::Arc;
+
+        // left half dark, right half bright
+        let pixels = vec![Vec3f::from(0.25), Vec3f::from(1.0)];
+        let image = Arc::new(Image::new(2, 1, pixels, false));
+        let texture = Texture::Image(ImageTexture::new(image).with_filter(Filter::Nearest));
+
+        let mut material = Material {
+            material: MaterialType::Lambert,
+            ..Default::default()
+        };
+        material.textures.albedo = Some(texture.clone());
+        material.textures.roughness = Some(texture.clone());
+        material.emittance = 2.0;
+
+        let mut hit = Hit {
+            uv: Vec2f::new(0.25, 0.5),
+            ..Default::default()
+        };
+
+        let shading = material.at(&hit);
+        assert_eq!(shading.albedo, Vec3f::from(0.2));
+        assert_eq!(shading.roughness, 0.125);
+        assert_eq!(shading.metallic, 0.0);
+        assert!(shading.textures.albedo.is_none());
+        assert_eq!(material.emission(&hit), Vec3f::from(0.4));
+
+        material.textures.emission = Some(texture);
+        hit.uv = Vec2f::new(0.75, 0.5);
+        assert_eq!(material.emission(&hit), Vec3f::from(2.0));
+    }
+
+    #[test]
+    fn test_normal_map() {
+        use crate::texture::*;
+        use std::sync::Arc;
+
+        let flat = Vec3f::new(0.5, 0.5, 1.0);
+        let tilted = Vec3f::new(1.0, 0.5, 0.5);
+        let image = Arc::new(Image::new(2, 1, vec![flat, tilted], false));
+        let texture = Texture::Image(ImageTexture::new(image).with_filter(Filter::Nearest));
+
+        let mut material = Material {
+            material: MaterialType::Lambert,
+            ..Default::default()
+        };
+        material.textures.normal = Some(texture);
+
+        let mut hit = Hit {
+            normal: Vec3f::new(0.0, 0.0, 1.0),
+            tangent: Vec3f::new(2.0, 0.0, 0.0),
+            bitangent: Vec3f::new(0.0, 2.0, 0.0),
+            uv: Vec2f::new(0.25, 0.5),
+            ..Default::default()
+        };
+        assert_eq!(material.shading_normal(&hit), hit.normal);
+
+        hit.uv = Vec2f::new(0.75, 0.5);
+        assert_eq!(material.shading_normal(&hit), Vec3f::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_bump_map() {
+        use crate::procedural::*;
+        use crate::texture::*;
+
+        // height grows linearly with u
+        let mut gradient = Procedural::new(Pattern::Gradient);
+        gradient.mapping = Mapping::Uv;
+
+        let mut material = Material {
+            material: MaterialType::Lambert,
+            ..Default::default()
+        };
+        material.textures.bump = Some(Texture::Procedural(gradient));
+        material.textures.bump_scale = 0.5;
+
+        let hit = Hit {
+            normal: Vec3f::new(0.0, 0.0, 1.0),
+            tangent: Vec3f::new(1.0, 0.0, 0.0),
+            bitangent: Vec3f::new(0.0, 1.0, 0.0),
+            uv: Vec2f::new(0.5, 0.5),
+            ..Default::default()
+        };
+
+        // slope of 0.5 along x tilts the normal toward -x
+        let normal = material.shading_normal(&hit);
+        let expected = Vec3f::new(-0.5, 0.0, 1.0).normalize();
+        assert!((normal - expected).length() < 1e-6, "{:?}", normal);
+    }
+
+    #[test]
+    fn test_physical_below_surface() {
+        let material = Material {
+            material: MaterialType::Physical,
+            ..Default::default()
+        };
+        let normal = Vec3f::new(0.0, 1.0, 0.0);
+        let f = material.bsdf(normal, normal, Vec3f::new(0.0, -1.0, 0.0));
+        assert_eq!(f, Vec3f::from(0.0));
+    }
+
+    #[test]
+    fn test_subsurface_boundary() {
+        // the surface refracts and reflects uncolored, the albedo colors the medium inside
+        let mut material = Material {
+            material: MaterialType::Subsurface(Subsurface::default()),
+            ..Default::default()
+        };
+        material.albedo = Vec3f::new(0.9, 0.5, 0.1);
+        material.roughness = 0.0;
+        assert!(material.is_specular());
+
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        for _ in 0..20 {
+            let (_, weight) = material.sample(normal, normal);
+            assert!(weight.x > 0.0 && weight.x == weight.y && weight.y == weight.z);
+        }
+
+        let interior = material.interior();
+        let albedo = interior.scattering / interior.extinction();
+        assert!(albedo.x > albedo.y && albedo.y > albedo.z);
+    }
+}
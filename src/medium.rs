@@ -0,0 +1,344 @@
+use crate::common::*;
+use crate::grid::Grid;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::spectrum::*;
+use crate::vector::*;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Participating medium, like fog, smoke or milk, homogeneous unless it has a density grid
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Medium {
+    /// Absorption coefficient per unit distance
+    pub absorption: Vec3f,
+    /// Scattering coefficient per unit distance
+    pub scattering: Vec3f,
+    /// Henyey-Greenstein asymmetry, positive values scatter forward and negative ones back
+    pub asymmetry: f64,
+    /// Voxel grid scaling both coefficients per point, for smoke and clouds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub density: Option<Grid>,
+}
+
+/// Henyey-Greenstein phase function for the angle between the old and new direction of travel
+pub fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denom * f64::sqrt(denom))
+}
+
+fn mean(v: Vec3f) -> f64 {
+    (v.x + v.y + v.z) / 3.0
+}
+
+/// Exponentially distributed distance between collisions with a medium of density 'majorant'
+fn free_flight(majorant: f64) -> f64 {
+    -f64::ln(1.0 - rand::thread_rng().gen_range(0.0..1.0)) / majorant
+}
+
+impl Medium {
+    /// Medium that only absorbs, as Beer-Lambert absorption inside glass
+    pub fn absorbing(absorption: Vec3f) -> Self {
+        Self {
+            absorption,
+            ..Default::default()
+        }
+    }
+
+    /// Medium with the coefficients replaced by their spectra at 'lambda' nanometers
+    pub fn at_wavelength(&self, lambda: f64) -> Medium {
+        Medium {
+            absorption: Vec3f::from(rgb_to_spectrum(self.absorption, lambda)),
+            scattering: Vec3f::from(rgb_to_spectrum(self.scattering, lambda)),
+            ..self.clone()
+        }
+    }
+
+    /// Extinction coefficient, the loss to absorption and out-scattering
+    pub fn extinction(&self) -> Vec3f {
+        self.absorption + self.scattering
+    }
+
+    pub fn scatters(&self) -> bool {
+        self.scattering != Vec3f::from(0.0)
+    }
+
+    /// Fraction of light left after traveling 'distance' along 'ray' through the medium
+    pub fn transmittance(&self, ray: &Ray, distance: f64) -> Vec3f {
+        match &self.density {
+            Some(grid) => self.ratio_tracking(grid, ray, distance),
+            None => self.homogeneous_transmittance(distance),
+        }
+    }
+
+    fn homogeneous_transmittance(&self, distance: f64) -> Vec3f {
+        // zero coefficients let light through even over infinite distances
+        let channel = |sigma: f64| {
+            if sigma == 0.0 {
+                1.0
+            } else {
+                f64::exp(-sigma * distance)
+            }
+        };
+        let sigma = self.extinction();
+        Vec3f::new(channel(sigma.x), channel(sigma.y), channel(sigma.z))
+    }
+
+    /// Samples the distance to the next scattering event along 'ray' before 'max_distance'
+    /// Returns the distance, or None if the ray reaches 'max_distance', and the path weight
+    pub fn sample_distance(&self, ray: &Ray, max_distance: f64) -> (Option<f64>, Vec3f) {
+        if !self.scatters() {
+            return (None, self.transmittance(ray, max_distance));
+        }
+
+        match &self.density {
+            Some(grid) => self.delta_tracking(grid, ray, max_distance),
+            None => self.sample_homogeneous(max_distance),
+        }
+    }
+
+    // single scattering albedo and transmittance over the density, averaged over the channels
+    // the distance was sampled by
+    fn sample_homogeneous(&self, max_distance: f64) -> (Option<f64>, Vec3f) {
+        let sigma = self.extinction();
+        let channel = rand::thread_rng().gen_range(0..3);
+        let distance = free_flight(sigma[channel]);
+
+        if distance < max_distance {
+            let transmittance = self.homogeneous_transmittance(distance);
+            let pdf = mean(sigma * transmittance);
+            (Some(distance), transmittance * self.scattering / pdf)
+        } else {
+            let transmittance = self.homogeneous_transmittance(max_distance);
+            let pdf = mean(transmittance);
+            if pdf == 0.0 {
+                return (None, Vec3f::from(0.0));
+            }
+            (None, transmittance / pdf)
+        }
+    }
+
+    /// Extinction bounding the grid everywhere, and the part of the ray inside the grid
+    fn majorant(&self, grid: &Grid, ray: &Ray, max_distance: f64) -> Option<(f64, f64, f64)> {
+        let sigma = self.extinction();
+        let majorant = grid.max_density() * f64::max(sigma.x, f64::max(sigma.y, sigma.z));
+        if majorant <= 0.0 || !majorant.is_finite() {
+            return None;
+        }
+
+        let (start, end) = grid.clip(ray, max_distance)?;
+        Some((majorant, start, end))
+    }
+
+    // Novák et al. 2014, "Residual Ratio Tracking for Estimating Attenuation in Participating Media"
+    /// Tentative collisions against the majorant, each keeping the fraction of null collisions
+    fn ratio_tracking(&self, grid: &Grid, ray: &Ray, distance: f64) -> Vec3f {
+        let (majorant, mut t, end) = match self.majorant(grid, ray, distance) {
+            Some(tracking) => tracking,
+            None => return Vec3f::from(1.0),
+        };
+
+        let mut transmittance = Vec3f::from(1.0);
+        loop {
+            t += free_flight(majorant);
+            if t >= end {
+                return transmittance;
+            }
+
+            let density = grid.density(ray.point_at(t));
+            transmittance *= Vec3f::from(1.0) - self.extinction() * (density / majorant);
+        }
+    }
+
+    // Kutz et al. 2017, "Spectral and Decomposition Tracking for Rendering Heterogeneous Volumes"
+    /// Tentative collisions against the majorant are real scattering or null collisions, chosen
+    /// by their share of the path weight, so colored coefficients need no tracking per channel
+    fn delta_tracking(&self, grid: &Grid, ray: &Ray, max_distance: f64) -> (Option<f64>, Vec3f) {
+        let (majorant, mut t, end) = match self.majorant(grid, ray, max_distance) {
+            Some(tracking) => tracking,
+            None => return (None, Vec3f::from(1.0)),
+        };
+
+        let mut weight = Vec3f::from(1.0);
+        let mut rng = rand::thread_rng();
+        loop {
+            t += free_flight(majorant);
+            if t >= end {
+                return (None, weight);
+            }
+
+            let density = grid.density(ray.point_at(t));
+            let scattering = self.scattering * density;
+            let null = Vec3f::from(majorant) - self.extinction() * density;
+
+            // absorption takes the remaining probability and ends the path without emission
+            let total = majorant * mean(weight);
+            if total <= 0.0 {
+                return (None, Vec3f::from(0.0));
+            }
+            let p_scatter = mean(weight * scattering) / total;
+            let p_null = mean(weight * null) / total;
+            let r = rng.gen_range(0.0..1.0);
+
+            if r < p_scatter {
+                return (Some(t), weight * scattering / (majorant * p_scatter));
+            } else if r < p_scatter + p_null {
+                weight = weight * null / (majorant * p_null);
+            } else {
+                return (None, Vec3f::from(0.0));
+            }
+        }
+    }
+
+    /// Phase function for light scattered from 'wi' towards 'wo', both pointing away
+    pub fn phase(&self, wo: Vec3f, wi: Vec3f) -> f64 {
+        henyey_greenstein(Vec3f::dot(-wo, wi), self.asymmetry)
+    }
+
+    /// Samples a new direction proportional to the phase function, which is also its density
+    pub fn sample_phase(&self, wo: Vec3f) -> Vec3f {
+        let g = self.asymmetry;
+        let mut rng = rand::thread_rng();
+        let (r1, r2) = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * r1
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * r1);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let theta = f64::acos(cos_theta.clamp(-1.0, 1.0));
+
+        Onb::new(-wo).transform(from_spherical(theta, 2.0 * PI * r2))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::medium::*;
+
+    #[test]
+    fn test_henyey_greenstein() {
+        // the phase function integrates to one and its mean cosine is the asymmetry
+        for g in [-0.7, 0.0, 0.3, 0.9] {
+            let n = 20000;
+            let (mut integral, mut mean_cos) = (0.0, 0.0);
+            for i in 0..n {
+                let cos_theta = -1.0 + 2.0 * (i as f64 + 0.5) / n as f64;
+                let p = henyey_greenstein(cos_theta, g) * 2.0 * PI * 2.0 / n as f64;
+                integral += p;
+                mean_cos += p * cos_theta;
+            }
+            assert!((integral - 1.0).abs() < 1e-3, "{} {}", g, integral);
+            assert!((mean_cos - g).abs() < 1e-3, "{} {}", g, mean_cos);
+        }
+    }
+
+    #[test]
+    fn test_sample_phase() {
+        // sampled directions scatter forward by the asymmetry on average
+        let medium = Medium {
+            asymmetry: 0.6,
+            ..Default::default()
+        };
+        let wo = Vec3f::new(0.0, 0.6, 0.8);
+
+        let n = 20000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let wi = medium.sample_phase(wo);
+            assert!((wi.length() - 1.0).abs() < 1e-9);
+            sum += Vec3f::dot(-wo, wi);
+        }
+        assert!((sum / n as f64 - 0.6).abs() < 0.02);
+    }
+
+    /// Mean weights of the scattering and surface events before 'max_distance'
+    fn events(medium: &Medium, ray: &Ray, max_distance: f64) -> (Vec3f, Vec3f) {
+        let n = 100000;
+        let (mut surface, mut scattered) = (Vec3f::from(0.0), Vec3f::from(0.0));
+        for _ in 0..n {
+            match medium.sample_distance(ray, max_distance) {
+                (Some(_), weight) => scattered += weight,
+                (None, weight) => surface += weight,
+            }
+        }
+        (surface / n as f64, scattered / n as f64)
+    }
+
+    #[test]
+    fn test_sample_distance() {
+        let medium = Medium {
+            absorption: Vec3f::new(0.1, 0.2, 0.3),
+            scattering: Vec3f::new(0.5, 1.0, 2.0),
+            ..Default::default()
+        };
+        let ray = Ray::new(Vec3f::from(0.0), Vec3f::new(0.0, 0.0, 1.0));
+
+        // weights of the surface and scattering events add up to the expected light per event
+        let max_distance = 0.8;
+        let (surface, scattered) = events(&medium, &ray, max_distance);
+
+        let transmittance = medium.transmittance(&ray, max_distance);
+        let albedo = medium.scattering / medium.extinction();
+        let expected = albedo * (Vec3f::from(1.0) - transmittance);
+        assert!((surface - transmittance).length() < 0.01, "{:?}", surface);
+        assert!((scattered - expected).length() < 0.01, "{:?}", scattered);
+
+        // vacuum is transparent to the infinite distance of a missed ray
+        let (event, weight) = Medium::default().sample_distance(&ray, f64::INFINITY);
+        assert!(event.is_none());
+        assert_eq!(weight, Vec3f::from(1.0));
+    }
+
+    #[test]
+    fn test_tracking() {
+        // a grid of uniform density matches the homogeneous medium with scaled coefficients
+        let grid = Grid::new([2, 2, 2], vec![0.5; 8], Vec3f::from(-1.0), Vec3f::from(1.0));
+        let absorption = Vec3f::new(0.2, 0.4, 0.6);
+        let scattering = Vec3f::new(2.0, 1.0, 0.5);
+        let heterogeneous = Medium {
+            absorption,
+            scattering,
+            density: Some(grid),
+            ..Default::default()
+        };
+        let homogeneous = Medium {
+            absorption: absorption * 0.5,
+            scattering: scattering * 0.5,
+            ..Default::default()
+        };
+
+        // the ray enters the grid after one unit and leaves it after three
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, -2.0), Vec3f::new(0.0, 0.0, 1.0));
+        let expected = homogeneous.transmittance(&ray, 2.0);
+
+        let n = 20000;
+        let mut transmittance = Vec3f::from(0.0);
+        for _ in 0..n {
+            transmittance += heterogeneous.transmittance(&ray, 10.0);
+        }
+        let transmittance = transmittance / n as f64;
+        assert!(
+            (transmittance - expected).length() < 0.01,
+            "{:?}",
+            transmittance
+        );
+
+        let (surface, scattered) = events(&heterogeneous, &ray, 10.0);
+        let albedo = scattering / (absorption + scattering);
+        assert!((surface - expected).length() < 0.01, "{:?}", surface);
+        let expected = albedo * (Vec3f::from(1.0) - expected);
+        assert!((scattered - expected).length() < 0.02, "{:?}", scattered);
+
+        // rays missing the grid pass unchanged
+        let miss = Ray::new(Vec3f::new(0.0, 2.0, -2.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(
+            heterogeneous.sample_distance(&miss, 10.0),
+            (None, Vec3f::from(1.0))
+        );
+    }
+}
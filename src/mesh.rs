@@ -0,0 +1,754 @@
+use crate::bvh::*;
+use crate::geometry::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Triangle(pub Vec3f, pub Vec3f, pub Vec3f);
+
+impl Bounded for Triangle {
+    fn bounds(&self) -> Aabb {
+        Aabb::from_points(&[self.0, self.1, self.2])
+    }
+}
+
+/// Triangle of a mesh, as indices into the vertex attributes
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Face {
+    pub vertices: [usize; 3],
+    pub uvs: Option<[usize; 3]>,
+    pub normals: Option<[usize; 3]>,
+    /// Index into the group names of the mesh
+    pub group: usize,
+    /// Index into the materials of the mesh
+    pub material: Option<usize>,
+}
+
+impl Face {
+    pub fn new(vertices: [usize; 3]) -> Self {
+        Self {
+            vertices,
+            ..Default::default()
+        }
+    }
+}
+
+/// Triangle mesh
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Mesh {
+    pub(crate) vertices: Vec<Vec3f>,
+    pub(crate) uvs: Vec<Vec2f>,
+    pub(crate) normals: Vec<Vec3f>,
+    /// Per-vertex colors, empty if the file has none
+    pub(crate) colors: Vec<Vec3f>,
+    pub(crate) faces: Vec<Face>,
+    pub(crate) groups: Vec<String>,
+    pub(crate) materials: Vec<Material>,
+    pub(crate) material_names: Vec<String>,
+    /// Problems that did not prevent loading, like a missing material library
+    #[serde(skip)]
+    pub(crate) warnings: Vec<String>,
+    #[serde(skip)]
+    pub(crate) triangles: Vec<Triangle>,
+    #[serde(skip)]
+    bvh: Bvh,
+}
+
+/// Maximum angle in degrees between faces that share a smoothed vertex normal
+const CREASE_ANGLE: f64 = 60.0;
+
+thread_local! {
+    /// Meshes loaded within 'Mesh::with_cache', keyed by canonical file path and group
+    static MESH_CACHE: RefCell<Option<HashMap<String, Arc<Mesh>>>> = const { RefCell::new(None) };
+}
+
+/// Drops the mesh cache when the outermost 'Mesh::with_cache' returns or unwinds
+struct CacheScope;
+
+impl Drop for CacheScope {
+    fn drop(&mut self) {
+        MESH_CACHE.with(|cache| *cache.borrow_mut() = None);
+    }
+}
+
+impl Mesh {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_triangles(triangles: Vec<Triangle>) -> Self {
+        let mut mesh = Self::new();
+        for triangle in triangles {
+            let i = mesh.vertices.len();
+            mesh.vertices
+                .extend_from_slice(&[triangle.0, triangle.1, triangle.2]);
+            mesh.faces.push(Face::new([i, i + 1, i + 2]));
+        }
+        mesh.build();
+        mesh
+    }
+
+    /// Must be called once all faces are added
+    pub(crate) fn build(&mut self) {
+        self.compute_normals();
+
+        self.triangles = self
+            .faces
+            .iter()
+            .map(|face| {
+                let [a, b, c] = face.vertices;
+                Triangle(self.vertices[a], self.vertices[b], self.vertices[c])
+            })
+            .collect();
+
+        let bounds: Vec<Aabb> = self.triangles.iter().map(|t| t.bounds()).collect();
+        self.bvh = Bvh::new(&bounds);
+    }
+
+    /// Adds angle-weighted vertex normals to faces without normals
+    /// Faces meeting at an angle larger than 'CREASE_ANGLE' are not smoothed
+    fn compute_normals(&mut self) {
+        if self.faces.iter().all(|face| face.normals.is_some()) {
+            return;
+        }
+
+        let corner = |face: &Face, k: usize| self.vertices[face.vertices[k % 3]];
+
+        let face_normals: Vec<Vec3f> = self
+            .faces
+            .iter()
+            .map(|face| {
+                let n = Vec3f::cross(
+                    corner(face, 1) - corner(face, 0),
+                    corner(face, 2) - corner(face, 0),
+                );
+                if n.length() > 0.0 {
+                    n.normalize()
+                } else {
+                    n
+                }
+            })
+            .collect();
+
+        // interior angle at each corner of each face
+        let angles: Vec<[f64; 3]> = self
+            .faces
+            .iter()
+            .map(|face| {
+                [0, 1, 2].map(|k| {
+                    let a = Vec3f::normalize(corner(face, k + 1) - corner(face, k));
+                    let b = Vec3f::normalize(corner(face, k + 2) - corner(face, k));
+                    Vec3f::dot(a, b).clamp(-1.0, 1.0).acos()
+                })
+            })
+            .collect();
+
+        let mut adjacent: Vec<Vec<usize>> = vec![Vec::new(); self.vertices.len()];
+        for (i, face) in self.faces.iter().enumerate() {
+            for v in face.vertices {
+                adjacent[v].push(i);
+            }
+        }
+
+        let min_cos = CREASE_ANGLE.to_radians().cos();
+
+        for i in 0..self.faces.len() {
+            if self.faces[i].normals.is_some() {
+                continue;
+            }
+
+            let face_normal = face_normals[i];
+            let mut indices = [0; 3];
+
+            for (k, &v) in self.faces[i].vertices.iter().enumerate() {
+                let mut sum = Vec3f::from(0.0);
+
+                for &j in &adjacent[v] {
+                    if Vec3f::dot(face_normals[j], face_normal) >= min_cos {
+                        let corner = self.faces[j].vertices.iter().position(|&w| w == v);
+                        sum += face_normals[j] * angles[j][corner.unwrap_or(0)];
+                    }
+                }
+
+                let normal = if sum.length() > 0.0 {
+                    sum.normalize()
+                } else {
+                    face_normal
+                };
+
+                self.normals.push(normal);
+                indices[k] = self.normals.len() - 1;
+            }
+
+            self.faces[i].normals = Some(indices);
+        }
+    }
+
+    /// Sets shading normal, texture coordinates, tangents and material of a hit on face 'index'
+    fn interpolate(&self, index: usize, hit: &mut Hit) {
+        let face = &self.faces[index];
+        hit.material = face.material;
+
+        let (u, v) = (hit.barycentric.x, hit.barycentric.y);
+
+        if let Some([t0, t1, t2]) = face.uvs {
+            hit.uv = self.uvs[t0] * (1.0 - u - v) + self.uvs[t1] * u + self.uvs[t2] * v;
+
+            // solve the edges for the derivatives along u and v
+            let [p0, p1, p2] = face.vertices.map(|i| self.vertices[i]);
+            let (duv1, duv2) = (self.uvs[t1] - self.uvs[t0], self.uvs[t2] - self.uvs[t0]);
+            let (e1, e2) = (p1 - p0, p2 - p0);
+            let det = duv1.x * duv2.y - duv2.x * duv1.y;
+
+            if det.abs() > 1e-12 {
+                hit.tangent = (e1 * duv2.y - e2 * duv1.y) / det;
+                hit.bitangent = (e2 * duv1.x - e1 * duv2.x) / det;
+            }
+        }
+
+        if !self.colors.is_empty() {
+            let [c0, c1, c2] = face.vertices.map(|i| self.colors[i]);
+            hit.color = c0 * (1.0 - u - v) + c1 * u + c2 * v;
+        }
+
+        if let Some([n0, n1, n2]) = face.normals {
+            let normal =
+                self.normals[n0] * (1.0 - u - v) + self.normals[n1] * u + self.normals[n2] * v;
+
+            if normal.length() > 0.0 {
+                hit.normal = normal.normalize();
+
+                // keep geometric normal on the side of the shading normal
+                if Vec3f::dot(hit.normal, hit.geometric_normal) < 0.0 {
+                    hit.geometric_normal = -hit.geometric_normal;
+                }
+            }
+        }
+    }
+
+    pub fn vertices(&self) -> &[Vec3f] {
+        &self.vertices
+    }
+
+    pub fn uvs(&self) -> &[Vec2f] {
+        &self.uvs
+    }
+
+    pub fn normals(&self) -> &[Vec3f] {
+        &self.normals
+    }
+
+    pub fn colors(&self) -> &[Vec3f] {
+        &self.colors
+    }
+
+    pub fn faces(&self) -> &[Face] {
+        &self.faces
+    }
+
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Returns index of the named material ('newmtl' in MTL files)
+    pub fn material_index(&self, name: &str) -> Option<usize> {
+        self.material_names.iter().position(|m| m == name)
+    }
+
+    /// Returns the part of the mesh belonging to the named group ('o' or 'g' in OBJ files)
+    pub fn group(&self, name: &str) -> Option<Mesh> {
+        let group = self.groups.iter().position(|g| g == name)?;
+
+        let mut mesh = Self {
+            vertices: self.vertices.clone(),
+            uvs: self.uvs.clone(),
+            normals: self.normals.clone(),
+            colors: self.colors.clone(),
+            faces: self
+                .faces
+                .iter()
+                .filter(|face| face.group == group)
+                .map(|&face| Face { group: 0, ..face })
+                .collect(),
+            groups: vec![name.to_string()],
+            materials: self.materials.clone(),
+            material_names: self.material_names.clone(),
+            warnings: self.warnings.clone(),
+            ..Default::default()
+        };
+        mesh.build();
+        Some(mesh)
+    }
+
+    /// Loads OBJ, PLY or STL file depending on the extension
+    pub fn from_file(path: &str) -> io::Result<Mesh> {
+        let extension = Path::new(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("obj") => Self::from_obj(path),
+            Some("ply") => Self::from_ply(path),
+            Some("stl") => Self::from_stl(path),
+            _ => {
+                let message = format!("{:?} is not an OBJ, PLY or STL file", path);
+                Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+            }
+        }
+    }
+
+    /// Runs 'load', typically a scene load, with meshes of the same file shared within it
+    /// Files are parsed again by later loads, so edits to them are picked up
+    pub fn with_cache<T>(load: impl FnOnce() -> T) -> T {
+        let outermost = MESH_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            let outermost = cache.is_none();
+            cache.get_or_insert_with(HashMap::new);
+            outermost
+        });
+
+        let _scope = outermost.then(|| CacheScope);
+        load()
+    }
+
+    /// Returns mesh, shared with earlier loads of the file inside 'with_cache'
+    pub fn load(path: &str) -> io::Result<Arc<Mesh>> {
+        Self::load_group(path, None)
+    }
+
+    /// Like 'load', but only keeps the faces of 'group' if given
+    pub fn load_group(path: &str, group: Option<&str>) -> io::Result<Arc<Mesh>> {
+        // the same file may be spelled differently, missing files fail when parsed
+        let canonical =
+            fs::canonicalize(path).map_or(path.to_string(), |p| p.to_string_lossy().into_owned());
+        let key = match group {
+            Some(group) => format!("{}#{}", canonical, group),
+            None => canonical,
+        };
+
+        let cached = MESH_CACHE.with(|cache| cache.borrow().as_ref()?.get(&key).cloned());
+        if let Some(mesh) = cached {
+            return Ok(mesh);
+        }
+
+        let mesh = match group {
+            Some(group) => {
+                let whole = Self::load(path)?;
+                whole.group(group).ok_or_else(|| {
+                    let message = format!("{:?} has no group {:?}", path, group);
+                    io::Error::new(io::ErrorKind::InvalidData, message)
+                })?
+            }
+            None => Mesh::from_file(path)?,
+        };
+
+        let mesh = Arc::new(mesh);
+        MESH_CACHE.with(|cache| {
+            if let Some(cache) = cache.borrow_mut().as_mut() {
+                cache.insert(key, Arc::clone(&mesh));
+            }
+        });
+        Ok(mesh)
+    }
+}
+
+/// Ray prepared for watertight triangle intersection (Woop et al. 2013)
+/// The ray is translated to the origin and sheared so it points along +z
+pub struct ShearedRay {
+    origin: Vec3f,
+    axes: [usize; 3],
+    shear: Vec3f,
+}
+
+impl ShearedRay {
+    pub fn new(ray: &Ray) -> Self {
+        let d = ray.direction;
+
+        // largest component of the direction becomes z
+        let kz = if d.x.abs() > d.y.abs() && d.x.abs() > d.z.abs() {
+            0
+        } else if d.y.abs() > d.z.abs() {
+            1
+        } else {
+            2
+        };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+
+        // keep winding direction of triangles
+        if d[kz] < 0.0 {
+            (kx, ky) = (ky, kx);
+        }
+
+        Self {
+            origin: ray.origin,
+            axes: [kx, ky, kz],
+            shear: Vec3f::new(d[kx] / d[kz], d[ky] / d[kz], 1.0 / d[kz]),
+        }
+    }
+}
+
+impl Triangle {
+    /// Returns distance and barycentric coordinates of the second and third vertex
+    /// Points on edges and vertices count as hits, so rays can't slip between
+    /// neighboring triangles
+    pub fn intersect(&self, ray: &ShearedRay, min_t: f64, max_t: f64) -> Option<(f64, Vec2f)> {
+        let [kx, ky, kz] = ray.axes;
+        let shear = ray.shear;
+
+        let a = self.0 - ray.origin;
+        let b = self.1 - ray.origin;
+        let c = self.2 - ray.origin;
+
+        let ax = a[kx] - shear.x * a[kz];
+        let ay = a[ky] - shear.y * a[kz];
+        let bx = b[kx] - shear.x * b[kz];
+        let by = b[ky] - shear.y * b[kz];
+        let cx = c[kx] - shear.x * c[kz];
+        let cy = c[ky] - shear.y * c[kz];
+
+        // scaled barycentric coordinates
+        let u = cx * by - cy * bx;
+        let v = ax * cy - ay * cx;
+        let w = bx * ay - by * ax;
+
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return None;
+        }
+
+        let det = u + v + w;
+        if det == 0.0 {
+            return None;
+        }
+
+        let t = (u * a[kz] + v * b[kz] + w * c[kz]) * shear.z / det;
+
+        if min_t < t && t < max_t {
+            Some((t, Vec2f::new(v / det, w / det)))
+        } else {
+            None
+        }
+    }
+
+    pub fn normal(&self) -> Vec3f {
+        Vec3f::normalize(Vec3f::cross(self.1 - self.0, self.2 - self.0))
+    }
+
+    fn hit_sheared(&self, ray: &Ray, sheared: &ShearedRay, min_t: f64, max_t: f64) -> Option<Hit> {
+        let (t, barycentric) = self.intersect(sheared, min_t, max_t)?;
+        let mut hit = Hit::new(t, self.normal(), ray.point_at(t), 0);
+        hit.barycentric = barycentric;
+        Some(hit)
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
+        self.hit_sheared(ray, &ShearedRay::new(ray), min_t, max_t)
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit> {
+        let sheared = ShearedRay::new(ray);
+        self.bvh.hit(ray, min_t, max_t, |i, closest| {
+            self.triangles[i]
+                .hit_sheared(ray, &sheared, min_t, closest)
+                .map(|mut hit| {
+                    self.interpolate(i, &mut hit);
+                    hit
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+
+    #[test]
+    fn test_triangle_hit() {
+        let triangle = Triangle(
+            Vec3f::new(-0.5, 0.0, 5.0),
+            Vec3f::new(0.0, 1.0, 5.0),
+            Vec3f::new(0.5, 0.0, 5.0),
+        );
+        let ray = Ray::new(Vec3f::new(0.0, 0.5, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        let hit = triangle.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert_eq!(hit.t, 5.0);
+        assert_eq!(hit.point, Vec3f::new(0.0, 0.5, 5.0));
+        assert_eq!(hit.normal, Vec3f::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_mesh_hit() {
+        let s = 0.5;
+        let quad = Mesh::from_triangles(vec![
+            Triangle(
+                Vec3f::new(-s, -s, -s), // bottom left
+                Vec3f::new(-s, s, -s),  // top left
+                Vec3f::new(s, -s, -s),  // bottom right
+            ),
+            Triangle(
+                Vec3f::new(-s, s, -s), // top left
+                Vec3f::new(s, s, -s),  // top right
+                Vec3f::new(s, -s, -s), // bottom right
+            ),
+        ]);
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+
+        let possible_hit = quad.hit(&ray, 0.0, f64::INFINITY);
+        assert!(possible_hit.is_some());
+
+        let hit = possible_hit.unwrap();
+        assert_eq!(hit.point, Vec3f::new(0.0, 0.0, -s));
+        assert_eq!(hit.normal, Vec3f::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_load_obj() {
+        if let Ok(mesh) = Mesh::from_obj("scenes/cube.obj") {
+            println!("{:?}", mesh);
+            assert_eq!(mesh.triangles.len(), 6 * 2);
+        }
+    }
+
+    #[test]
+    fn test_from_file() {
+        let mesh = Mesh::from_file("scenes/cube.OBJ");
+        assert_eq!(mesh.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let mesh = Mesh::from_file("scenes/cube.3ds");
+        assert_eq!(mesh.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        assert_eq!(Mesh::from_file("scenes/cube.obj").unwrap().faces().len(), 12);
+    }
+
+    #[test]
+    fn test_hit_cube() {
+        let mesh = Mesh::from_obj("scenes/cube.obj").unwrap();
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+        if let Some(hit) = mesh.hit(&ray, 0.001, f64::INFINITY) {
+            println!("{:?}", hit);
+        }
+    }
+
+    #[test]
+    fn test_cache() {
+        // shared within one load, whatever the spelling of the path, parsed again afterwards
+        let (a, b) = Mesh::with_cache(|| {
+            let a = Mesh::load("scenes/cube.obj").unwrap();
+            let b = Mesh::with_cache(|| Mesh::load("scenes/../scenes/cube.obj").unwrap());
+            (a, b)
+        });
+        assert!(Arc::ptr_eq(&a, &b));
+
+        let c = Mesh::load("scenes/cube.obj").unwrap();
+        assert!(!Arc::ptr_eq(&a, &c));
+        assert!(!Arc::ptr_eq(&c, &Mesh::load("scenes/cube.obj").unwrap()));
+    }
+
+    #[test]
+    fn test_group() {
+        let mesh = Mesh::load_group("scenes/cube.obj", Some("Cube_Cube.001")).unwrap();
+        assert_eq!(mesh.faces().len(), 12);
+        assert!(Mesh::load_group("scenes/cube.obj", Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_triangle_barycentric() {
+        let triangle = Triangle(
+            Vec3f::new(0.0, 0.0, 5.0),
+            Vec3f::new(1.0, 0.0, 5.0),
+            Vec3f::new(0.0, 1.0, 5.0),
+        );
+        let ray = Ray::new(Vec3f::new(0.25, 0.5, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        let hit = triangle.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.barycentric.x - 0.25).abs() < 1e-9);
+        assert!((hit.barycentric.y - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_uv_interpolation() {
+        let obj = "
+            v 0 0 5
+            v 1 0 5
+            v 0 1 5
+            vt 0 0
+            vt 2 0
+            vt 0 4
+            f 1/1 2/2 3/3
+        ";
+        let mesh = Mesh::read_obj(obj.as_bytes(), std::path::Path::new("")).unwrap();
+
+        let ray = Ray::new(Vec3f::new(0.25, 0.5, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        let hit = mesh.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.uv.x - 0.5).abs() < 1e-9);
+        assert!((hit.uv.y - 2.0).abs() < 1e-9);
+
+        assert_eq!(hit.tangent, Vec3f::new(0.5, 0.0, 0.0));
+        assert_eq!(hit.bitangent, Vec3f::new(0.0, 0.25, 0.0));
+    }
+
+    #[test]
+    fn test_smooth_normals() {
+        // shallow roof with the ridge along the z axis
+        let obj = "
+            v -1 0 -1
+            v -1 0 1
+            v 0 0.1 1
+            v 0 0.1 -1
+            v 1 0 1
+            v 1 0 -1
+            f 1 2 3
+            f 1 3 4
+            f 4 3 5
+            f 4 5 6
+        ";
+        let mesh = Mesh::read_obj(obj.as_bytes(), std::path::Path::new("")).unwrap();
+
+        let ray = Ray::new(Vec3f::new(-0.5, 5.0, 0.5), Vec3f::new(0.0, -1.0, 0.0));
+        let hit = mesh.hit(&ray, 0.001, f64::INFINITY).unwrap();
+
+        let face_normal = Vec3f::normalize(Vec3f::new(-0.1, 1.0, 0.0));
+        assert!((hit.geometric_normal - face_normal).length() < 1e-9);
+
+        // halfway to the ridge, the shading normal is tilted less than the face
+        assert!(face_normal.x < hit.normal.x && hit.normal.x < 0.0);
+        assert!((hit.normal.length() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_crease() {
+        // two sides of a cube stay flat
+        let obj = "
+            v -1 -1 -1
+            v -1 1 -1
+            v 1 1 -1
+            v 1 -1 -1
+            v 1 1 1
+            v 1 -1 1
+            f 1 2 3 4
+            f 4 3 5 6
+        ";
+        let mesh = Mesh::read_obj(obj.as_bytes(), std::path::Path::new("")).unwrap();
+
+        let ray = Ray::new(Vec3f::new(0.5, 0.5, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        let hit = mesh.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.normal - Vec3f::new(0.0, 0.0, -1.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_triangle_interval() {
+        let triangle = Triangle(
+            Vec3f::new(-0.5, 0.0, 5.0),
+            Vec3f::new(0.0, 1.0, 5.0),
+            Vec3f::new(0.5, 0.0, 5.0),
+        );
+        let ray = Ray::new(Vec3f::new(0.0, 0.5, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(triangle.hit(&ray, 0.0, 4.0).is_none());
+        assert!(triangle.hit(&ray, 6.0, f64::INFINITY).is_none());
+        assert!(triangle.hit(&ray, 4.0, 6.0).is_some());
+
+        // behind the origin
+        let ray = Ray::new(Vec3f::new(0.0, 0.5, 10.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(triangle.hit(&ray, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_triangle_edge_hits() {
+        // quad split along the diagonal from (0, 0) to (1, 1)
+        let lower = Triangle(
+            Vec3f::new(0.0, 0.0, 2.0),
+            Vec3f::new(1.0, 0.0, 2.0),
+            Vec3f::new(1.0, 1.0, 2.0),
+        );
+        let upper = Triangle(
+            Vec3f::new(0.0, 0.0, 2.0),
+            Vec3f::new(1.0, 1.0, 2.0),
+            Vec3f::new(0.0, 1.0, 2.0),
+        );
+
+        for i in 1..100 {
+            let x = i as f64 / 100.0;
+            let ray = Ray::new(Vec3f::new(x, x, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+            assert!(lower.hit(&ray, 0.0, f64::INFINITY).is_some());
+            assert!(upper.hit(&ray, 0.0, f64::INFINITY).is_some());
+        }
+
+        // slanted rays through the shared edge must not slip through
+        let quad = Mesh::from_triangles(vec![lower, upper]);
+        for i in 1..100 {
+            let x = i as f64 / 100.0;
+            let origin = Vec3f::new(-0.3, 0.7, -1.0);
+            let ray = Ray::towards(origin, Vec3f::new(x, x, 2.0));
+            assert!(quad.hit(&ray, 0.0, f64::INFINITY).is_some());
+        }
+
+        // outer edge
+        let ray = Ray::new(Vec3f::new(0.5, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        let hit = lower.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert_eq!(hit.barycentric, Vec2f::new(0.5, 0.0));
+
+        let ray = Ray::new(Vec3f::new(0.5, -1e-9, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(lower.hit(&ray, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_triangle_vertex_hits() {
+        let triangle = Triangle(
+            Vec3f::new(0.0, 0.0, 2.0),
+            Vec3f::new(1.0, 0.0, 2.0),
+            Vec3f::new(0.0, 1.0, 2.0),
+        );
+
+        let corners = [
+            (Vec3f::new(0.0, 0.0, 0.0), Vec2f::new(0.0, 0.0)),
+            (Vec3f::new(1.0, 0.0, 0.0), Vec2f::new(1.0, 0.0)),
+            (Vec3f::new(0.0, 1.0, 0.0), Vec2f::new(0.0, 1.0)),
+        ];
+
+        for (origin, barycentric) in corners {
+            let ray = Ray::new(origin, Vec3f::new(0.0, 0.0, 1.0));
+            let hit = triangle.hit(&ray, 0.0, f64::INFINITY).unwrap();
+            assert_eq!(hit.t, 2.0);
+            assert_eq!(hit.barycentric, barycentric);
+        }
+
+        // fan of triangles around a shared vertex
+        let center = Vec3f::new(0.0, 0.0, 2.0);
+        let fan: Vec<Triangle> = (0..7)
+            .map(|i| {
+                let angle = |i: usize| i as f64 * 2.0 * std::f64::consts::PI / 7.0;
+                let a = Vec3f::new(angle(i).cos(), angle(i).sin(), 2.0);
+                let b = Vec3f::new(angle(i + 1).cos(), angle(i + 1).sin(), 2.0);
+                Triangle(center, a, b)
+            })
+            .collect();
+        let fan = Mesh::from_triangles(fan);
+
+        let ray = Ray::towards(Vec3f::new(0.3, -0.2, 0.0), center);
+        let hit = fan.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.point - center).length() < 1e-9);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::geometry::Hit;
+use crate::material::*;
+use crate::texture::Texture;
+use crate::vector::*;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Second material blended into the first by weight, for masks and partially metallic surfaces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mix {
+    pub material: Material,
+    /// Fraction of 'material' in the blend
+    pub weight: f64,
+    /// Scales the weight per point, like a dirt mask
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture: Option<Texture>,
+}
+
+impl Mix {
+    pub fn new(material: Material, weight: f64) -> Self {
+        Self {
+            material,
+            weight,
+            texture: None,
+        }
+    }
+
+    /// Fraction of 'material' at the hit point
+    pub fn weight(&self, hit: &Hit) -> f64 {
+        let scale = self.texture.as_ref().map_or(1.0, |t| t.value(hit));
+        (self.weight * scale).clamp(0.0, 1.0)
+    }
+
+    /// Mix with the material replaced by its spectrum at 'lambda' nanometers
+    pub fn at_wavelength(&self, lambda: f64) -> Mix {
+        Mix {
+            material: self.material.at_wavelength(lambda),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn bsdf(&self, base: &Material, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        base.bsdf(normal, wo, wi) * (1.0 - self.weight)
+            + self.material.bsdf(normal, wo, wi) * self.weight
+    }
+
+    pub(crate) fn pdf(&self, base: &Material, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        base.pdf(normal, wo, wi) * (1.0 - self.weight)
+            + self.material.pdf(normal, wo, wi) * self.weight
+    }
+
+    /// Samples one of the materials by weight, with the density of the blend
+    pub(crate) fn sample_f(&self, base: &Material, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64) {
+        let (wi, _) = if rand::thread_rng().gen_range(0.0..1.0) < self.weight {
+            self.material.sample_f(normal, wo)
+        } else {
+            base.sample_f(normal, wo)
+        };
+
+        (wi, self.pdf(base, normal, wo, wi))
+    }
+
+    pub(crate) fn sample(&self, base: &Material, normal: Vec3f, wo: Vec3f) -> (Vec3f, Vec3f) {
+        let (wi, pdf) = self.sample_f(base, normal, wo);
+        if pdf == 0.0 {
+            return (wi, Vec3f::from(0.0));
+        }
+        let cos_theta = Vec3f::dot(normal, wi).abs();
+        (wi, self.bsdf(base, normal, wo, wi) * cos_theta / pdf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mix::*;
+
+    #[test]
+    fn test_mix() {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::new(0.3, 0.0, 1.0).normalize();
+        let wi = Vec3f::new(-0.4, 0.2, 1.0).normalize();
+
+        let white = Material {
+            albedo: Vec3f::from(1.0),
+            ..Default::default()
+        };
+        let metal = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.3,
+            material: MaterialType::Physical,
+            ..Default::default()
+        };
+        let mixed = Material {
+            mix: Some(Box::new(Mix::new(metal.clone(), 0.25))),
+            ..white.clone()
+        };
+
+        let expected = white.bsdf(normal, wo, wi) * 0.75 + metal.bsdf(normal, wo, wi) * 0.25;
+        assert!((mixed.bsdf(normal, wo, wi) - expected).length() < 1e-12);
+
+        // sampled densities agree with the density evaluated for the same direction
+        for _ in 0..100 {
+            let (wi, pdf) = mixed.sample_f(normal, wo);
+            assert!((pdf - mixed.pdf(normal, wo, wi)).abs() <= 1e-9 * pdf);
+        }
+    }
+
+    #[test]
+    fn test_mix_texture() {
+        use crate::texture::*;
+        use std::sync::Arc;
+
+        // mask is off on the left half and on over the right half
+        let pixels = vec![Vec3f::from(0.0), Vec3f::from(1.0)];
+        let image = Arc::new(Image::new(2, 1, pixels, false));
+        let mask = Texture::Image(ImageTexture::new(image).with_filter(Filter::Nearest));
+
+        let dirt = Material {
+            albedo: Vec3f::from(0.1),
+            ..Default::default()
+        };
+        let mut mix = Mix::new(dirt, 0.5);
+        mix.texture = Some(mask);
+        let clean = Material {
+            mix: Some(Box::new(mix)),
+            albedo: Vec3f::from(0.9),
+            ..Default::default()
+        };
+
+        let mut hit = Hit {
+            normal: Vec3f::new(0.0, 0.0, 1.0),
+            uv: Vec2f::new(0.25, 0.5),
+            ..Default::default()
+        };
+        let (normal, wo) = (hit.normal, hit.normal);
+        let f = clean.at(&hit).bsdf(normal, wo, wo).x * std::f64::consts::PI;
+        assert!((f - 0.9).abs() < 1e-12);
+
+        hit.uv = Vec2f::new(0.75, 0.5);
+        let f = clean.at(&hit).bsdf(normal, wo, wo).x * std::f64::consts::PI;
+        assert!((f - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_mix_specular() {
+        // delta lobes cannot be blended, one of the materials is picked per hit
+        let mirror = Material {
+            albedo: Vec3f::from(1.0),
+            material: MaterialType::Mirror,
+            ..Default::default()
+        };
+        let mixed = Material {
+            mix: Some(Box::new(Mix::new(mirror, 0.5))),
+            albedo: Vec3f::from(1.0),
+            ..Default::default()
+        };
+
+        let n = 2000;
+        let mirrors = (0..n)
+            .map(|_| mixed.at(&Hit::default()))
+            .filter(|m| m.material == MaterialType::Mirror)
+            .count();
+        assert!(mirrors > n / 3 && mirrors < 2 * n / 3, "{}", mirrors);
+        assert!(mixed.at(&Hit::default()).mix.is_none());
+    }
+}
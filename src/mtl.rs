@@ -0,0 +1,209 @@
+use crate::material::*;
+use crate::vector::*;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+
+fn invalid_data(line: usize, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("MTL line {}: {}", line, message),
+    )
+}
+
+fn parse_float(tokens: &[&str]) -> Result<f64, String> {
+    let token = tokens.first().ok_or("expected a value")?;
+    token
+        .parse::<f64>()
+        .map_err(|_| format!("invalid number {:?}", token))
+}
+
+/// A single value is used for all three channels
+fn parse_color(tokens: &[&str]) -> Result<Vec3f, String> {
+    let values = tokens
+        .iter()
+        .take(3)
+        .map(|token| {
+            token
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number {:?}", token))
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+
+    match values.len() {
+        1 => Ok(Vec3f::from(values[0])),
+        3 => Ok(Vec3f::new(values[0], values[1], values[2])),
+        _ => Err("expected 1 or 3 values".to_string()),
+    }
+}
+
+/// Material statements of one 'newmtl' block
+#[derive(Debug, Default)]
+struct MtlEntry {
+    name: String,
+    kd: Option<Vec3f>,
+    ke: Option<Vec3f>,
+    ni: Option<f64>,
+    ns: Option<f64>,
+    pr: Option<f64>,
+    pm: Option<f64>,
+    dissolve: Option<f64>,
+    illum: Option<u32>,
+}
+
+impl MtlEntry {
+    fn to_material(&self) -> Material {
+        let transparent =
+            self.dissolve.is_some_and(|d| d < 1.0) || matches!(self.illum, Some(4 | 6 | 7 | 9));
+        let physical = self.pr.is_some() || self.pm.is_some();
+
+        // Blinn-Phong exponent to microfacet roughness, glass without either stays smooth
+        let roughness = match (self.pr, self.ns) {
+            (Some(pr), _) => pr,
+            (None, Some(ns)) => f64::sqrt(2.0 / (ns.max(0.0) + 2.0)),
+            (None, None) if transparent => 0.0,
+            (None, None) => 1.0,
+        };
+
+        let emission = self.ke.unwrap_or(Vec3f::from(0.0));
+
+        Material {
+            albedo: self.kd.unwrap_or(Vec3f::from(0.8)),
+            roughness,
+            ior: self.ni.unwrap_or(1.5),
+            metallic: self.pm.unwrap_or(0.0),
+            material: if transparent {
+                MaterialType::Transparent
+            } else if physical {
+                MaterialType::Physical
+            } else {
+                MaterialType::Lambert
+            },
+            ..Default::default()
+        }
+        .with_emission(emission)
+    }
+}
+
+/// Loads Wavefront MTL material library
+pub fn load_mtl(path: &str) -> io::Result<Vec<(String, Material)>> {
+    let file = File::open(path)?;
+    read_mtl(BufReader::new(file))
+}
+
+/// Maps 'Kd' to albedo, 'Ke' to the emitted radiance, 'Ni' to ior,
+/// 'Pr' (or 'Ns') to roughness and 'Pm' to metallic
+pub fn read_mtl<R: BufRead>(reader: R) -> io::Result<Vec<(String, Material)>> {
+    let mut entries: Vec<MtlEntry> = Vec::new();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let number = number + 1;
+
+        let content = line.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = content.split_whitespace().collect();
+
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let args = &tokens[1..];
+
+        if tokens[0] == "newmtl" {
+            entries.push(MtlEntry {
+                name: args.join(" "),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let entry = match entries.last_mut() {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        let result = match tokens[0] {
+            "Kd" => parse_color(args).map(|c| entry.kd = Some(c)),
+            "Ke" => parse_color(args).map(|c| entry.ke = Some(c)),
+            "Ni" => parse_float(args).map(|v| entry.ni = Some(v)),
+            "Ns" => parse_float(args).map(|v| entry.ns = Some(v)),
+            "Pr" => parse_float(args).map(|v| entry.pr = Some(v)),
+            "Pm" => parse_float(args).map(|v| entry.pm = Some(v)),
+            "d" => parse_float(args).map(|v| entry.dissolve = Some(v)),
+            "Tr" => parse_float(args).map(|v| entry.dissolve = Some(1.0 - v)),
+            "illum" => parse_float(args).map(|v| entry.illum = Some(v as u32)),
+            _ => Ok(()),
+        };
+
+        result.map_err(|e| invalid_data(number, e))?;
+    }
+
+    Ok(entries
+        .iter()
+        .map(|entry| (entry.name.clone(), entry.to_material()))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::geometry::Hit;
+    use crate::mtl::*;
+
+    #[test]
+    fn test_read_mtl() {
+        let mtl = "
+            # Blender MTL File
+            newmtl Red
+            Ns 250.0
+            Kd 0.8 0.1 0.1
+            Ke 0.0 0.0 0.0
+            Ni 1.45
+            d 1.0
+            illum 2
+
+            newmtl Gold
+            Kd 1.0 0.78 0.34
+            Pr 0.2
+            Pm 1.0
+
+            newmtl Lamp
+            Kd 1 1 1
+            Ke 2.0 4.0 3.0
+
+            newmtl Glass
+            Kd 1
+            Ni 1.5
+            d 0.1
+        ";
+        let materials = read_mtl(mtl.as_bytes()).unwrap();
+        let names: Vec<&str> = materials.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["Red", "Gold", "Lamp", "Glass"]);
+
+        let red = &materials[0].1;
+        assert_eq!(red.albedo, Vec3f::new(0.8, 0.1, 0.1));
+        assert_eq!(red.ior, 1.45);
+        assert_eq!(red.material, MaterialType::Lambert);
+        assert!(red.roughness < 0.1);
+
+        let gold = &materials[1].1;
+        assert_eq!(gold.material, MaterialType::Physical);
+        assert_eq!(gold.roughness, 0.2);
+        assert_eq!(gold.metallic, 1.0);
+
+        let lamp = &materials[2].1;
+        assert_eq!(lamp.emittance, 4.0);
+        assert_eq!(lamp.emission(&Hit::default()), Vec3f::new(2.0, 4.0, 3.0));
+
+        let glass = &materials[3].1;
+        assert_eq!(glass.material, MaterialType::Transparent);
+        assert_eq!(glass.albedo, Vec3f::from(1.0));
+        assert!(glass.is_specular());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(read_mtl("newmtl A\nKd 1 0".as_bytes()).is_err());
+        assert!(read_mtl("newmtl A\nNi glass".as_bytes()).is_err());
+    }
+}
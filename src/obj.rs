@@ -0,0 +1,459 @@
+use crate::mesh::*;
+use crate::mtl::*;
+use crate::vector::*;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Indices of one corner of an OBJ face
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Corner {
+    vertex: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+fn invalid_data(line: usize, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("OBJ line {}: {}", line, message),
+    )
+}
+
+fn parse_floats(tokens: &[&str], min: usize) -> Result<Vec<f64>, String> {
+    if tokens.len() < min {
+        return Err(format!("expected at least {} values", min));
+    }
+
+    tokens
+        .iter()
+        .map(|token| {
+            token
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number {:?}", token))
+        })
+        .collect()
+}
+
+/// Resolves 1-based index, negative indices count back from the last element
+fn parse_index(token: &str, count: usize) -> Result<usize, String> {
+    let index = token
+        .parse::<i64>()
+        .map_err(|_| format!("invalid index {:?}", token))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        Err(format!("index {} out of range", index))
+    } else {
+        Ok(resolved as usize)
+    }
+}
+
+/// Parses 'v', 'v/vt', 'v//vn' or 'v/vt/vn'
+fn parse_corner(token: &str, mesh: &Mesh) -> Result<Corner, String> {
+    let mut parts = token.split('/');
+
+    let vertex = parse_index(parts.next().unwrap_or(""), mesh.vertices.len())?;
+
+    let uv = match parts.next() {
+        Some(part) if !part.is_empty() => Some(parse_index(part, mesh.uvs.len())?),
+        _ => None,
+    };
+
+    let normal = match parts.next() {
+        Some(part) if !part.is_empty() => Some(parse_index(part, mesh.normals.len())?),
+        _ => None,
+    };
+
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex {:?}", token));
+    }
+
+    Ok(Corner { vertex, uv, normal })
+}
+
+/// Returns index of the group, adding it if necessary
+fn find_group(mesh: &mut Mesh, name: &str) -> usize {
+    match mesh.groups.iter().position(|g| g == name) {
+        Some(index) => index,
+        None => {
+            mesh.groups.push(name.to_string());
+            mesh.groups.len() - 1
+        }
+    }
+}
+
+/// Splits a polygon into triangles by clipping ears, so concave polygons stay inside
+/// their outline, returns indices into 'points'
+// Convex polygons give a fan around the first vertex, as ears are searched from there
+pub(crate) fn triangulate(points: &[Vec3f]) -> Vec<[usize; 3]> {
+    let n = points.len();
+
+    // Newell's method, robust for slightly non-planar polygons
+    let mut normal = Vec3f::from(0.0);
+    for i in 0..n {
+        normal += Vec3f::cross(points[i], points[(i + 1) % n]);
+    }
+
+    // side of 'p' relative to the edge from 'a' to 'b', positive to the left
+    let side = |a: usize, b: usize, p: usize| {
+        Vec3f::dot(
+            Vec3f::cross(points[b] - points[a], points[p] - points[b]),
+            normal,
+        )
+    };
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let corner = |k: usize| {
+            (
+                remaining[(k + m - 1) % m],
+                remaining[k],
+                remaining[(k + 1) % m],
+            )
+        };
+
+        let is_ear = |k: usize| {
+            let (a, b, c) = corner(k);
+            side(a, b, c) > 0.0
+                && remaining.iter().all(|&p| {
+                    p == a
+                        || p == b
+                        || p == c
+                        || side(a, b, p) < 0.0
+                        || side(b, c, p) < 0.0
+                        || side(c, a, p) < 0.0
+                })
+        };
+
+        // degenerate polygons without ears keep cutting the fan
+        let k = (1..m).chain(0..1).find(|&k| is_ear(k)).unwrap_or(1);
+        let (a, b, c) = corner(k);
+        triangles.push([a, b, c]);
+        remaining.remove(k);
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+impl Mesh {
+    /// Loads Wavefront OBJ file
+    pub fn from_obj(path: &str) -> io::Result<Mesh> {
+        let file = File::open(path)?;
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+        Self::read_obj(BufReader::new(file), directory)
+    }
+
+    /// Polygons are triangulated by ear clipping, convex ones as a fan around their first vertex
+    /// Material libraries ('mtllib') are resolved relative to 'directory', those failing to
+    /// load are listed in the warnings of the mesh
+    pub fn read_obj<R: BufRead>(reader: R, directory: &Path) -> io::Result<Mesh> {
+        let mut mesh = Mesh::new();
+        let mut group: Option<usize> = None;
+        let mut material: Option<usize> = None;
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let number = number + 1;
+
+            // strip comments
+            let content = line.split('#').next().unwrap_or("");
+            let tokens: Vec<&str> = content.split_whitespace().collect();
+
+            if tokens.is_empty() {
+                continue;
+            }
+
+            let args = &tokens[1..];
+
+            match tokens[0] {
+                "v" => {
+                    // 'w' and vertex colors are ignored
+                    let v = parse_floats(args, 3).map_err(|e| invalid_data(number, e))?;
+                    mesh.vertices.push(Vec3f::new(v[0], v[1], v[2]));
+                }
+                "vt" => {
+                    let vt = parse_floats(args, 1).map_err(|e| invalid_data(number, e))?;
+                    let v = vt.get(1).copied().unwrap_or(0.0);
+                    mesh.uvs.push(Vec2f::new(vt[0], v));
+                }
+                "vn" => {
+                    let n = parse_floats(args, 3).map_err(|e| invalid_data(number, e))?;
+                    mesh.normals.push(Vec3f::new(n[0], n[1], n[2]));
+                }
+                "o" | "g" => {
+                    let name = if args.is_empty() {
+                        "default".to_string()
+                    } else {
+                        args.join(" ")
+                    };
+                    group = Some(find_group(&mut mesh, &name));
+                }
+                "mtllib" => {
+                    for name in args {
+                        let path = directory.join(name);
+
+                        // a missing library should not prevent loading the geometry
+                        match load_mtl(&path.to_string_lossy()) {
+                            Ok(materials) => {
+                                for (name, m) in materials {
+                                    mesh.material_names.push(name);
+                                    mesh.materials.push(m);
+                                }
+                            }
+                            Err(e) => {
+                                let warning = format!("failed to load {:?}: {}", path, e);
+                                mesh.warnings.push(warning);
+                            }
+                        }
+                    }
+                }
+                "usemtl" => {
+                    // unknown names fall back to the object material
+                    material = mesh.material_index(&args.join(" "));
+                }
+                "f" => {
+                    if args.len() < 3 {
+                        let message = format!("face needs 3 vertices, got {}", args.len());
+                        return Err(invalid_data(number, message));
+                    }
+
+                    let corners = args
+                        .iter()
+                        .map(|token| parse_corner(token, &mesh))
+                        .collect::<Result<Vec<Corner>, String>>()
+                        .map_err(|e| invalid_data(number, e))?;
+
+                    let group = *group.get_or_insert_with(|| find_group(&mut mesh, "default"));
+
+                    let points: Vec<Vec3f> =
+                        corners.iter().map(|c| mesh.vertices[c.vertex]).collect();
+
+                    for [i, j, k] in triangulate(&points) {
+                        let triangle = [corners[i], corners[j], corners[k]];
+
+                        let uvs = triangle.iter().map(|c| c.uv).collect::<Option<Vec<_>>>();
+                        let normals = triangle
+                            .iter()
+                            .map(|c| c.normal)
+                            .collect::<Option<Vec<_>>>();
+
+                        mesh.faces.push(Face {
+                            vertices: triangle.map(|c| c.vertex),
+                            uvs: uvs.map(|i| [i[0], i[1], i[2]]),
+                            normals: normals.map(|i| [i[0], i[1], i[2]]),
+                            group,
+                            material,
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        mesh.build();
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::obj::*;
+
+    fn parse(obj: &str) -> io::Result<Mesh> {
+        Mesh::read_obj(obj.as_bytes(), Path::new(""))
+    }
+
+    #[test]
+    fn test_face_formats() {
+        let obj = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0 1.0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vn 0 0 1
+            f 1 2 3
+            f 1/1 2/2 3/3
+            f 1//1 2//1 3//1
+            f 1/1/1 2/2/1 3/3/1 # comment
+        ";
+        let mesh = parse(obj).unwrap();
+        let faces = mesh.faces();
+        assert_eq!(faces.len(), 4);
+
+        assert_eq!(faces[0].vertices, [0, 1, 2]);
+        assert_eq!(faces[0].uvs, None);
+
+        assert_eq!(faces[1].uvs, Some([0, 1, 2]));
+
+        // missing normals are computed and appended after those from the file
+        assert_eq!(faces[0].normals, Some([1, 2, 3]));
+        assert_eq!(faces[1].normals, Some([4, 5, 6]));
+
+        assert_eq!(faces[2].uvs, None);
+        assert_eq!(faces[2].normals, Some([0, 0, 0]));
+
+        assert_eq!(faces[3].uvs, Some([0, 1, 2]));
+        assert_eq!(faces[3].normals, Some([0, 0, 0]));
+
+        assert_eq!(mesh.uvs()[2], Vec2f::new(1.0, 1.0));
+        assert_eq!(mesh.normals()[0], Vec3f::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_polygons() {
+        let obj = "
+            v 0 0 0
+            v 1 0 0
+            v 2 1 0
+            v 1 2 0
+            v 0 1 0
+            f 1 2 3 4 5
+        ";
+        let mesh = parse(obj).unwrap();
+        let faces: Vec<[usize; 3]> = mesh.faces().iter().map(|f| f.vertices).collect();
+        assert_eq!(faces, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn test_concave_polygon() {
+        // a fan around the first vertex would cover the notch with a flipped triangle
+        let obj = "
+            v 0 2 0
+            v 0 0 0
+            v 2 0 0
+            v 2 2 0
+            v 1 1 0
+            f 1 2 3 4 5
+        ";
+        let mesh = parse(obj).unwrap();
+        assert_eq!(mesh.faces().len(), 3);
+
+        let mut area = 0.0;
+        for face in mesh.faces() {
+            let [a, b, c] = face.vertices.map(|i| mesh.vertices()[i]);
+            let z = Vec3f::cross(b - a, c - a).z / 2.0;
+            assert!(z > 0.0, "{:?}", face.vertices);
+            area += z;
+        }
+        assert!((area - 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_negative_indices() {
+        let obj = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            vn 0 0 1
+            f -3//-1 -2//-1 -1//-1
+            v 0 1 0
+            f -4 -2 -1
+        ";
+        let mesh = parse(obj).unwrap();
+        assert_eq!(mesh.faces()[0].vertices, [0, 1, 2]);
+        assert_eq!(mesh.faces()[0].normals, Some([0, 0, 0]));
+        assert_eq!(mesh.faces()[1].vertices, [0, 2, 3]);
+    }
+
+    #[test]
+    fn test_groups() {
+        let obj = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            f 1 2 3
+            o first
+            f 1 2 3
+            g second part
+            f 1 2 3
+            o first
+            f 3 2 1
+        ";
+        let mesh = parse(obj).unwrap();
+        assert_eq!(mesh.groups(), ["default", "first", "second part"]);
+
+        let groups: Vec<usize> = mesh.faces().iter().map(|f| f.group).collect();
+        assert_eq!(groups, vec![0, 1, 2, 1]);
+
+        let first = mesh.group("first").unwrap();
+        assert_eq!(first.faces().len(), 2);
+        assert!(mesh.group("third").is_none());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse("v 0 0").is_err());
+        assert!(parse("v 0 0 0\nv 1 0 0\nf 1 2").is_err());
+        assert!(parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4").is_err());
+        assert!(parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 0 1 2").is_err());
+        assert!(parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1/1 2/1 3/1").is_err());
+    }
+
+    #[test]
+    fn test_materials() {
+        let directory = std::env::temp_dir().join("raytracer_obj_materials");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("colors.mtl"),
+            "newmtl red\nKd 1 0 0\nnewmtl light\nKd 1 1 1\nKe 5 5 5\n",
+        )
+        .unwrap();
+
+        let obj = "
+            mtllib colors.mtl
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            f 1 2 3
+            usemtl light
+            f 1 2 3
+            usemtl red
+            f 1 2 3
+            usemtl None
+            f 1 2 3
+        ";
+        let mesh = Mesh::read_obj(obj.as_bytes(), &directory).unwrap();
+        assert_eq!(mesh.materials().len(), 2);
+        assert_eq!(mesh.materials()[0].albedo, Vec3f::new(1.0, 0.0, 0.0));
+        assert_eq!(mesh.materials()[1].emittance, 5.0);
+
+        let materials: Vec<Option<usize>> = mesh.faces().iter().map(|f| f.material).collect();
+        assert_eq!(materials, vec![None, Some(1), Some(0), None]);
+
+        // materials are kept when splitting the mesh into groups
+        let group = mesh.group("default").unwrap();
+        assert_eq!(group.materials().len(), 2);
+        assert_eq!(group.faces()[1].material, Some(1));
+        assert!(mesh.warnings().is_empty());
+
+        // missing libraries are reported, the faces fall back to the object material
+        let mesh = Mesh::read_obj("mtllib missing.mtl\nusemtl red".as_bytes(), &directory);
+        let warnings = mesh.unwrap().warnings().to_vec();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("missing.mtl"));
+    }
+
+    #[test]
+    fn test_cube() {
+        let mesh = Mesh::from_obj("scenes/cube.obj").unwrap();
+        assert_eq!(mesh.faces().len(), 12);
+        assert_eq!(mesh.uvs().len(), 14);
+        assert_eq!(mesh.normals().len(), 6);
+        assert_eq!(mesh.groups(), ["Cube_Cube.001"]);
+    }
+}
@@ -23,6 +23,19 @@ impl Onb {
         Self { axis: [u, v, w] }
     }
 
+    /// Returns bases around 'w' with 'u' along 'tangent' projected onto the plane of 'w'
+    /// Falls back to 'new' when the tangent is zero or parallel to 'w'
+    pub fn from_tangent(w: Vec3f, tangent: Vec3f) -> Self {
+        let u = tangent - w * Vec3f::dot(w, tangent);
+        if u.length() < 1e-9 {
+            return Self::new(w);
+        }
+
+        let u = u.normalize();
+        let v = Vec3f::cross(w, u);
+        Self { axis: [u, v, w] }
+    }
+
     /// Create coordinate system around w and transform a
     ///
     pub fn local_to_world(w: Vec3f, a: Vec3f) -> Vec3f {
@@ -34,6 +47,15 @@ impl Onb {
         self.u() * a.x + self.v() * a.y + self.w() * a.z
     }
 
+    /// Inverse of 'transform', coordinates of world vector 'a' in the bases
+    pub fn to_local(&self, a: Vec3f) -> Vec3f {
+        Vec3f::new(
+            Vec3f::dot(a, self.u()),
+            Vec3f::dot(a, self.v()),
+            Vec3f::dot(a, self.w()),
+        )
+    }
+
     pub fn u(&self) -> Vec3f {
         self.axis[0]
     }
@@ -74,4 +96,19 @@ mod test {
 
         assert_eq!(onb.transform(v), Vec3::new(0.0, 1.0, 0.0));
     }
+
+    #[test]
+    fn test_from_tangent() {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let onb = Onb::from_tangent(normal, Vec3::new(2.0, 0.0, 1.0));
+        assert_eq!(onb.u(), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(onb.v(), Vec3::new(0.0, 1.0, 0.0));
+
+        let a = Vec3::new(0.3, -0.2, 0.5);
+        assert!((onb.to_local(onb.transform(a)) - a).length() < 1e-12);
+
+        // degenerate tangent
+        let onb = Onb::from_tangent(normal, normal);
+        assert!(Vec3::dot(onb.u(), normal).abs() < 1e-12);
+    }
 }
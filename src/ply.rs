@@ -0,0 +1,554 @@
+use crate::mesh::*;
+use crate::obj::triangulate;
+use crate::vector::*;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::str::SplitWhitespace;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("PLY: {}", message))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(format!("unknown type {:?}", name)),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// Maximum value of integer types, used to normalize colors
+    fn max(self) -> f64 {
+        match self {
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    scalar: Scalar,
+    /// Type of the element count for list properties
+    list: Option<Scalar>,
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|p| p.list.is_none() && names.contains(&p.name.as_str()))
+    }
+}
+
+/// Value of one property, lists are only used for faces
+#[derive(Debug, Clone)]
+enum Value {
+    Scalar(f64),
+    List(Vec<f64>),
+}
+
+impl Value {
+    fn scalar(&self) -> f64 {
+        match self {
+            Value::Scalar(value) => *value,
+            Value::List(_) => 0.0,
+        }
+    }
+}
+
+/// Reads property values from the body of the file
+trait Source {
+    fn read(&mut self, scalar: Scalar) -> io::Result<f64>;
+
+    fn read_property(&mut self, property: &Property) -> io::Result<Value> {
+        match property.list {
+            Some(count) => {
+                let count = self.read(count)?;
+                if count < 0.0 {
+                    return Err(invalid_data(format!("negative list size {}", count)));
+                }
+                let values = (0..count as usize)
+                    .map(|_| self.read(property.scalar))
+                    .collect::<io::Result<Vec<f64>>>()?;
+                Ok(Value::List(values))
+            }
+            None => Ok(Value::Scalar(self.read(property.scalar)?)),
+        }
+    }
+}
+
+struct AsciiSource<'a> {
+    tokens: SplitWhitespace<'a>,
+}
+
+impl Source for AsciiSource<'_> {
+    fn read(&mut self, _scalar: Scalar) -> io::Result<f64> {
+        let token = self
+            .tokens
+            .next()
+            .ok_or_else(|| invalid_data("unexpected end of file".to_string()))?;
+        token
+            .parse::<f64>()
+            .map_err(|_| invalid_data(format!("invalid number {:?}", token)))
+    }
+}
+
+struct BinarySource<R: Read> {
+    reader: R,
+    big_endian: bool,
+}
+
+impl<R: Read> Source for BinarySource<R> {
+    fn read(&mut self, scalar: Scalar) -> io::Result<f64> {
+        let mut bytes = [0u8; 8];
+        let bytes = &mut bytes[..scalar.size()];
+        self.reader.read_exact(bytes)?;
+
+        if self.big_endian {
+            bytes.reverse();
+        }
+
+        let value = match scalar {
+            Scalar::I8 => bytes[0] as i8 as f64,
+            Scalar::U8 => bytes[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+        };
+        Ok(value)
+    }
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> io::Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        return Err(invalid_data("missing 'ply' magic number".to_string()));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("missing 'end_header'".to_string()));
+        }
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.as_slice() {
+            ["end_header"] => break,
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(invalid_data(format!("unknown format {:?}", name))),
+                });
+            }
+            ["element", name, count] => {
+                let count = count
+                    .parse::<usize>()
+                    .map_err(|_| invalid_data(format!("invalid element count {:?}", count)))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            ["property", "list", count, scalar, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    scalar: Scalar::parse(scalar).map_err(invalid_data)?,
+                    list: Some(Scalar::parse(count).map_err(invalid_data)?),
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("property before element".to_string()))?
+                    .properties
+                    .push(property);
+            }
+            ["property", scalar, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    scalar: Scalar::parse(scalar).map_err(invalid_data)?,
+                    list: None,
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("property before element".to_string()))?
+                    .properties
+                    .push(property);
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => (),
+            _ => {
+                return Err(invalid_data(format!(
+                    "invalid header line {:?}",
+                    line.trim()
+                )))
+            }
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("missing 'format'".to_string()))?;
+    Ok((format, elements))
+}
+
+fn read_body(source: &mut dyn Source, elements: &[Element]) -> io::Result<Mesh> {
+    let mut mesh = Mesh::new();
+    let mut has_normals = false;
+    let mut has_uvs = false;
+
+    for element in elements {
+        let position = [
+            element.find(&["x"]),
+            element.find(&["y"]),
+            element.find(&["z"]),
+        ];
+        let normal = [
+            element.find(&["nx"]),
+            element.find(&["ny"]),
+            element.find(&["nz"]),
+        ];
+        let color = [
+            element.find(&["red", "r"]),
+            element.find(&["green", "g"]),
+            element.find(&["blue", "b"]),
+        ];
+        let uv = [
+            element.find(&["u", "s", "texture_u", "texture_s"]),
+            element.find(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let indices = element.properties.iter().position(|p| {
+            p.list.is_some() && matches!(p.name.as_str(), "vertex_indices" | "vertex_index")
+        });
+
+        for _ in 0..element.count {
+            let values = element
+                .properties
+                .iter()
+                .map(|property| source.read_property(property))
+                .collect::<io::Result<Vec<Value>>>()?;
+
+            let get = |index: Option<usize>| index.map(|i| values[i].scalar());
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let [x, y, z] = position.map(get);
+                    let (x, y, z) = match (x, y, z) {
+                        (Some(x), Some(y), Some(z)) => (x, y, z),
+                        _ => return Err(invalid_data("vertex needs x, y and z".to_string())),
+                    };
+                    mesh.vertices.push(Vec3f::new(x, y, z));
+
+                    if let [Some(x), Some(y), Some(z)] = normal.map(get) {
+                        mesh.normals.push(Vec3f::new(x, y, z));
+                        has_normals = true;
+                    }
+
+                    if let [Some(r), Some(g), Some(b)] = color.map(get) {
+                        let max = element.properties[color[0].unwrap()].scalar.max();
+                        mesh.colors.push(Vec3f::new(r, g, b) / max);
+                    }
+
+                    if let [Some(u), Some(v)] = uv.map(get) {
+                        mesh.uvs.push(Vec2f::new(u, v));
+                        has_uvs = true;
+                    }
+                }
+                "face" => {
+                    let corners = match indices.map(|i| &values[i]) {
+                        Some(Value::List(corners)) => corners,
+                        _ => return Err(invalid_data("face needs vertex_indices".to_string())),
+                    };
+
+                    if corners.len() < 3 {
+                        let message = format!("face needs 3 vertices, got {}", corners.len());
+                        return Err(invalid_data(message));
+                    }
+
+                    let corners = corners
+                        .iter()
+                        .map(|&i| {
+                            if i < 0.0 || i as usize >= mesh.vertices.len() {
+                                Err(invalid_data(format!("index {} out of range", i)))
+                            } else {
+                                Ok(i as usize)
+                            }
+                        })
+                        .collect::<io::Result<Vec<usize>>>()?;
+
+                    let points: Vec<Vec3f> = corners.iter().map(|&i| mesh.vertices[i]).collect();
+
+                    for triangle in triangulate(&points) {
+                        let vertices = triangle.map(|i| corners[i]);
+                        mesh.faces.push(Face {
+                            vertices,
+                            uvs: has_uvs.then_some(vertices),
+                            normals: has_normals.then_some(vertices),
+                            ..Default::default()
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    let count = mesh.vertices.len();
+    if (has_normals && mesh.normals.len() != count)
+        || (has_uvs && mesh.uvs.len() != count)
+        || (!mesh.colors.is_empty() && mesh.colors.len() != count)
+    {
+        return Err(invalid_data("vertex attributes are incomplete".to_string()));
+    }
+
+    if mesh.faces.is_empty() {
+        return Err(invalid_data(
+            "no faces, point clouds are not supported".to_string(),
+        ));
+    }
+
+    mesh.groups.push("default".to_string());
+    mesh.build();
+    Ok(mesh)
+}
+
+impl Mesh {
+    /// Loads Stanford PLY file
+    pub fn from_ply(path: &str) -> io::Result<Mesh> {
+        let file = File::open(path)?;
+        Self::read_ply(BufReader::new(file))
+    }
+
+    /// Supports ASCII and binary encodings with vertex normals, colors and texture coordinates
+    pub fn read_ply<R: BufRead>(mut reader: R) -> io::Result<Mesh> {
+        let (format, elements) = read_header(&mut reader)?;
+
+        match format {
+            Format::Ascii => {
+                let mut body = String::new();
+                reader.read_to_string(&mut body)?;
+                let mut source = AsciiSource {
+                    tokens: body.split_whitespace(),
+                };
+                read_body(&mut source, &elements)
+            }
+            Format::LittleEndian | Format::BigEndian => {
+                let mut source = BinarySource {
+                    reader,
+                    big_endian: format == Format::BigEndian,
+                };
+                read_body(&mut source, &elements)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::geometry::Hittable;
+    use crate::material::Material;
+    use crate::ply::*;
+    use crate::ray::Ray;
+
+    const ASCII: &str = "ply
+format ascii 1.0
+comment square with colors
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 1 255 0 0
+1 0 0 0 0 1 0 255 0
+1 1 0 0 0 1 0 0 255
+0 1 0 0 0 1 255 255 255
+4 0 1 2 3
+";
+
+    #[test]
+    fn test_ascii() {
+        let mesh = Mesh::read_ply(ASCII.as_bytes()).unwrap();
+        assert_eq!(mesh.vertices().len(), 4);
+        assert_eq!(mesh.vertices()[2], Vec3f::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.normals()[0], Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh.colors()[1], Vec3f::new(0.0, 1.0, 0.0));
+
+        let faces: Vec<[usize; 3]> = mesh.faces().iter().map(|f| f.vertices).collect();
+        assert_eq!(faces, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.faces()[1].normals, Some([0, 2, 3]));
+        assert_eq!(mesh.faces()[1].uvs, None);
+
+        // colors are interpolated over the face and tint the albedo
+        let ray = Ray::new(Vec3f::new(0.75, 0.25, 1.0), Vec3f::new(0.0, 0.0, -1.0));
+        let hit = mesh.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.color - Vec3f::new(0.25, 0.5, 0.25)).length() < 1e-12);
+        let material = Material {
+            albedo: Vec3f::from(0.5),
+            ..Default::default()
+        };
+        assert!((material.albedo(&hit) - Vec3f::new(0.125, 0.25, 0.125)).length() < 1e-12);
+    }
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let header = format!(
+            "ply\nformat {} 1.0\nelement vertex 3\nproperty double x\nproperty double y\n\
+             property double z\nproperty float u\nproperty float v\nelement edge 1\n\
+             property int vertex1\nproperty int vertex2\nelement face 1\n\
+             property list uchar uint vertex_indices\nend_header\n",
+            format
+        );
+
+        let mut data = header.into_bytes();
+        let vertices = [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]];
+        for (i, v) in vertices.iter().enumerate() {
+            for x in v {
+                let bytes = if big_endian {
+                    f64::to_be_bytes(*x)
+                } else {
+                    f64::to_le_bytes(*x)
+                };
+                data.extend_from_slice(&bytes);
+            }
+            for uv in [i as f32 * 0.5, 1.0] {
+                let bytes = if big_endian {
+                    f32::to_be_bytes(uv)
+                } else {
+                    f32::to_le_bytes(uv)
+                };
+                data.extend_from_slice(&bytes);
+            }
+        }
+        for index in [0i32, 1] {
+            let bytes = if big_endian {
+                index.to_be_bytes()
+            } else {
+                index.to_le_bytes()
+            };
+            data.extend_from_slice(&bytes);
+        }
+        data.push(3);
+        for index in [0u32, 1, 2] {
+            let bytes = if big_endian {
+                index.to_be_bytes()
+            } else {
+                index.to_le_bytes()
+            };
+            data.extend_from_slice(&bytes);
+        }
+        data
+    }
+
+    #[test]
+    fn test_binary() {
+        for big_endian in [false, true] {
+            let mesh = Mesh::read_ply(binary(big_endian).as_slice()).unwrap();
+            assert_eq!(mesh.vertices()[1], Vec3f::new(2.0, 0.0, 0.0));
+            assert_eq!(mesh.uvs()[2], Vec2f::new(1.0, 1.0));
+            assert_eq!(mesh.faces().len(), 1);
+            assert_eq!(mesh.faces()[0].vertices, [0, 1, 2]);
+            assert_eq!(mesh.faces()[0].uvs, Some([0, 1, 2]));
+
+            // normals are computed from the geometry
+            let normal = mesh.faces()[0].normals.unwrap()[0];
+            assert_eq!(mesh.normals()[normal], Vec3f::new(0.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn test_concave() {
+        // triangulated like OBJ polygons, without covering the notch
+        let ply = "ply\nformat ascii 1.0\nelement vertex 5\nproperty float x\n\
+                   property float y\nproperty float z\nelement face 1\n\
+                   property list uchar int vertex_indices\nend_header\n\
+                   0 2 0\n0 0 0\n2 0 0\n2 2 0\n1 1 0\n5 0 1 2 3 4\n";
+        let mesh = Mesh::read_ply(ply.as_bytes()).unwrap();
+        let faces: Vec<[usize; 3]> = mesh.faces().iter().map(|f| f.vertices).collect();
+        let points: Vec<Vec3f> = mesh.vertices().to_vec();
+        assert_eq!(faces, triangulate(&points));
+        assert_ne!(faces, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Mesh::read_ply("obj\n".as_bytes()).is_err());
+        assert!(Mesh::read_ply(ASCII.replace("4 0 1 2 3", "4 0 1 2 4").as_bytes()).is_err());
+        assert!(Mesh::read_ply(ASCII.replace("4 0 1 2 3\n", "").as_bytes()).is_err());
+
+        let truncated = binary(false);
+        assert!(Mesh::read_ply(&truncated[..truncated.len() - 2]).is_err());
+
+        let points = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n\
+                      property float y\nproperty float z\nend_header\n0 0 0\n";
+        assert!(Mesh::read_ply(points.as_bytes()).is_err());
+    }
+}
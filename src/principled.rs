@@ -0,0 +1,424 @@
+use crate::common::*;
+use crate::material::*;
+use crate::onb::Onb;
+use crate::vector::*;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Parameters of the principled BSDF besides the base color (albedo), roughness, metallic and ior
+// Burley 2012, "Physically-Based Shading at Disney", lobes weighted like Blender's Principled BSDF
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Principled {
+    /// Blends the diffuse toward a flattened, subsurface like response
+    pub subsurface: f64,
+    /// Dielectric specular amount, 0.5 matches an ior of 1.5
+    pub specular: f64,
+    /// Tints the dielectric specular toward the base color
+    pub specular_tint: f64,
+    /// Stretches the specular highlight along the surface tangent
+    pub anisotropic: f64,
+    /// Grazing retroreflection for cloth
+    pub sheen: f64,
+    /// Tints the sheen toward the base color
+    pub sheen_tint: f64,
+    /// Strength of a white specular coat
+    pub clearcoat: f64,
+    /// Glossiness of the coat, from satin at 0 to gloss at 1
+    pub clearcoat_gloss: f64,
+    /// Fraction of the dielectric part that is glass, refracting by 'ior'
+    pub transmission: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            subsurface: 0.0,
+            specular: 0.5,
+            specular_tint: 0.0,
+            anisotropic: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+        }
+    }
+}
+
+/// Lobes of the principled BSDF
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const GLASS: usize = 2;
+const CLEARCOAT: usize = 3;
+
+/// Roughness of the clearcoat masking term
+const CLEARCOAT_ROUGHNESS: f64 = 0.25;
+
+/// Local frame normal
+const UP: Vec3f = Vec3f {
+    x: 0.0,
+    y: 0.0,
+    z: 1.0,
+};
+
+fn mix(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    f64::powi((1.0 - cos_theta).clamp(0.0, 1.0), 5)
+}
+
+/// Base color normalized to unit luminance, the hue used by the tint parameters
+fn tint(base: Vec3f) -> Vec3f {
+    let luminance = 0.2126 * base.x + 0.7152 * base.y + 0.0722 * base.z;
+    if luminance > 0.0 {
+        base / luminance
+    } else {
+        Vec3f::from(1.0)
+    }
+}
+
+/// Berry (GTR1) distribution of the clearcoat
+fn gtr1(cos_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_h * cos_h))
+}
+
+/// Local microfacet normal distributed by GTR1 times its cosine
+fn sample_gtr1(alpha: f64) -> Vec3f {
+    let mut rng = rand::thread_rng();
+    let (r1, r2): (f64, f64) = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+    let a2 = alpha * alpha;
+    let cos2 = (1.0 - a2.powf(1.0 - r1)) / (1.0 - a2);
+    let cos_theta = f64::sqrt(cos2.clamp(0.0, 1.0));
+    from_spherical(f64::acos(cos_theta), 2.0 * PI * r2)
+}
+
+impl Principled {
+    /// Weight of each lobe in the sum
+    fn weights(&self, material: &Material) -> [f64; 4] {
+        let glass = (1.0 - material.metallic) * self.transmission;
+        [
+            (1.0 - material.metallic) * (1.0 - self.transmission),
+            1.0 - glass,
+            glass,
+            0.25 * self.clearcoat,
+        ]
+    }
+
+    /// Probability of sampling each lobe, only glass scatters light arriving from below
+    fn probabilities(&self, material: &Material, wo: Vec3f) -> [f64; 4] {
+        let mut weights = self.weights(material);
+        if wo.z <= 0.0 {
+            weights = [0.0, 0.0, weights[GLASS], 0.0];
+        }
+
+        let sum: f64 = weights.iter().sum();
+        if sum > 0.0 {
+            weights.map(|w| w / sum)
+        } else {
+            weights
+        }
+    }
+
+    /// Specular roughness along the tangent and bitangent
+    fn alphas(&self, material: &Material) -> (f64, f64) {
+        let alpha = material.roughness * material.roughness;
+        let aspect = f64::sqrt(1.0 - 0.9 * self.anisotropic);
+        (
+            f64::max(alpha / aspect, SMOOTH_ROUGHNESS),
+            f64::max(alpha * aspect, SMOOTH_ROUGHNESS),
+        )
+    }
+
+    fn glass_roughness(material: &Material) -> f64 {
+        f64::max(material.roughness * material.roughness, SMOOTH_ROUGHNESS)
+    }
+
+    fn clearcoat_alpha(&self) -> f64 {
+        mix(0.1, 0.001, self.clearcoat_gloss)
+    }
+
+    /// BSDF for local directions around +z
+    fn eval(&self, material: &Material, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        let base = material.albedo;
+        let weights = self.weights(material);
+        let mut f = Vec3f::from(0.0);
+
+        if wo.z > 0.0 && wi.z > 0.0 {
+            let h = Vec3f::normalize(wo + wi);
+            let cos_d = Vec3f::dot(wi, h);
+            let (fl, fv, fh) = (
+                schlick_weight(wi.z),
+                schlick_weight(wo.z),
+                schlick_weight(cos_d),
+            );
+
+            if weights[DIFFUSE] > 0.0 {
+                let roughness = material.roughness;
+                let fd90 = 0.5 + 2.0 * cos_d * cos_d * roughness;
+                let fd = mix(1.0, fd90, fl) * mix(1.0, fd90, fv);
+
+                let fss90 = cos_d * cos_d * roughness;
+                let fss = mix(1.0, fss90, fl) * mix(1.0, fss90, fv);
+                let ss = 1.25 * (fss * (1.0 / (wi.z + wo.z) - 0.5) + 0.5);
+
+                let sheen = Vec3::lerp(Vec3f::from(1.0), tint(base), self.sheen_tint)
+                    * (self.sheen * fh);
+                let diffuse = base * (mix(fd, ss, self.subsurface) / PI) + sheen;
+                f += diffuse * weights[DIFFUSE];
+            }
+
+            let (alpha_x, alpha_y) = self.alphas(material);
+            let dielectric = Vec3::lerp(Vec3f::from(1.0), tint(base), self.specular_tint)
+                * (0.08 * self.specular);
+            let f0 = Vec3::lerp(dielectric, base, material.metallic);
+            let fresnel = Vec3::lerp(f0, Vec3f::from(1.0), fh);
+            let distribution = ggx_anisotropic(h, alpha_x, alpha_y);
+            let geometry =
+                ggx_anisotropic_g1(wo, alpha_x, alpha_y) * ggx_anisotropic_g1(wi, alpha_x, alpha_y);
+            f += fresnel * (distribution * geometry / (4.0 * wo.z * wi.z) * weights[SPECULAR]);
+
+            if weights[CLEARCOAT] > 0.0 {
+                let fresnel = mix(0.04, 1.0, fh);
+                let distribution = gtr1(h.z, self.clearcoat_alpha());
+                let geometry = smith_g1_ggx(wo, h, UP, CLEARCOAT_ROUGHNESS)
+                    * smith_g1_ggx(wi, h, UP, CLEARCOAT_ROUGHNESS);
+                f += Vec3f::from(fresnel * distribution * geometry / (4.0 * wo.z * wi.z))
+                    * weights[CLEARCOAT];
+            }
+        }
+
+        if weights[GLASS] > 0.0 {
+            let glass = rough_dielectric(
+                UP,
+                wo,
+                wi,
+                Self::glass_roughness(material),
+                material.ior,
+                None,
+            );
+            f += base * glass * weights[GLASS];
+        }
+
+        f
+    }
+
+    /// Density of 'sample_local' choosing the local direction 'wi'
+    fn pdf_local(&self, material: &Material, wo: Vec3f, wi: Vec3f) -> f64 {
+        let probabilities = self.probabilities(material, wo);
+        let mut pdf = 0.0;
+
+        if wo.z > 0.0 && wi.z > 0.0 {
+            let h = Vec3f::normalize(wo + wi);
+            let oh = Vec3f::dot(wo, h);
+
+            pdf += probabilities[DIFFUSE] * wi.z / PI;
+
+            let (alpha_x, alpha_y) = self.alphas(material);
+            pdf += probabilities[SPECULAR]
+                * ggx_anisotropic_g1(wo, alpha_x, alpha_y)
+                * ggx_anisotropic(h, alpha_x, alpha_y)
+                / (4.0 * wo.z);
+
+            if probabilities[CLEARCOAT] > 0.0 && oh > 0.0 {
+                pdf += probabilities[CLEARCOAT] * gtr1(h.z, self.clearcoat_alpha()) * h.z
+                    / (4.0 * oh);
+            }
+        }
+
+        if probabilities[GLASS] > 0.0 {
+            let roughness = Self::glass_roughness(material);
+            pdf += probabilities[GLASS]
+                * rough_dielectric_pdf(UP, wo, wi, roughness, material.ior, None);
+        }
+
+        pdf
+    }
+
+    /// Picks a lobe by its probability and samples it, for local directions around +z
+    fn sample_local(&self, material: &Material, wo: Vec3f) -> Vec3f {
+        let probabilities = self.probabilities(material, wo);
+        let mut r = rand::thread_rng().gen_range(0.0..1.0);
+
+        let mut lobe = GLASS;
+        for (i, p) in probabilities.iter().enumerate() {
+            if r < *p {
+                lobe = i;
+                break;
+            }
+            r -= p;
+        }
+
+        match lobe {
+            DIFFUSE => cosine_weighted_hemisphere(),
+            SPECULAR => {
+                let (alpha_x, alpha_y) = self.alphas(material);
+                reflect(-wo, ggx_visible_normal(wo, alpha_x, alpha_y))
+            }
+            CLEARCOAT => reflect(-wo, sample_gtr1(self.clearcoat_alpha())),
+            _ => {
+                let roughness = Self::glass_roughness(material);
+                sample_rough_dielectric(UP, wo, roughness, material.ior, None).0
+            }
+        }
+    }
+
+    pub(crate) fn bsdf(&self, material: &Material, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        let frame = Onb::from_tangent(normal, material.tangent);
+        self.eval(material, frame.to_local(wo), frame.to_local(wi))
+    }
+
+    pub(crate) fn pdf(&self, material: &Material, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        let frame = Onb::from_tangent(normal, material.tangent);
+        self.pdf_local(material, frame.to_local(wo), frame.to_local(wi))
+    }
+
+    /// Direction with the density of all lobes combined, for multiple importance sampling
+    pub(crate) fn sample_f(&self, material: &Material, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64) {
+        let frame = Onb::from_tangent(normal, material.tangent);
+        let wo = frame.to_local(wo);
+
+        if self.probabilities(material, wo).iter().all(|p| *p == 0.0) {
+            return (frame.transform(reflect(-wo, UP)), 0.0);
+        }
+
+        let wi = self.sample_local(material, wo);
+        (frame.transform(wi), self.pdf_local(material, wo, wi))
+    }
+
+    pub(crate) fn sample(&self, material: &Material, normal: Vec3f, wo: Vec3f) -> (Vec3f, Vec3f) {
+        let (wi, pdf) = self.sample_f(material, normal, wo);
+        if pdf == 0.0 {
+            return (wi, Vec3f::from(0.0));
+        }
+        let cos_theta = Vec3f::dot(normal, wi).abs();
+        (wi, self.bsdf(material, normal, wo, wi) * cos_theta / pdf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::principled::*;
+
+    /// Directional albedo by importance sampling and by uniform sampling of the sphere
+    fn albedo(material: &Material, wo: Vec3f) -> (f64, f64) {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let mut rng = rand::thread_rng();
+        let n = 40000;
+
+        let (mut sampled, mut uniform) = (0.0, 0.0);
+        for _ in 0..n {
+            let (wi, pdf) = material.sample_f(normal, wo);
+            if pdf > 0.0 {
+                sampled += material.bsdf(normal, wo, wi).y * wi.z.abs() / pdf;
+            }
+
+            let z: f64 = rng.gen_range(-1.0..1.0);
+            let phi = rng.gen_range(0.0..2.0 * PI);
+            let r = f64::sqrt(1.0 - z * z);
+            let wi = Vec3f::new(r * phi.cos(), r * phi.sin(), z);
+            uniform += material.bsdf(normal, wo, wi).y * z.abs() * 4.0 * PI;
+        }
+        (sampled / n as f64, uniform / n as f64)
+    }
+
+    #[test]
+    fn test_principled_sampling() {
+        // the pdf of the lobe mixture must match the sampled directions
+        let wo = Vec3f::new(0.4, 0.3, 1.0).normalize();
+        for parameters in [
+            Principled::default(),
+            Principled {
+                anisotropic: 0.8,
+                sheen: 1.0,
+                subsurface: 1.0,
+                clearcoat: 1.0,
+                clearcoat_gloss: 0.5,
+                ..Default::default()
+            },
+            Principled {
+                transmission: 0.5,
+                ..Default::default()
+            },
+        ] {
+            let material = Material {
+                albedo: Vec3f::from(1.0),
+                roughness: 0.4,
+                material: MaterialType::Principled(parameters),
+                tangent: Vec3f::new(1.0, 0.0, 0.0),
+                ..Default::default()
+            };
+            let (sampled, uniform) = albedo(&material, wo);
+            assert!(
+                (sampled - uniform).abs() < 0.05,
+                "{:?} {} {}",
+                parameters,
+                sampled,
+                uniform
+            );
+            assert!(sampled > 0.5 && sampled < 1.05, "{:?} {}", parameters, sampled);
+        }
+    }
+
+    #[test]
+    fn test_principled_metal() {
+        // a white metal reflects nearly everything, and nothing from below
+        let material = Material {
+            albedo: Vec3f::from(1.0),
+            metallic: 1.0,
+            roughness: 0.3,
+            material: MaterialType::Principled(Principled::default()),
+            tangent: Vec3f::new(1.0, 0.0, 0.0),
+            ..Default::default()
+        };
+        let (sampled, _) = albedo(&material, Vec3f::new(0.2, 0.0, 1.0).normalize());
+        assert!(sampled > 0.9 && sampled < 1.01, "{}", sampled);
+
+        let below = Vec3f::new(0.0, 0.0, -1.0);
+        let (_, pdf) = material.sample_f(Vec3f::new(0.0, 0.0, 1.0), below);
+        assert_eq!(pdf, 0.0);
+    }
+
+    #[test]
+    fn test_principled_anisotropic() {
+        // the highlight is stretched along the tangent
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let material = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.4,
+            metallic: 1.0,
+            material: MaterialType::Principled(Principled {
+                anisotropic: 0.9,
+                ..Default::default()
+            }),
+            tangent: Vec3f::new(1.0, 0.0, 0.0),
+            ..Default::default()
+        };
+        let wo = Vec3f::new(0.0, 0.0, 1.0);
+        let along = Vec3f::new(0.5, 0.0, 1.0).normalize();
+        let across = Vec3f::new(0.0, 0.5, 1.0).normalize();
+        assert!(material.bsdf(normal, wo, along).x > 2.0 * material.bsdf(normal, wo, across).x);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let material: Material = serde_json::from_str(
+            r#"{ "albedo": [0.8, 0.1, 0.1], "emittance": 0, "roughness": 0.3, "ior": 1.5,
+                 "metallic": 0, "material": { "Principled": { "clearcoat": 1 } } }"#,
+        )
+        .unwrap();
+
+        match material.material {
+            MaterialType::Principled(p) => {
+                assert_eq!(p.clearcoat, 1.0);
+                assert_eq!(p.specular, 0.5);
+            }
+            _ => panic!("{:?}", material.material),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use crate::geometry::Hit;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Permutation table of Ken Perlin's reference implementation
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+/// Offset for grid aligned patterns, so surfaces lying exactly on a cell border do not flicker
+const GRID_OFFSET: f64 = 1e-4;
+
+/// Permutation table entry, indices wrap around like the doubled table of the reference
+fn perm(i: usize) -> usize {
+    PERMUTATION[i & 255] as usize
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// Dot product with one of 12 gradient directions selected by 'hash'
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = match h {
+        0..=3 => y,
+        12 | 14 => x,
+        _ => z,
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// Improved Perlin noise (Perlin 2002), roughly in [-1, 1] and zero at integer points
+pub fn perlin(p: Vec3f) -> f64 {
+    // lattice cell, wrapped to the size of the table
+    let cell = |x: f64| (x.floor() as i64 & 255) as usize;
+    let (xi, yi, zi) = (cell(p.x), cell(p.y), cell(p.z));
+
+    let (x, y, z) = (p.x - p.x.floor(), p.y - p.y.floor(), p.z - p.z.floor());
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let a = perm(xi) + yi;
+    let (aa, ab) = (perm(a) + zi, perm(a + 1) + zi);
+    let b = perm(xi + 1) + yi;
+    let (ba, bb) = (perm(b) + zi, perm(b + 1) + zi);
+
+    let corner = |h: usize, dx: f64, dy: f64, dz: f64| grad(perm(h), x - dx, y - dy, z - dz);
+
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, corner(aa, 0.0, 0.0, 0.0), corner(ba, 1.0, 0.0, 0.0)),
+            lerp(u, corner(ab, 0.0, 1.0, 0.0), corner(bb, 1.0, 1.0, 0.0)),
+        ),
+        lerp(
+            v,
+            lerp(
+                u,
+                corner(aa + 1, 0.0, 0.0, 1.0),
+                corner(ba + 1, 1.0, 0.0, 1.0),
+            ),
+            lerp(
+                u,
+                corner(ab + 1, 0.0, 1.0, 1.0),
+                corner(bb + 1, 1.0, 1.0, 1.0),
+            ),
+        ),
+    )
+}
+
+/// Fractional Brownian motion, octaves of noise with doubling frequency and halving amplitude
+/// Normalized to roughly [-1, 1]
+pub fn fbm(p: Vec3f, octaves: u32) -> f64 {
+    let (mut sum, mut weight, mut total) = (0.0, 1.0, 0.0);
+    let mut p = p;
+    for _ in 0..octaves.max(1) {
+        sum += perlin(p) * weight;
+        total += weight;
+        weight *= 0.5;
+        p = p * 2.0;
+    }
+    sum / total
+}
+
+/// Like 'fbm' but with absolute values of the octaves, in [0, 1]
+pub fn turbulence(p: Vec3f, octaves: u32) -> f64 {
+    let (mut sum, mut weight, mut total) = (0.0, 1.0, 0.0);
+    let mut p = p;
+    for _ in 0..octaves.max(1) {
+        sum += perlin(p).abs() * weight;
+        total += weight;
+        weight *= 0.5;
+        p = p * 2.0;
+    }
+    (sum / total).min(1.0)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// Alternating cells of size 1 / 'scale'
+    Checker,
+    /// fBm noise
+    Noise,
+    Turbulence,
+    /// Veins along x distorted by turbulence
+    Marble,
+    /// Rings around the y axis distorted by noise
+    Wood,
+    /// Linear ramp from x = 0 to x = 1 / 'scale'
+    Gradient,
+}
+
+/// Coordinates the pattern is evaluated at
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Mapping {
+    /// Hit point in world space, a solid texture
+    #[default]
+    World,
+    /// Texture coordinates (u, v, 0)
+    Uv,
+}
+
+/// Texture computed from the hit point, the pattern value in [0, 1] selects a color
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Procedural {
+    pub pattern: Pattern,
+    /// Colors spread evenly over the pattern value, the first at 0 and the last at 1
+    #[serde(default = "black_and_white")]
+    pub colors: Vec<Vec3f>,
+    /// Frequency of the pattern
+    #[serde(default = "one")]
+    pub scale: f64,
+    #[serde(default)]
+    pub mapping: Mapping,
+    /// Number of noise octaves
+    #[serde(default = "four")]
+    pub octaves: u32,
+}
+
+fn black_and_white() -> Vec<Vec3f> {
+    vec![Vec3f::from(0.0), Vec3f::from(1.0)]
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn four() -> u32 {
+    4
+}
+
+impl Procedural {
+    pub fn new(pattern: Pattern) -> Self {
+        Self {
+            pattern,
+            colors: black_and_white(),
+            scale: 1.0,
+            mapping: Mapping::default(),
+            octaves: 4,
+        }
+    }
+
+    /// Pattern value in [0, 1] at point 'p'
+    pub fn pattern(&self, p: Vec3f) -> f64 {
+        let q = p * self.scale;
+
+        match self.pattern {
+            Pattern::Checker => {
+                let q = q + GRID_OFFSET;
+                let cells = q.x.floor() as i64 + q.y.floor() as i64 + q.z.floor() as i64;
+                cells.rem_euclid(2) as f64
+            }
+            Pattern::Noise => 0.5 + 0.5 * fbm(q, self.octaves),
+            Pattern::Turbulence => turbulence(q, self.octaves),
+            Pattern::Marble => 0.5 + 0.5 * f64::sin(q.x * PI + 5.0 * turbulence(q, self.octaves)),
+            Pattern::Wood => {
+                let r = f64::sqrt(q.x * q.x + q.z * q.z) + 0.3 * fbm(q, self.octaves);
+                r - r.floor()
+            }
+            Pattern::Gradient => q.x.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Color at the hit point
+    pub fn color(&self, hit: &Hit) -> Vec3f {
+        let p = match self.mapping {
+            Mapping::World => hit.point,
+            Mapping::Uv => Vec3f::new(hit.uv.x, hit.uv.y, 0.0),
+        };
+        let t = self.pattern(p).clamp(0.0, 1.0);
+
+        match self.colors.len() {
+            0 => Vec3f::from(t),
+            1 => self.colors[0],
+            n => {
+                let x = t * (n - 1) as f64;
+                let i = (x.floor() as usize).min(n - 2);
+                Vec3f::lerp(self.colors[i], self.colors[i + 1], x - i as f64)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::procedural::*;
+
+    #[test]
+    fn test_perlin() {
+        // zero at lattice points, bounded elsewhere
+        assert_eq!(perlin(Vec3f::new(3.0, -2.0, 7.0)), 0.0);
+
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        for i in 0..1000 {
+            let x = i as f64 * 0.137;
+            let n = perlin(Vec3f::new(x, x * 0.71 - 3.0, x * 1.3 + 0.5));
+            min = min.min(n);
+            max = max.max(n);
+        }
+        assert!(-1.0 <= min && max <= 1.0);
+        assert!(max - min > 0.5);
+
+        // continuous across cell borders
+        let a = perlin(Vec3f::new(0.999_999, 0.3, 0.6));
+        let b = perlin(Vec3f::new(1.000_001, 0.3, 0.6));
+        assert!((a - b).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_checker() {
+        let checker = Procedural {
+            scale: 2.0,
+            ..Procedural::new(Pattern::Checker)
+        };
+        assert_eq!(checker.pattern(Vec3f::new(0.25, 0.0, 0.25)), 0.0);
+        assert_eq!(checker.pattern(Vec3f::new(0.75, 0.0, 0.25)), 1.0);
+        assert_eq!(checker.pattern(Vec3f::new(-0.25, 0.0, 0.25)), 1.0);
+
+        // a plane on a cell border has a single color
+        assert_eq!(checker.pattern(Vec3f::new(0.25, 1.0 - 1e-12, 0.25)), 0.0);
+        assert_eq!(checker.pattern(Vec3f::new(0.25, 1.0 + 1e-12, 0.25)), 0.0);
+    }
+
+    #[test]
+    fn test_patterns_in_range() {
+        let patterns = [
+            Pattern::Noise,
+            Pattern::Turbulence,
+            Pattern::Marble,
+            Pattern::Wood,
+            Pattern::Gradient,
+        ];
+        for pattern in patterns {
+            let procedural = Procedural {
+                scale: 3.0,
+                ..Procedural::new(pattern)
+            };
+            for i in 0..200 {
+                let x = i as f64 * 0.05 - 5.0;
+                let t = procedural.pattern(Vec3f::new(x, 0.3 * x, -x));
+                assert!((0.0..=1.0).contains(&t), "{:?} {}", pattern, t);
+            }
+        }
+    }
+
+    #[test]
+    fn test_colors() {
+        let gradient = Procedural {
+            colors: vec![
+                Vec3f::new(1.0, 0.0, 0.0),
+                Vec3f::new(0.0, 1.0, 0.0),
+                Vec3f::new(0.0, 0.0, 1.0),
+            ],
+            mapping: Mapping::Uv,
+            ..Procedural::new(Pattern::Gradient)
+        };
+
+        let mut hit = Hit {
+            uv: Vec2f::new(0.25, 0.0),
+            ..Default::default()
+        };
+        assert_eq!(gradient.color(&hit), Vec3f::new(0.5, 0.5, 0.0));
+
+        hit.uv = Vec2f::new(1.0, 0.0);
+        assert_eq!(gradient.color(&hit), Vec3f::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let json =
+            r#"{ "pattern": "Checker", "colors": [[1, 1, 1], [0.1, 0.1, 0.1]], "scale": 4 }"#;
+        let checker: Procedural = serde_json::from_str(json).unwrap();
+        assert_eq!(checker.pattern, Pattern::Checker);
+        assert_eq!(checker.mapping, Mapping::World);
+        assert_eq!(checker.scale, 4.0);
+    }
+}
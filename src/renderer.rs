@@ -2,10 +2,14 @@ use crate::camera::*;
 use crate::common::*;
 use crate::geometry::*;
 use crate::material::*;
+use crate::medium::*;
+use crate::onb::Onb;
 use crate::ray::*;
+use crate::spectrum::*;
 use crate::vector::*;
 
 use image::RgbImage;
+use std::f64::consts::PI;
 use std::thread;
 use std::thread::available_parallelism;
 use std::vec;
@@ -24,6 +28,71 @@ fn print_progress(current_sample: u32, total_samples: u32) {
     );
 }
 
+/// Deepest nesting of transparent objects that is tracked
+const MAX_MEDIA: usize = 4;
+
+/// Most collisions a random walk inside a Subsurface object follows before giving up
+const MAX_WALK: u32 = 256;
+
+/// Media a path is inside, innermost last, within the medium outside all objects
+/// Deeper nesting keeps attenuating with the innermost tracked medium
+#[derive(Debug, Clone, Default)]
+struct Media {
+    outside: Medium,
+    media: [Medium; MAX_MEDIA],
+    depth: usize,
+}
+
+impl Media {
+    fn new(outside: Medium) -> Self {
+        Self {
+            outside,
+            ..Default::default()
+        }
+    }
+
+    /// Innermost medium
+    fn medium(&self) -> &Medium {
+        if self.depth == 0 {
+            &self.outside
+        } else {
+            &self.media[usize::min(self.depth, MAX_MEDIA) - 1]
+        }
+    }
+
+    /// Fraction of light left after traveling 'distance' along 'ray' through the innermost medium
+    fn transmittance(&self, ray: &Ray, distance: f64) -> Vec3f {
+        self.medium().transmittance(ray, distance)
+    }
+
+    /// Media after the path crosses a transparent surface from 'wo' to 'wi'
+    fn cross(mut self, hit: &Hit, material: &Material, wo: Vec3f, wi: Vec3f) -> Self {
+        if !matches!(
+            material.material,
+            MaterialType::Transparent
+                | MaterialType::Principled(_)
+                | MaterialType::Interface
+                | MaterialType::Subsurface(_)
+        ) {
+            return self;
+        }
+
+        let outside = Vec3f::dot(wo, hit.geometric_normal) > 0.0;
+        let transmitted = outside != (Vec3f::dot(wi, hit.geometric_normal) > 0.0);
+
+        if transmitted && outside {
+            if self.depth < MAX_MEDIA {
+                self.media[self.depth] = material.interior();
+            }
+            self.depth += 1;
+        } else if transmitted {
+            self.depth = self.depth.saturating_sub(1);
+        }
+
+        self
+    }
+}
+
 pub struct Renderer;
 
 impl Renderer {
@@ -37,66 +106,293 @@ impl Renderer {
         }
     }
 
-    fn sample_lights(scene: &Scene, hit: &Hit, wo: Vec3f) -> Vec3f {
-        let material = scene.objects[hit.idx].material;
+    /// Fraction of light passing along 'ray' up to 'distance', through the media and the
+    /// boundaries of Interface objects, zero if any other surface is in the way
+    fn transmittance(
+        scene: &Scene,
+        ray: &Ray,
+        distance: f64,
+        mut media: Media,
+        wavelength: Option<f64>,
+    ) -> Vec3f {
+        let mut ray = *ray;
+        let mut distance = distance;
+        let mut transmittance = Vec3f::from(1.0);
+
+        while let Some(hit) = scene.hit(&ray, 0.001, distance) {
+            let surface = scene.material(&hit);
+            if surface.material != MaterialType::Interface {
+                return Vec3f::from(0.0);
+            }
+
+            let material = match wavelength {
+                Some(lambda) => surface.at_wavelength(lambda),
+                None => surface.clone(),
+            };
+            transmittance *= media.transmittance(&ray, hit.t);
+            media = media.cross(&hit, &material, -ray.direction, ray.direction);
+            ray = Ray::new(hit.get_point_towards(ray.direction), ray.direction);
+            distance -= hit.t;
+        }
 
-        if material.material == MaterialType::Mirror
-            || material.material == MaterialType::Transparent
-        {
-            return Vec3::from(0.0);
+        transmittance * media.transmittance(&ray, distance)
+    }
+
+    /// Direction to a point sampled on 'light', its distance, and the arriving emission over
+    /// the density of the direction, None if the point faces away
+    fn sample_light(
+        light: &Light,
+        point: Vec3f,
+        wavelength: Option<f64>,
+    ) -> Option<(Vec3f, f64, Vec3f)> {
+        let (direction, distance, normal) = light.sample(point);
+        let cos_theta = Vec3::dot(normal, -direction);
+
+        if cos_theta <= 0.0 {
+            return None;
         }
 
-        let mut direct_light = Vec3::from(0.0);
-        let point = hit.get_point();
+        let emission = at_wavelength(light.emission, wavelength);
 
-        for &light in &scene.lights {
-            let (direction, distance, normal) = light.sample(hit.point);
-            let shadow_ray = Ray::new(point, direction);
+        let pdf = {
+            let distance2 = distance * distance;
+            let area = light.geometry.surface_area();
+            distance2 / (area * cos_theta)
+        };
 
-            let cos_theta = Vec3::dot(normal, -direction);
+        Some((direction, distance, emission / pdf))
+    }
 
-            let closest = scene.hit(&shadow_ray, 0.001, f64::INFINITY);
+    /// 'material' - Material at the hit point, with textures applied
+    /// 'media' - Media on the side of 'wo'
+    fn sample_lights(
+        scene: &Scene,
+        hit: &Hit,
+        material: &Material,
+        wo: Vec3f,
+        media: &Media,
+        wavelength: Option<f64>,
+    ) -> Vec3f {
+        if material.is_specular() {
+            return Vec3::from(0.0);
+        }
 
-            if (closest.is_none() || distance < closest.unwrap().t) && 0.0 < cos_theta {
-                let emission = light.emission;
+        let mut direct_light = Vec3::from(0.0);
 
-                let pdf = {
-                    let distance2 = distance * distance;
-                    let area = light.geometry.surface_area();
-                    distance2 / (area * cos_theta)
+        for light in &scene.lights {
+            let (direction, distance, emission) =
+                match Self::sample_light(light, hit.point, wavelength) {
+                    Some(sample) => sample,
+                    None => continue,
                 };
 
+            // lights on the other side from 'wo' are reached by transmission through the surface
+            let point = hit.get_point_towards(direction);
+            let shadow_ray = Ray::new(point, direction);
+            let media = media.clone().cross(hit, material, wo, direction);
+
+            let transmittance =
+                Self::transmittance(scene, &shadow_ray, distance, media, wavelength);
+
+            if transmittance != Vec3f::from(0.0) {
                 let bsdf = material.bsdf(hit.normal, wo, direction);
 
-                direct_light += bsdf * Vec3::dot(hit.normal, direction).abs() * emission / pdf;
+                direct_light +=
+                    bsdf * Vec3::dot(hit.normal, direction).abs() * emission * transmittance;
             }
         }
 
         direct_light / (scene.lights.len() as f64)
     }
 
+    /// Light scattered towards 'wo' at 'point' inside the innermost medium
+    fn medium_scattering(
+        point: Vec3f,
+        wo: Vec3f,
+        scene: &Scene,
+        bounce: u32,
+        media: Media,
+        wavelength: Option<f64>,
+    ) -> Vec3f {
+        let mut color = Vec3f::from(0.0);
+
+        for light in &scene.lights {
+            if let Some((direction, distance, emission)) =
+                Self::sample_light(light, point, wavelength)
+            {
+                let shadow_ray = Ray::new(point, direction);
+                let transmittance =
+                    Self::transmittance(scene, &shadow_ray, distance, media.clone(), wavelength);
+                color += emission * transmittance * media.medium().phase(wo, direction);
+            }
+        }
+        color = color / (scene.lights.len() as f64);
+
+        // sampled by the phase function, which cancels against its density
+        if 0 < bounce {
+            let ray = Ray::new(point, media.medium().sample_phase(wo));
+            color += Self::path_tracing(&ray, scene, bounce - 1, media, wavelength);
+        }
+
+        color
+    }
+
+    /// Follows 'ray' through the innermost medium, scattering until it reaches a surface
+    /// Returns the surface hit from inside and the weight of the walk, None if lost or absorbed
+    fn random_walk(ray: &Ray, scene: &Scene, medium: &Medium) -> Option<(Hit, Vec3f)> {
+        let mut ray = *ray;
+        let mut weight = Vec3f::from(1.0);
+
+        for _ in 0..MAX_WALK {
+            // meshes that are not closed let the walk escape
+            let hit = scene.hit(&ray, 0.001, f64::INFINITY)?;
+
+            let (scattered, w) = medium.sample_distance(&ray, hit.t);
+            weight *= w;
+            if weight == Vec3f::from(0.0) {
+                return None;
+            }
+
+            match scattered {
+                Some(distance) => {
+                    let direction = medium.sample_phase(-ray.direction);
+                    ray = Ray::new(ray.point_at(distance), direction);
+                }
+                None => return Some((hit, weight)),
+            }
+        }
+
+        None
+    }
+
+    /// Light a random walk carries out of a Subsurface object, starting along the refracted 'ray'
+    /// 'media' - Media inside the object
+    fn subsurface(
+        ray: &Ray,
+        scene: &Scene,
+        bounce: u32,
+        media: Media,
+        wavelength: Option<f64>,
+    ) -> Vec3f {
+        let (hit, weight) = match Self::random_walk(ray, scene, media.medium()) {
+            Some(walk) => walk,
+            None => return Vec3f::from(0.0),
+        };
+
+        // the walk leaves through a diffuse transmission, which light sampling can reach
+        let normal = hit.geometric_normal;
+        let wi = Onb::local_to_world(normal, cosine_weighted_hemisphere());
+        let media = media.cross(&hit, scene.material(&hit), -normal, wi);
+        let point = hit.get_point_towards(normal);
+
+        let mut color = Vec3f::from(0.0);
+        for light in &scene.lights {
+            if let Some((direction, distance, emission)) =
+                Self::sample_light(light, point, wavelength)
+            {
+                let cos_theta = Vec3f::dot(normal, direction);
+                if cos_theta > 0.0 {
+                    let shadow_ray = Ray::new(point, direction);
+                    let transmittance = Self::transmittance(
+                        scene,
+                        &shadow_ray,
+                        distance,
+                        media.clone(),
+                        wavelength,
+                    );
+                    color += emission * transmittance * cos_theta / PI;
+                }
+            }
+        }
+        color = color / (scene.lights.len() as f64);
+
+        // cosine sampling cancels against the lambertian lobe
+        if 0 < bounce {
+            let ray = Ray::new(point, wi);
+            color += Self::path_tracing(&ray, scene, bounce - 1, media, wavelength);
+        }
+
+        color * weight
+    }
+
+    /// 'media' - Media the ray travels through
+    /// 'wavelength' - Wavelength in nanometers the path carries in spectral mode
     #[allow(dead_code)]
-    fn path_tracing(ray: &Ray, scene: &Scene, bounce: u32) -> Vec3f {
-        if let Some(hit) = scene.hit(ray, 0.001, f64::INFINITY) {
-            let material = scene.objects[hit.idx].material;
-            let point = hit.get_point();
+    fn path_tracing(
+        ray: &Ray,
+        scene: &Scene,
+        bounce: u32,
+        media: Media,
+        wavelength: Option<f64>,
+    ) -> Vec3f {
+        let hit = scene.hit(ray, 0.001, f64::INFINITY);
+
+        // the medium may scatter the ray before it reaches the surface
+        let t = hit.as_ref().map_or(f64::INFINITY, |hit| hit.t);
+        let (scattered, weight) = media.medium().sample_distance(ray, t);
+        if let Some(distance) = scattered {
+            let point = ray.point_at(distance);
+            let wo = -ray.direction;
+            return Self::medium_scattering(point, wo, scene, bounce, media, wavelength) * weight;
+        }
+
+        if let Some(mut hit) = hit {
+            let surface = scene.material(&hit);
+            hit.normal = surface.shading_normal(&hit);
+            let material = match wavelength {
+                Some(lambda) => surface.at(&hit).at_wavelength(lambda),
+                None => surface.at(&hit),
+            };
             let wo = -ray.direction;
 
-            let mut color = material.albedo * material.emittance;
+            // boundaries of media are crossed without spending a bounce
+            if material.material == MaterialType::Interface {
+                let ray = Ray::new(hit.get_point_towards(ray.direction), ray.direction);
+                let media = media.cross(&hit, &material, wo, ray.direction);
+                return Self::path_tracing(&ray, scene, bounce, media, wavelength) * weight;
+            }
+
+            let mut color = at_wavelength(surface.emission(&hit), wavelength);
 
-            color += Self::sample_lights(scene, &hit, wo);
+            color += Self::sample_lights(scene, &hit, &material, wo, &media, wavelength);
 
             if 0 < bounce {
                 let (wi, pdf) = material.sample_f(hit.normal, wo);
-                let bsdf = material.bsdf(hit.normal, wo, wi);
-                let cos_theta = Vec3::dot(hit.normal, wi).abs();
-                let ray = Ray::new(point, wi);
-                color += Self::path_tracing(&ray, scene, bounce - 1) * bsdf * cos_theta / pdf;
+
+                if 0.0 < pdf {
+                    let bsdf = material.bsdf(hit.normal, wo, wi);
+                    let cos_theta = Vec3::dot(hit.normal, wi).abs();
+                    let ray = Ray::new(hit.get_point_towards(wi), wi);
+                    let entered = Vec3f::dot(wi, hit.geometric_normal) < 0.0
+                        && Vec3f::dot(wo, hit.geometric_normal) > 0.0;
+                    let media = media.cross(&hit, &material, wo, wi);
+                    let radiance = match material.material {
+                        MaterialType::Subsurface(_) if entered => {
+                            Self::subsurface(&ray, scene, bounce - 1, media, wavelength)
+                        }
+                        _ => Self::path_tracing(&ray, scene, bounce - 1, media, wavelength),
+                    };
+                    color += radiance * bsdf * cos_theta / pdf;
+                }
             }
 
-            color
+            color * weight
         } else {
-            scene.background
+            at_wavelength(scene.background, wavelength) * weight
+        }
+    }
+
+    /// Radiance along a camera ray, in spectral mode carried by a single sampled wavelength
+    fn trace(ray: &Ray, scene: &Scene, bounces: u32) -> Vec3f {
+        let outside = scene.medium.clone().unwrap_or_default();
+
+        if scene.spectral {
+            let lambda = sample_wavelength();
+            let media = Media::new(outside.at_wavelength(lambda));
+            let radiance = Self::path_tracing(ray, scene, bounces, media, Some(lambda));
+            spectrum_to_rgb(radiance.x, lambda)
+        } else {
+            Self::path_tracing(ray, scene, bounces, Media::new(outside), None)
         }
     }
 
@@ -111,8 +407,9 @@ impl Renderer {
             for y in 0..height {
                 for x in 0..width {
                     let ray = camera.get_ray((x, y));
-                    let color = Self::path_tracing(&ray, scene, bounces) / (samples as f64);
-                    assert!(0.0 <= f64::min(color.x, f64::min(color.y, color.z)));
+                    let color = Self::trace(&ray, scene, bounces) / (samples as f64);
+                    // single wavelengths may map outside the sRGB gamut
+                    assert!(scene.spectral || 0.0 <= f64::min(color.x, f64::min(color.y, color.z)));
                     framebuffer[(y * width + x) as usize] += color;
                 }
             }
@@ -121,7 +418,7 @@ impl Renderer {
             }
         }
 
-        to_image(framebuffer, width as u32, height as u32)
+        to_image(framebuffer, width, height)
     }
 
     #[allow(dead_code)]
@@ -146,12 +443,16 @@ impl Renderer {
             for (worker, chunk) in framebuffer.chunks_mut(chunk_size).enumerate() {
                 scope.spawn(move || {
                     for sample in 0..samples {
-                        for i in 0..chunk.len() {
+                        for (i, pixel) in chunk.iter_mut().enumerate() {
                             let xy = get_xy((worker * chunk_size + i) as u32, width);
                             let ray = camera.get_ray(xy);
-                            let color = Self::path_tracing(&ray, scene, bounces);
-                            assert!(0.0 <= f64::min(color.x, f64::min(color.y, color.z)));
-                            chunk[i] += color / (samples as f64);
+                            let color = Self::trace(&ray, scene, bounces);
+                            // single wavelengths may map outside the sRGB gamut
+                            assert!(
+                                scene.spectral
+                                    || 0.0 <= f64::min(color.x, f64::min(color.y, color.z))
+                            );
+                            *pixel += color / (samples as f64);
                         }
                         if worker == 0 && sample % 5 == 0 {
                             print_progress(sample, samples);
@@ -173,3 +474,138 @@ impl Renderer {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::principled::Principled;
+    use crate::renderer::*;
+    use crate::subsurface::Subsurface;
+
+    #[test]
+    fn test_media() {
+        let glass = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.0,
+            material: MaterialType::Transparent,
+            absorption: Vec3f::new(0.0, 1.0, 2.0),
+            ..Default::default()
+        };
+        let hit = Hit::new(1.0, Vec3f::new(0.0, 0.0, 1.0), Vec3f::from(0.0), 0);
+        let (up, down) = (Vec3f::new(0.0, 0.0, 1.0), Vec3f::new(0.0, 0.0, -1.0));
+
+        // reflection stays outside, refraction enters
+        let outside = Media::default();
+        assert_eq!(outside.clone().cross(&hit, &glass, up, up).depth, 0);
+        let inside = outside.clone().cross(&hit, &glass, up, down);
+        assert_eq!(inside.depth, 1);
+
+        let ray = Ray::new(Vec3f::from(0.0), up);
+        let t = inside.transmittance(&ray, 0.5);
+        assert_eq!(t.x, 1.0);
+        assert!((t.z - f64::exp(-1.0)).abs() < 1e-12);
+        assert_eq!(outside.transmittance(&ray, 0.5), Vec3f::from(1.0));
+
+        // leaving through the surface from below
+        assert_eq!(inside.clone().cross(&hit, &glass, down, up).depth, 0);
+        assert_eq!(inside.cross(&hit, &glass, down, down).depth, 1);
+
+        // scattering media behind invisible boundaries, within fog outside
+        let fog = Medium {
+            scattering: Vec3f::from(0.1),
+            ..Default::default()
+        };
+        let smoke = Medium {
+            absorption: Vec3f::from(0.5),
+            scattering: Vec3f::from(2.0),
+            asymmetry: 0.3,
+            density: None,
+        };
+        let boundary = Material {
+            material: MaterialType::Interface,
+            medium: Some(smoke.clone()),
+            ..glass
+        };
+        let outside = Media::new(fog.clone());
+        assert_eq!(outside.medium(), &fog);
+        let inside = outside.cross(&hit, &boundary, up, down);
+        assert_eq!(inside.medium(), &smoke);
+        assert_eq!(inside.cross(&hit, &boundary, down, up).medium(), &fog);
+    }
+
+    #[test]
+    fn test_transmitted_light() {
+        // light inside a body shines through its surface, whatever the material transmitting it
+        let mut scene = Scene::new(Vec3f::from(0.0));
+        scene.lights.push(Light {
+            geometry: Sphere::new(Vec3f::new(0.0, 0.0, -5.0), 1.0),
+            emission: Vec3f::from(10.0),
+        });
+        let hit = Hit::new(1.0, Vec3f::new(0.0, 0.0, 1.0), Vec3f::from(0.0), 0);
+        let wo = Vec3f::new(0.0, 0.0, 1.0);
+
+        let principled = Principled {
+            transmission: 1.0,
+            ..Default::default()
+        };
+        for material in [
+            MaterialType::Transparent,
+            MaterialType::Principled(principled),
+        ] {
+            let material = Material {
+                albedo: Vec3f::from(1.0),
+                roughness: 0.5,
+                material,
+                ..Default::default()
+            };
+            scene.objects = vec![Object {
+                geometry: Geometry::SPHERE(Sphere::new(Vec3f::new(0.0, 0.0, -10.0), 10.0)),
+                material: material.clone(),
+            }];
+
+            let media = Media::default();
+            let light = (0..100)
+                .map(|_| Renderer::sample_lights(&scene, &hit, &material, wo, &media, None))
+                .fold(Vec3f::from(0.0), |sum, light| sum + light);
+            assert!(light.x > 0.0, "{:?}", material.material);
+        }
+    }
+
+    #[test]
+    fn test_random_walk() {
+        // a thick body reflects close to the albedo it was given, up to the fit of the inversion
+        let albedo = Vec3f::new(0.8, 0.5, 0.2);
+        let subsurface = Subsurface {
+            mean_free_path: Vec3f::from(0.01),
+            asymmetry: 0.0,
+        };
+        let material = Material {
+            albedo,
+            roughness: 0.0,
+            ior: 1.0,
+            material: MaterialType::Subsurface(subsurface),
+            ..Default::default()
+        };
+        let mut scene = Scene::new(Vec3f::from(0.0));
+        scene.add(Object {
+            geometry: Geometry::SPHERE(Sphere::new(Vec3f::from(0.0), 10.0)),
+            material: material.clone(),
+        });
+
+        // entering diffusely, as the albedo inversion was fit for
+        let medium = material.interior();
+        let inward = Vec3f::new(0.0, 1.0, 0.0);
+        let n = 5000;
+        let mut reflected = Vec3f::from(0.0);
+        for _ in 0..n {
+            let direction = Onb::local_to_world(inward, cosine_weighted_hemisphere());
+            let ray = Ray::new(Vec3f::new(0.0, -9.999, 0.0), direction);
+            if let Some((hit, weight)) = Renderer::random_walk(&ray, &scene, &medium) {
+                assert!((hit.point.length() - 10.0).abs() < 1e-6);
+                reflected += weight;
+            }
+        }
+        let reflected = reflected / n as f64;
+        assert!((reflected - albedo).length() < 0.1, "{:?}", reflected);
+        assert!(reflected.x > reflected.y && reflected.y > reflected.z);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::common::*;
+use crate::material::*;
+use crate::onb::Onb;
+use crate::spectrum::*;
+use crate::vector::*;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+/// Fibers over the material scattering light at grazing angles, like the rim of cloth and velvet
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sheen {
+    pub color: Vec3f,
+    /// Alpha of the Charlie distribution, higher values spread the sheen towards the center
+    pub roughness: f64,
+}
+
+impl Default for Sheen {
+    fn default() -> Self {
+        Self {
+            color: Vec3f::from(1.0),
+            roughness: 0.5,
+        }
+    }
+}
+
+/// Smallest alpha, below it the distribution becomes too sharp to sample uniformly
+const MIN_ALPHA: f64 = 0.05;
+
+/// Resolution of the directional albedo table in cosine and roughness
+const ALBEDO_SIZE: usize = 16;
+
+// Estevez and Kulla 2017, "Production Friendly Microfacet Sheen BRDF"
+/// Charlie distribution of the fiber normals, peaking perpendicular to the surface normal
+fn charlie(cos_h: f64, alpha: f64) -> f64 {
+    let inv = 1.0 / alpha;
+    let sin2 = f64::max(1.0 - cos_h * cos_h, 0.0);
+    (2.0 + inv) * f64::powf(sin2, 0.5 * inv) / (2.0 * PI)
+}
+
+// Neubelt and Pettineo 2013, "Crafting a Next-Gen Material Pipeline for The Order: 1886"
+/// Untinted velvet BRDF, the Charlie distribution with the visibility term of Ashikhmin
+pub(crate) fn sheen_lobe(normal: Vec3f, wo: Vec3f, wi: Vec3f, roughness: f64) -> f64 {
+    let cos_o = Vec3f::dot(normal, wo);
+    let cos_i = Vec3f::dot(normal, wi);
+
+    if cos_o <= 0.0 || cos_i <= 0.0 {
+        return 0.0;
+    }
+
+    let halfway = Vec3f::normalize(wo + wi);
+    let alpha = roughness.clamp(MIN_ALPHA, 1.0);
+    let visibility = 1.0 / (4.0 * (cos_i + cos_o - cos_i * cos_o));
+
+    charlie(Vec3f::dot(normal, halfway), alpha) * visibility
+}
+
+/// The lobe has no practical inverse, so directions are sampled uniformly over the hemisphere
+pub(crate) fn sheen_pdf(normal: Vec3f, wi: Vec3f) -> f64 {
+    if Vec3f::dot(normal, wi) > 0.0 {
+        1.0 / (2.0 * PI)
+    } else {
+        0.0
+    }
+}
+
+pub(crate) fn sample_sheen(normal: Vec3f) -> (Vec3f, f64) {
+    (Onb::local_to_world(normal, uniform_hemisphere()), 1.0 / (2.0 * PI))
+}
+
+/// Fraction of the light the untinted lobe reflects for 'cos_o', interpolated from a table
+pub(crate) fn sheen_albedo(cos_o: f64, roughness: f64) -> f64 {
+    static TABLE: OnceLock<Vec<f64>> = OnceLock::new();
+
+    let table = TABLE.get_or_init(|| {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let (steps_theta, steps_phi) = (64, 32);
+        let mut table = Vec::with_capacity(ALBEDO_SIZE * ALBEDO_SIZE);
+
+        for j in 0..ALBEDO_SIZE {
+            let roughness = roughness_node(j);
+            for i in 0..ALBEDO_SIZE {
+                let cos_o = f64::max(cosine_node(i), 1e-4);
+                let wo = Vec3f::new(f64::sqrt(1.0 - cos_o * cos_o), 0.0, cos_o);
+
+                // midpoint rule over cos and the half circle of phi, mirrored by symmetry
+                let mut sum = 0.0;
+                for t in 0..steps_theta {
+                    let cos_i = (t as f64 + 0.5) / steps_theta as f64;
+                    let sin_i = f64::sqrt(1.0 - cos_i * cos_i);
+                    for p in 0..steps_phi {
+                        let phi = (p as f64 + 0.5) / steps_phi as f64 * PI;
+                        let wi = Vec3f::new(sin_i * phi.cos(), sin_i * phi.sin(), cos_i);
+                        sum += sheen_lobe(normal, wo, wi, roughness) * cos_i;
+                    }
+                }
+                table.push(sum * 2.0 * PI / (steps_theta * steps_phi) as f64);
+            }
+        }
+        table
+    });
+
+    let scale = (ALBEDO_SIZE - 1) as f64;
+    let x = cos_o.clamp(0.0, 1.0) * scale;
+    let y = (roughness.clamp(MIN_ALPHA, 1.0) - MIN_ALPHA) / (1.0 - MIN_ALPHA) * scale;
+    let (i, j) = (
+        usize::min(x as usize, ALBEDO_SIZE - 2),
+        usize::min(y as usize, ALBEDO_SIZE - 2),
+    );
+    let (fx, fy) = (x - i as f64, y - j as f64);
+
+    let at = |i: usize, j: usize| table[j * ALBEDO_SIZE + i];
+    let bottom = at(i, j) * (1.0 - fx) + at(i + 1, j) * fx;
+    let top = at(i, j + 1) * (1.0 - fx) + at(i + 1, j + 1) * fx;
+    bottom * (1.0 - fy) + top * fy
+}
+
+fn cosine_node(i: usize) -> f64 {
+    i as f64 / (ALBEDO_SIZE - 1) as f64
+}
+
+fn roughness_node(j: usize) -> f64 {
+    MIN_ALPHA + (1.0 - MIN_ALPHA) * j as f64 / (ALBEDO_SIZE - 1) as f64
+}
+
+fn max_component(v: Vec3f) -> f64 {
+    f64::max(v.x, f64::max(v.y, v.z))
+}
+
+// The base is evaluated beneath the fibers, keeping the light the sheen did not reflect,
+// after the albedo scaling of Kulla and Conty 2017
+impl Sheen {
+    /// Sheen with the color replaced by its spectrum at 'lambda' nanometers
+    pub fn at_wavelength(&self, lambda: f64) -> Sheen {
+        Sheen {
+            color: Vec3f::from(rgb_to_spectrum(self.color, lambda)),
+            ..*self
+        }
+    }
+
+    /// Fraction of the light reaching the base for light arriving at 'cos_theta'
+    fn transmittance(&self, cos_theta: f64) -> f64 {
+        1.0 - max_component(self.color) * sheen_albedo(cos_theta, self.roughness)
+    }
+
+    /// Probability of sampling the sheen instead of the base, by their share of reflected light
+    fn probability(&self, base: &Material, cos_o: f64) -> f64 {
+        let mean = |v: Vec3f| (v.x + v.y + v.z) / 3.0;
+        let sheen = mean(self.color) * sheen_albedo(cos_o, self.roughness);
+        let sum = sheen + self.transmittance(cos_o) * mean(base.albedo);
+
+        if sum > 0.0 {
+            sheen / sum
+        } else {
+            1.0
+        }
+    }
+
+    /// Light arriving from below the surface sees the base only
+    pub(crate) fn bsdf(&self, base: &Material, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        let cos_o = Vec3f::dot(normal, wo);
+        if cos_o <= 0.0 {
+            return base.bsdf(normal, wo, wi);
+        }
+
+        self.color * sheen_lobe(normal, wo, wi, self.roughness)
+            + base.bsdf(normal, wo, wi) * self.transmittance(cos_o)
+    }
+
+    pub(crate) fn pdf(&self, base: &Material, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        let cos_o = Vec3f::dot(normal, wo);
+        if cos_o <= 0.0 {
+            return base.pdf(normal, wo, wi);
+        }
+
+        let p = self.probability(base, cos_o);
+        p * sheen_pdf(normal, wi) + (1.0 - p) * base.pdf(normal, wo, wi)
+    }
+
+    pub(crate) fn sample_f(&self, base: &Material, normal: Vec3f, wo: Vec3f) -> (Vec3f, f64) {
+        let cos_o = Vec3f::dot(normal, wo);
+        if cos_o <= 0.0 {
+            return base.sample_f(normal, wo);
+        }
+
+        let wi = if rand::thread_rng().gen_range(0.0..1.0) < self.probability(base, cos_o) {
+            sample_sheen(normal).0
+        } else {
+            base.sample_f(normal, wo).0
+        };
+
+        (wi, self.pdf(base, normal, wo, wi))
+    }
+
+    pub(crate) fn sample(&self, base: &Material, normal: Vec3f, wo: Vec3f) -> (Vec3f, Vec3f) {
+        let (wi, pdf) = self.sample_f(base, normal, wo);
+        if pdf == 0.0 {
+            return (wi, Vec3f::from(0.0));
+        }
+        let cos_theta = Vec3f::dot(normal, wi).abs();
+        (wi, self.bsdf(base, normal, wo, wi) * cos_theta / pdf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sheen::*;
+
+    fn albedo(material: &Material, wo: Vec3f) -> Vec3f {
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let n = 40000;
+        let mut sum = Vec3f::from(0.0);
+        for _ in 0..n {
+            sum += material.sample(normal, wo).1;
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn test_sheen_albedo() {
+        // the table agrees with a monte carlo estimate of the lobe
+        let velvet = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.4,
+            material: MaterialType::Sheen,
+            ..Default::default()
+        };
+        for cos_o in [0.2, 0.6, 1.0] {
+            let wo = Vec3f::new(f64::sqrt(1.0 - cos_o * cos_o), 0.0, cos_o);
+            let estimate = albedo(&velvet, wo).x;
+            let table = sheen_albedo(cos_o, velvet.roughness);
+            assert!(table > 0.0 && table < 1.0);
+            assert!((estimate - table).abs() < 0.02, "{} {} {}", cos_o, estimate, table);
+        }
+    }
+
+    #[test]
+    fn test_sheen_retroreflection() {
+        // velvet reflects more towards grazing angles than at normal incidence
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let grazing = Vec3f::new(0.95, 0.0, 0.1).normalize();
+        let up = Vec3f::new(0.0, 0.0, 1.0);
+        assert!(sheen_lobe(normal, grazing, grazing, 0.3) > sheen_lobe(normal, up, up, 0.3));
+    }
+
+    #[test]
+    fn test_sheen_energy() {
+        // a white sheen over a white diffuse stays below one
+        let cloth = Material {
+            albedo: Vec3f::from(1.0),
+            sheen: Some(Sheen::default()),
+            ..Default::default()
+        };
+        for wo in [
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(0.5, 0.0, 1.0).normalize(),
+            Vec3f::new(1.0, 0.0, 0.1).normalize(),
+        ] {
+            let albedo = albedo(&cloth, wo);
+            assert!(albedo.x > 0.9 && albedo.x < 1.02, "{:?} {:?}", wo, albedo);
+        }
+    }
+
+    #[test]
+    fn test_sheen_pdf() {
+        // sampled densities agree with the density evaluated for the same direction
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::new(0.3, 0.2, 1.0).normalize();
+        let sheen = Sheen {
+            color: Vec3f::new(0.9, 0.3, 0.3),
+            roughness: 0.3,
+        };
+        let cloth = Material {
+            albedo: Vec3f::from(1.0),
+            roughness: 0.4,
+            material: MaterialType::OrenNayar,
+            sheen: Some(sheen),
+            ..Default::default()
+        };
+
+        for _ in 0..100 {
+            let (wi, pdf) = cloth.sample_f(normal, wo);
+            assert!((pdf - cloth.pdf(normal, wo, wi)).abs() <= 1e-9 * pdf);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::vector::*;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Shortest sampled wavelength in nanometers
+pub const LAMBDA_MIN: f64 = 380.0;
+/// Longest sampled wavelength in nanometers
+pub const LAMBDA_MAX: f64 = 780.0;
+
+/// Wavelengths of the Fraunhofer d, F and C lines in nanometers
+const LAMBDA_D: f64 = 587.56;
+const LAMBDA_F: f64 = 486.13;
+const LAMBDA_C: f64 = 656.27;
+
+/// Wavelength dependent index of refraction, wavelengths in micrometers
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Dispersion {
+    /// n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    /// n² = 1 + Σ b λ² / (λ² - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7 crown glass
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    /// Cauchy fit through 'ior' at the d line with the given Abbe number
+    pub fn from_abbe(ior: f64, abbe: f64) -> Self {
+        let inverse_square = |lambda: f64| 1.0 / (lambda * 1e-3 * lambda * 1e-3);
+        let b = (ior - 1.0) / (abbe * (inverse_square(LAMBDA_F) - inverse_square(LAMBDA_C)));
+        let a = ior - b * inverse_square(LAMBDA_D);
+        Dispersion::Cauchy { a, b }
+    }
+
+    /// Index of refraction at 'lambda' nanometers
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let l2 = (lambda * 1e-3) * (lambda * 1e-3);
+        match *self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                f64::sqrt(1.0 + sum)
+            }
+        }
+    }
+}
+
+/// Uniformly sampled wavelength in nanometers, with density 1 / (LAMBDA_MAX - LAMBDA_MIN)
+pub fn sample_wavelength() -> f64 {
+    rand::thread_rng().gen_range(LAMBDA_MIN..LAMBDA_MAX)
+}
+
+// Smits 1999, "An RGB-to-Spectrum Conversion for Reflectances"
+// Ten bins spaced evenly from 380 to 720 nm
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Value of the Smits basis spectrum at 'lambda', clamped to the table range
+fn smits(table: &[f64; 10], lambda: f64) -> f64 {
+    let x = ((lambda - 380.0) / (720.0 - 380.0) * 9.0).clamp(0.0, 9.0);
+    let i = usize::min(x as usize, 8);
+    let t = x - i as f64;
+    table[i] * (1.0 - t) + table[i + 1] * t
+}
+
+/// Smooth spectrum matching linear 'rgb', evaluated at 'lambda' nanometers
+pub fn rgb_to_spectrum(rgb: Vec3f, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let s = |table: &[f64; 10]| smits(table, lambda);
+
+    if r <= g && r <= b {
+        r * s(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * s(&SMITS_CYAN) + (b - g) * s(&SMITS_BLUE)
+            } else {
+                (b - r) * s(&SMITS_CYAN) + (g - b) * s(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * s(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * s(&SMITS_MAGENTA) + (b - r) * s(&SMITS_BLUE)
+            } else {
+                (b - g) * s(&SMITS_MAGENTA) + (r - b) * s(&SMITS_RED)
+            }
+    } else {
+        b * s(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * s(&SMITS_YELLOW) + (g - r) * s(&SMITS_GREEN)
+            } else {
+                (g - b) * s(&SMITS_YELLOW) + (r - g) * s(&SMITS_RED)
+            }
+    }
+}
+
+/// Returns 'rgb' unchanged, or as the gray value of its spectrum at 'wavelength'
+pub fn at_wavelength(rgb: Vec3f, wavelength: Option<f64>) -> Vec3f {
+    match wavelength {
+        Some(lambda) => Vec3f::from(rgb_to_spectrum(rgb, lambda)),
+        None => rgb,
+    }
+}
+
+/// Piecewise Gaussian with separate widths left and right of the mean
+fn lobe(lambda: f64, mean: f64, left: f64, right: f64) -> f64 {
+    let t = (lambda - mean) / if lambda < mean { left } else { right };
+    f64::exp(-0.5 * t * t)
+}
+
+// Wyman et al. 2013, "Simple Analytic Approximations to the CIE XYZ Color Matching Functions"
+/// CIE 1931 color matching functions at 'lambda' nanometers
+pub fn cie_xyz(lambda: f64) -> Vec3f {
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    Vec3f::new(x, y, z)
+}
+
+/// CIE XYZ to linear sRGB (D65)
+pub fn xyz_to_srgb(xyz: Vec3f) -> Vec3f {
+    let matrix = Mat3f::from([
+        3.2406, -1.5372, -0.4986, //
+        -0.9689, 1.8758, 0.0415, //
+        0.0557, -0.2040, 1.0570,
+    ]);
+    matrix * xyz
+}
+
+/// Average linear sRGB response over the sampled range, used to keep white spectra white
+fn white_balance() -> Vec3f {
+    static WHITE: OnceLock<Vec3f> = OnceLock::new();
+
+    *WHITE.get_or_init(|| {
+        let steps = 4000;
+        let mut sum = Vec3f::from(0.0);
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + (i as f64 + 0.5) / steps as f64 * (LAMBDA_MAX - LAMBDA_MIN);
+            sum += xyz_to_srgb(cie_xyz(lambda));
+        }
+        sum / steps as f64
+    })
+}
+
+/// Linear sRGB estimate of 'radiance' carried at the uniformly sampled 'lambda'
+/// Single samples may have negative channels, their average converges to the color
+pub fn spectrum_to_rgb(radiance: f64, lambda: f64) -> Vec3f {
+    xyz_to_srgb(cie_xyz(lambda)) / white_balance() * radiance
+}
+
+#[cfg(test)]
+mod test {
+    use crate::spectrum::*;
+
+    #[test]
+    fn test_dispersion() {
+        // N-BK7 at the d line
+        let n = Dispersion::BK7.ior(LAMBDA_D);
+        assert!((n - 1.5168).abs() < 1e-4, "{}", n);
+
+        // blue bends more than red
+        assert!(Dispersion::BK7.ior(450.0) > Dispersion::BK7.ior(650.0));
+
+        let cauchy = Dispersion::from_abbe(1.5168, 64.17);
+        assert!((cauchy.ior(LAMBDA_D) - 1.5168).abs() < 1e-9);
+        let abbe = (cauchy.ior(LAMBDA_D) - 1.0) / (cauchy.ior(LAMBDA_F) - cauchy.ior(LAMBDA_C));
+        assert!((abbe - 64.17).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rgb_to_spectrum() {
+        for lambda in [400.0, 500.0, 600.0, 700.0] {
+            let white = rgb_to_spectrum(Vec3f::from(1.0), lambda);
+            assert!((white - 1.0).abs() < 1e-3);
+            let gray = rgb_to_spectrum(Vec3f::from(0.5), lambda);
+            assert!((gray - 0.5).abs() < 1e-3);
+        }
+
+        let red = Vec3f::new(1.0, 0.0, 0.0);
+        assert!(rgb_to_spectrum(red, 650.0) > 0.9);
+        assert!(rgb_to_spectrum(red, 450.0) < 0.1);
+
+        assert_eq!(at_wavelength(red, None), red);
+        assert_eq!(
+            at_wavelength(red, Some(450.0)).x,
+            at_wavelength(red, Some(450.0)).z
+        );
+    }
+
+    #[test]
+    fn test_spectrum_to_rgb() {
+        // estimate of a spectrum converges to the color it was made from
+        let n = 4000;
+        for rgb in [
+            Vec3f::from(1.0),
+            Vec3f::new(0.8, 0.2, 0.1),
+            Vec3f::new(0.1, 0.3, 0.9),
+        ] {
+            let mut sum = Vec3f::from(0.0);
+            for i in 0..n {
+                let lambda = LAMBDA_MIN + (i as f64 + 0.5) / n as f64 * (LAMBDA_MAX - LAMBDA_MIN);
+                sum += spectrum_to_rgb(rgb_to_spectrum(rgb, lambda), lambda);
+            }
+            let estimate = sum / n as f64;
+            assert!((estimate - rgb).length() < 0.06, "{:?} {:?}", rgb, estimate);
+        }
+
+        // the peak of the green response is green
+        let green = spectrum_to_rgb(1.0, 530.0);
+        assert!(green.y > green.x && green.y > green.z);
+    }
+}
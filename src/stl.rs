@@ -0,0 +1,218 @@
+use crate::mesh::*;
+use crate::vector::*;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read};
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("STL: {}", message))
+}
+
+/// Binary files start with an 80 byte header followed by the triangle count
+const HEADER_SIZE: usize = 84;
+
+/// Normal, three vertices and attribute byte count
+const TRIANGLE_SIZE: usize = 50;
+
+/// STL stores every triangle separately, identical positions are merged so normals can be smoothed
+#[derive(Default)]
+struct Welder {
+    mesh: Mesh,
+    indices: HashMap<[u64; 3], usize>,
+}
+
+impl Welder {
+    fn add_vertex(&mut self, vertex: Vec3f) -> usize {
+        // -0.0 and 0.0 are the same position
+        let key = [vertex.x, vertex.y, vertex.z].map(|x| (x + 0.0).to_bits());
+        let mesh = &mut self.mesh;
+        *self.indices.entry(key).or_insert_with(|| {
+            mesh.vertices.push(vertex);
+            mesh.vertices.len() - 1
+        })
+    }
+
+    fn add_triangle(&mut self, triangle: [Vec3f; 3]) {
+        let vertices = triangle.map(|v| self.add_vertex(v));
+
+        // skip triangles collapsed by welding
+        if vertices[0] != vertices[1] && vertices[1] != vertices[2] && vertices[0] != vertices[2] {
+            self.mesh.faces.push(Face::new(vertices));
+        }
+    }
+
+    fn finish(mut self) -> io::Result<Mesh> {
+        if self.mesh.faces.is_empty() {
+            return Err(invalid_data("no triangles".to_string()));
+        }
+
+        self.mesh.groups.push("default".to_string());
+        self.mesh.build();
+        Ok(self.mesh)
+    }
+}
+
+/// Binary files have a size that matches the triangle count in the header
+fn is_binary(data: &[u8]) -> bool {
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+
+    let count = u32::from_le_bytes(data[80..84].try_into().unwrap()) as usize;
+    data.len() == HEADER_SIZE + count * TRIANGLE_SIZE
+}
+
+fn read_binary(data: &[u8]) -> io::Result<Mesh> {
+    let mut welder = Welder::default();
+
+    let float = |bytes: &[u8], offset: usize| {
+        f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as f64
+    };
+
+    for chunk in data[HEADER_SIZE..].chunks_exact(TRIANGLE_SIZE) {
+        // the stored facet normal is skipped, it is recomputed from the vertices
+        let vertex = |k: usize| {
+            let offset = 12 * (k + 1);
+            Vec3f::new(
+                float(chunk, offset),
+                float(chunk, offset + 4),
+                float(chunk, offset + 8),
+            )
+        };
+        welder.add_triangle([vertex(0), vertex(1), vertex(2)]);
+    }
+
+    welder.finish()
+}
+
+fn read_ascii(data: &[u8]) -> io::Result<Mesh> {
+    let text = std::str::from_utf8(data).map_err(|_| invalid_data("invalid text".to_string()))?;
+
+    let mut welder = Welder::default();
+    let mut facet: Vec<Vec3f> = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.as_slice() {
+            ["vertex", x, y, z] => {
+                let parse = |token: &str| {
+                    token.parse::<f64>().map_err(|_| {
+                        invalid_data(format!("line {}: invalid number {:?}", number + 1, token))
+                    })
+                };
+                facet.push(Vec3f::new(parse(x)?, parse(y)?, parse(z)?));
+            }
+            ["endfacet"] => {
+                if facet.len() != 3 {
+                    let message = format!(
+                        "line {}: facet needs 3 vertices, got {}",
+                        number + 1,
+                        facet.len()
+                    );
+                    return Err(invalid_data(message));
+                }
+                welder.add_triangle([facet[0], facet[1], facet[2]]);
+                facet.clear();
+            }
+            ["vertex", ..] => {
+                let message = format!("line {}: vertex needs 3 values", number + 1);
+                return Err(invalid_data(message));
+            }
+            _ => (),
+        }
+    }
+
+    welder.finish()
+}
+
+impl Mesh {
+    /// Loads STL file
+    pub fn from_stl(path: &str) -> io::Result<Mesh> {
+        let file = File::open(path)?;
+        Self::read_stl(BufReader::new(file))
+    }
+
+    /// Detects whether the data is ASCII or binary, facet normals are ignored
+    pub fn read_stl<R: Read>(mut reader: R) -> io::Result<Mesh> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        // binary headers may also start with "solid", so the size is checked first
+        if is_binary(&data) {
+            read_binary(&data)
+        } else if data.trim_ascii_start().starts_with(b"solid") {
+            read_ascii(&data)
+        } else {
+            Err(invalid_data("neither ASCII nor binary".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::stl::*;
+
+    const ASCII: &str = "solid square
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 1 0
+      vertex 0 1 -0
+    endloop
+  endfacet
+endsolid square
+";
+
+    #[test]
+    fn test_ascii() {
+        let mesh = Mesh::read_stl(ASCII.as_bytes()).unwrap();
+        assert_eq!(mesh.vertices().len(), 4);
+
+        let faces: Vec<[usize; 3]> = mesh.faces().iter().map(|f| f.vertices).collect();
+        assert_eq!(faces, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn test_binary() {
+        let mut data = b"solid but actually binary".to_vec();
+        data.resize(80, 0);
+        data.extend_from_slice(&2u32.to_le_bytes());
+
+        let triangles = [
+            [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+            [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+        ];
+        for triangle in triangles {
+            data.extend_from_slice(&[0; 12]);
+            for x in triangle.iter().flatten() {
+                data.extend_from_slice(&x.to_le_bytes());
+            }
+            data.extend_from_slice(&[0; 2]);
+        }
+
+        let mesh = Mesh::read_stl(data.as_slice()).unwrap();
+        assert_eq!(mesh.vertices().len(), 4);
+        assert_eq!(mesh.faces()[1].vertices, [0, 2, 3]);
+
+        let normal = mesh.faces()[0].normals.unwrap()[0];
+        assert_eq!(mesh.normals()[normal], Vec3f::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Mesh::read_stl("solid empty\nendsolid empty\n".as_bytes()).is_err());
+        assert!(Mesh::read_stl(ASCII.replace("vertex 1 0 0", "vertex 1 0").as_bytes()).is_err());
+        assert!(Mesh::read_stl(ASCII.replace("vertex 1 0 0\n", "").as_bytes()).is_err());
+        assert!(Mesh::read_stl("ply\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::medium::Medium;
+use crate::spectrum::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+
+/// Light scattering beneath a dielectric surface, like skin, marble and wax
+// Chiang, Kutz and Burley 2016, "Practical and Controllable Subsurface Scattering for
+// Production Path Tracing", albedo is the color after all the scattering inside
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subsurface {
+    /// Average distance light travels inside between collisions, per channel
+    pub mean_free_path: Vec3f,
+    /// Henyey-Greenstein asymmetry of the scattering inside
+    pub asymmetry: f64,
+}
+
+impl Default for Subsurface {
+    fn default() -> Self {
+        Self {
+            mean_free_path: Vec3f::from(0.1),
+            asymmetry: 0.0,
+        }
+    }
+}
+
+/// Single scattering albedo whose multiple scattering reflects 'albedo' in total
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let a = albedo.clamp(0.0, 1.0);
+    let s = 4.09712 + 4.20863 * a - f64::sqrt(9.59217 + 41.6808 * a + 17.7126 * a * a);
+    1.0 - s * s
+}
+
+impl Subsurface {
+    /// Subsurface with the mean free path replaced by its spectrum at 'lambda' nanometers
+    pub fn at_wavelength(&self, lambda: f64) -> Subsurface {
+        Subsurface {
+            mean_free_path: Vec3f::from(rgb_to_spectrum(self.mean_free_path, lambda)),
+            ..*self
+        }
+    }
+
+    /// Medium inside a surface of color 'albedo', traversed by a random walk
+    pub fn medium(&self, albedo: Vec3f) -> Medium {
+        let extinction = Vec3f::new(
+            1.0 / self.mean_free_path.x.max(1e-6),
+            1.0 / self.mean_free_path.y.max(1e-6),
+            1.0 / self.mean_free_path.z.max(1e-6),
+        );
+        let scattering = extinction
+            * Vec3f::new(
+                single_scattering_albedo(albedo.x),
+                single_scattering_albedo(albedo.y),
+                single_scattering_albedo(albedo.z),
+            );
+
+        Medium {
+            absorption: extinction - scattering,
+            scattering,
+            asymmetry: self.asymmetry,
+            density: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::subsurface::*;
+
+    #[test]
+    fn test_single_scattering_albedo() {
+        assert!(single_scattering_albedo(0.0).abs() < 1e-4);
+        assert!((single_scattering_albedo(1.0) - 1.0).abs() < 1e-4);
+
+        // scattering many times loses more, so single scattering keeps more than the total
+        let mut last = 0.0;
+        for i in 1..10 {
+            let albedo = i as f64 / 10.0;
+            let single = single_scattering_albedo(albedo);
+            assert!(single > albedo && single > last);
+            last = single;
+        }
+    }
+
+    #[test]
+    fn test_subsurface_medium() {
+        let subsurface = Subsurface {
+            mean_free_path: Vec3f::new(1.0, 0.5, 0.25),
+            asymmetry: 0.2,
+        };
+        let medium = subsurface.medium(Vec3f::new(0.8, 0.5, 0.2));
+
+        let extinction = medium.extinction();
+        assert!((extinction - Vec3f::new(1.0, 2.0, 4.0)).length() < 1e-12);
+        let albedo = medium.scattering / extinction;
+        assert!(albedo.x > albedo.y && albedo.y > albedo.z);
+        assert_eq!(medium.asymmetry, 0.2);
+    }
+}
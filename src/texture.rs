@@ -0,0 +1,419 @@
+use crate::geometry::Hit;
+use crate::procedural::Procedural;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// How texture coordinates outside [0, 1] are mapped onto the image
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Wrap {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+/// Decoded image with float channels
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3f>,
+    /// 8 and 16 bit images store sRGB encoded colors, float images linear ones
+    srgb: bool,
+}
+
+/// Images loaded so far, keyed by file path
+static IMAGE_CACHE: OnceLock<Mutex<HashMap<String, Arc<Image>>>> = OnceLock::new();
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        f64::powf((c + 0.055) / 1.055, 2.4)
+    }
+}
+
+/// Maps integer texel coordinate into [0, size)
+fn wrap(i: i64, size: usize, mode: Wrap) -> usize {
+    let size = size as i64;
+    let i = match mode {
+        Wrap::Repeat => i.rem_euclid(size),
+        Wrap::Clamp => i.clamp(0, size - 1),
+        Wrap::Mirror => {
+            let m = i.rem_euclid(2 * size);
+            if m < size {
+                m
+            } else {
+                2 * size - 1 - m
+            }
+        }
+    };
+    i as usize
+}
+
+impl Image {
+    /// Panics if 'pixels' does not match the size
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3f>, srgb: bool) -> Self {
+        assert_eq!(pixels.len(), width * height, "Image size does not match");
+        Self {
+            width,
+            height,
+            pixels,
+            srgb,
+        }
+    }
+
+    /// Returns shared image, the file is only decoded the first time it is requested
+    pub fn load(path: &str) -> io::Result<Arc<Image>> {
+        let cache = IMAGE_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+
+        if let Some(image) = cache.lock().unwrap().get(path) {
+            return Ok(Arc::clone(image));
+        }
+
+        let decoded =
+            image::open(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let srgb = !matches!(
+            decoded,
+            image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+        );
+
+        let rgb = decoded.into_rgb32f();
+        let pixels = rgb
+            .pixels()
+            .map(|p| Vec3f::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+
+        let image = Arc::new(Image::new(
+            rgb.width() as usize,
+            rgb.height() as usize,
+            pixels,
+            srgb,
+        ));
+        cache
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), Arc::clone(&image));
+        Ok(image)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn texel(&self, x: i64, y: i64, mode: Wrap) -> Vec3f {
+        let x = wrap(x, self.width, mode);
+        let y = wrap(y, self.height, mode);
+        self.pixels[y * self.width + x]
+    }
+}
+
+/// Pixels are left out, they would flood the output
+impl fmt::Debug for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Image")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("srgb", &self.srgb)
+            .finish()
+    }
+}
+
+/// Image mapped onto a surface with texture coordinates
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    image: Arc<Image>,
+    path: String,
+    wrap: Wrap,
+    filter: Filter,
+    /// Number of repetitions along u and v
+    scale: Vec2f,
+    /// Channel used for scalar parameters
+    channel: usize,
+}
+
+/// Source of a material parameter that varies over the surface
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "TextureDesc", into = "TextureDesc")]
+pub enum Texture {
+    Image(ImageTexture),
+    Procedural(Procedural),
+}
+
+/// Texture as written in the scene file, an image path, an image with options or a pattern
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum TextureDesc {
+    Path(String),
+    Image {
+        path: String,
+        #[serde(default)]
+        wrap: Wrap,
+        #[serde(default)]
+        filter: Filter,
+        #[serde(default = "one")]
+        scale: Vec2f,
+        #[serde(default)]
+        channel: usize,
+    },
+    Procedural(Procedural),
+}
+
+fn one() -> Vec2f {
+    Vec2f::from(1.0)
+}
+
+impl TryFrom<TextureDesc> for Texture {
+    type Error = io::Error;
+
+    fn try_from(desc: TextureDesc) -> io::Result<Self> {
+        let (path, wrap, filter, scale, channel) = match desc {
+            TextureDesc::Path(path) => (path, Wrap::default(), Filter::default(), one(), 0),
+            TextureDesc::Image {
+                path,
+                wrap,
+                filter,
+                scale,
+                channel,
+            } => (path, wrap, filter, scale, channel),
+            TextureDesc::Procedural(procedural) => return Ok(Texture::Procedural(procedural)),
+        };
+
+        if channel > 2 {
+            let message = format!("texture channel {} out of range", channel);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+
+        let mut texture = ImageTexture::new(Image::load(&path)?);
+        texture.path = path;
+        texture.wrap = wrap;
+        texture.filter = filter;
+        texture.scale = scale;
+        texture.channel = channel;
+        Ok(Texture::Image(texture))
+    }
+}
+
+impl From<Texture> for TextureDesc {
+    fn from(texture: Texture) -> Self {
+        match texture {
+            Texture::Image(texture) => TextureDesc::Image {
+                path: texture.path,
+                wrap: texture.wrap,
+                filter: texture.filter,
+                scale: texture.scale,
+                channel: texture.channel,
+            },
+            Texture::Procedural(procedural) => TextureDesc::Procedural(procedural),
+        }
+    }
+}
+
+impl Texture {
+    /// Linear color at the hit point
+    pub fn color(&self, hit: &Hit) -> Vec3f {
+        match self {
+            Texture::Image(texture) => texture.color(hit.uv),
+            Texture::Procedural(procedural) => procedural.color(hit),
+        }
+    }
+
+    /// Scalar parameter at the hit point, the selected channel of images or red of patterns
+    pub fn value(&self, hit: &Hit) -> f64 {
+        match self {
+            Texture::Image(texture) => texture.value(hit.uv),
+            Texture::Procedural(procedural) => procedural.color(hit).x,
+        }
+    }
+
+    /// Stored color at the hit point, not color converted, for normal maps
+    pub fn vector(&self, hit: &Hit) -> Vec3f {
+        match self {
+            Texture::Image(texture) => texture.vector(hit.uv),
+            Texture::Procedural(procedural) => procedural.color(hit),
+        }
+    }
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<Image>) -> Self {
+        Self {
+            image,
+            path: String::new(),
+            wrap: Wrap::default(),
+            filter: Filter::default(),
+            scale: Vec2f::from(1.0),
+            channel: 0,
+        }
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Returns stored value at 'uv', (0, 0) is the bottom left corner of the image
+    fn lookup(&self, uv: Vec2f) -> Vec3f {
+        let image = &self.image;
+        let x = uv.x * self.scale.x * image.width as f64;
+        let y = (1.0 - uv.y * self.scale.y) * image.height as f64;
+
+        match self.filter {
+            Filter::Nearest => image.texel(x.floor() as i64, y.floor() as i64, self.wrap),
+            Filter::Bilinear => {
+                // texel centers are at half-integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = image.texel(x0, y0, self.wrap) * (1.0 - fx)
+                    + image.texel(x0 + 1, y0, self.wrap) * fx;
+                let bottom = image.texel(x0, y0 + 1, self.wrap) * (1.0 - fx)
+                    + image.texel(x0 + 1, y0 + 1, self.wrap) * fx;
+
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
+    }
+
+    /// Linear color at 'uv'
+    pub fn color(&self, uv: Vec2f) -> Vec3f {
+        let c = self.lookup(uv);
+        if self.image.srgb {
+            Vec3f::new(
+                srgb_to_linear(c.x),
+                srgb_to_linear(c.y),
+                srgb_to_linear(c.z),
+            )
+        } else {
+            c
+        }
+    }
+
+    /// Stored value of the selected channel at 'uv', not color converted
+    pub fn value(&self, uv: Vec2f) -> f64 {
+        self.lookup(uv)[self.channel]
+    }
+
+    /// Stored color at 'uv', not color converted
+    pub fn vector(&self, uv: Vec2f) -> Vec3f {
+        self.lookup(uv)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::texture::*;
+
+    /// 2x2 linear image, black and white on the top row, red and green below
+    fn texture() -> ImageTexture {
+        let pixels = vec![
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(1.0, 1.0, 1.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+        ];
+        ImageTexture::new(Arc::new(Image::new(2, 2, pixels, false)))
+    }
+
+    #[test]
+    fn test_nearest() {
+        let texture = texture().with_filter(Filter::Nearest);
+        assert_eq!(texture.color(Vec2f::new(0.25, 0.75)), Vec3f::from(0.0));
+        assert_eq!(texture.color(Vec2f::new(0.75, 0.75)), Vec3f::from(1.0));
+        assert_eq!(
+            texture.color(Vec2f::new(0.25, 0.25)),
+            Vec3f::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(texture.value(Vec2f::new(0.75, 0.25)), 0.0);
+    }
+
+    #[test]
+    fn test_bilinear() {
+        let texture = texture().with_wrap(Wrap::Clamp);
+
+        // texel centers return the texel
+        assert_eq!(texture.color(Vec2f::new(0.75, 0.75)), Vec3f::from(1.0));
+
+        // halfway between all four texels
+        let c = texture.color(Vec2f::new(0.5, 0.5));
+        assert_eq!(c, Vec3f::new(0.5, 0.5, 0.25));
+
+        // clamped at the border
+        assert_eq!(texture.color(Vec2f::new(0.0, 1.0)), Vec3f::from(0.0));
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap(-1, 4, Wrap::Repeat), 3);
+        assert_eq!(wrap(9, 4, Wrap::Repeat), 1);
+        assert_eq!(wrap(-1, 4, Wrap::Clamp), 0);
+        assert_eq!(wrap(9, 4, Wrap::Clamp), 3);
+        assert_eq!(wrap(-1, 4, Wrap::Mirror), 0);
+        assert_eq!(wrap(5, 4, Wrap::Mirror), 2);
+        assert_eq!(wrap(8, 4, Wrap::Mirror), 0);
+
+        let texture = texture().with_filter(Filter::Nearest);
+        let repeated = texture.color(Vec2f::new(1.25, -0.75));
+        assert_eq!(repeated, Vec3f::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_srgb() {
+        let image = Image::new(1, 1, vec![Vec3f::new(0.5, 1.0, 0.0)], true);
+        let texture = ImageTexture::new(Arc::new(image));
+        let c = texture.color(Vec2f::from(0.5));
+        assert!((c.x - 0.214).abs() < 1e-3);
+        assert_eq!(c.y, 1.0);
+        assert_eq!(texture.value(Vec2f::from(0.5)), 0.5);
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join("raytracer_texture.png");
+        let mut image = image::RgbImage::new(2, 1);
+        image.put_pixel(1, 0, image::Rgb([255, 0, 0]));
+        image.save(&path).unwrap();
+
+        let json = format!(r#"{{ "path": {:?}, "filter": "Nearest" }}"#, path);
+        let texture: Texture = serde_json::from_str(&json).unwrap();
+        let hit = Hit {
+            uv: Vec2f::new(0.75, 0.5),
+            ..Default::default()
+        };
+        assert_eq!(texture.color(&hit), Vec3f::new(1.0, 0.0, 0.0));
+
+        match texture {
+            Texture::Image(texture) => assert_eq!(texture.image.width(), 2),
+            Texture::Procedural(_) => panic!("expected image texture"),
+        }
+
+        assert!(serde_json::from_str::<Texture>(r#""missing.png""#).is_err());
+
+        let json = r#"{ "pattern": "Marble", "scale": 2 }"#;
+        let texture: Texture = serde_json::from_str(json).unwrap();
+        assert!(matches!(texture, Texture::Procedural(_)));
+    }
+}
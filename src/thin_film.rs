@@ -0,0 +1,203 @@
+use crate::material::fresnel_dielectric;
+use crate::spectrum::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Thin dielectric film on the surface, like soap or oil, whose interference causes iridescence
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThinFilm {
+    /// Thickness of the film in nanometers
+    pub thickness: f64,
+    pub ior: f64,
+    /// Wavelength in nanometers the film is evaluated at in spectral mode
+    #[serde(skip)]
+    pub wavelength: Option<f64>,
+}
+
+/// Thickness in nanometers below which the film fades into the plain interface
+const FADE_THICKNESS: f64 = 30.0;
+
+/// Phase shift of a reflection off a medium of index 'far' from one of index 'near'
+fn phase(near: f64, far: f64) -> f64 {
+    if far < near {
+        PI
+    } else {
+        0.0
+    }
+}
+
+/// Linear sRGB of the interference over the visible range, in Fourier space
+// Gaussian fits of the CIE color matching functions by Belcour and Barla
+fn sensitivity(opd: f64, shift: Vec3f) -> Vec3f {
+    let phase = 2.0 * PI * opd * 1e-9;
+    let gaussian = |val: f64, pos: f64, var: f64, shift: f64| {
+        val * f64::sqrt(2.0 * PI * var)
+            * f64::cos(pos * phase + shift)
+            * f64::exp(-phase * phase * var)
+    };
+
+    let xyz = Vec3f::new(
+        gaussian(5.4856e-13, 1.6810e+06, 4.3278e+09, shift.x)
+            + gaussian(9.7470e-14, 2.2399e+06, 4.5282e+09, shift.x),
+        gaussian(4.4201e-13, 1.7953e+06, 9.3046e+09, shift.y),
+        gaussian(5.2481e-13, 2.2084e+06, 6.6121e+09, shift.z),
+    ) / 1.0685e-7;
+
+    // the response to a zero path difference is white
+    xyz_to_srgb(xyz) / xyz_to_srgb(Vec3f::from(1.0))
+}
+
+// Belcour and Barla 2017, "A Practical Extension to Microfacet Theory for the Modeling of
+// Varying Iridescence"
+impl ThinFilm {
+    /// Reflectance for light arriving at 'cos_i' from a medium of index 'outside', over a base
+    /// of index 'inside' per channel
+    pub fn reflectance(&self, cos_i: f64, outside: f64, inside: Vec3f) -> Vec3f {
+        let cos1 = cos_i.clamp(0.0, 1.0);
+
+        if self.thickness <= 0.0 {
+            let r = |n: f64| fresnel_dielectric(cos1, n / outside);
+            return Vec3f::new(r(inside.x), r(inside.y), r(inside.z));
+        }
+
+        let t = f64::min(self.thickness / FADE_THICKNESS, 1.0);
+        let film = outside + (self.ior - outside) * t * t * (3.0 - 2.0 * t);
+
+        let sin2 = (outside / film) * (outside / film) * (1.0 - cos1 * cos1);
+        if sin2 >= 1.0 {
+            return Vec3f::from(1.0);
+        }
+        let cos2 = f64::sqrt(1.0 - sin2);
+
+        // first interface, light reflected back out of the film changes phase in reverse
+        let r12 = fresnel_dielectric(cos1, film / outside);
+        let t121 = 1.0 - r12;
+        let phi21 = PI - phase(outside, film);
+
+        // second interface
+        let r23 = Vec3f::new(
+            fresnel_dielectric(cos2, inside.x / film),
+            fresnel_dielectric(cos2, inside.y / film),
+            fresnel_dielectric(cos2, inside.z / film),
+        );
+        let phi = Vec3f::new(
+            phi21 + phase(film, inside.x),
+            phi21 + phase(film, inside.y),
+            phi21 + phase(film, inside.z),
+        );
+
+        // optical path difference in nanometers
+        let opd = 2.0 * film * self.thickness * cos2;
+
+        let channel = |r23: f64| {
+            let r123 = (r12 * r23).clamp(1e-5, 0.9999);
+            let rs = t121 * t121 * r23 / (1.0 - r123);
+            (r12 + rs, rs - t121, f64::sqrt(r123))
+        };
+        let (x, y, z) = (channel(r23.x), channel(r23.y), channel(r23.z));
+        let c0 = Vec3f::new(x.0, y.0, z.0);
+        let cm = Vec3f::new(x.1, y.1, z.1);
+        let r = Vec3f::new(x.2, y.2, z.2);
+
+        let reflectance = match self.wavelength {
+            Some(lambda) => {
+                // closed form of the series at a single wavelength
+                let series = |c0: f64, cm: f64, r: f64, phi: f64| {
+                    let cos = f64::cos(2.0 * PI * opd / lambda + phi);
+                    c0 + 2.0 * cm * (r * cos - r * r) / (1.0 - 2.0 * r * cos + r * r)
+                };
+                Vec3f::new(
+                    series(c0.x, cm.x, r.x, phi.x),
+                    series(c0.y, cm.y, r.y, phi.y),
+                    series(c0.z, cm.z, r.z, phi.z),
+                )
+            }
+            None => {
+                let mut reflectance = c0;
+                let mut amplitude = cm;
+                for m in 1..=2 {
+                    amplitude *= r;
+                    let m = m as f64;
+                    reflectance += amplitude * sensitivity(m * opd, phi * m) * 2.0;
+                }
+                reflectance
+            }
+        };
+
+        // out of gamut colors
+        Vec3f::new(
+            reflectance.x.clamp(0.0, 1.0),
+            reflectance.y.clamp(0.0, 1.0),
+            reflectance.z.clamp(0.0, 1.0),
+        )
+    }
+
+    /// Reflectance of a dielectric boundary with relative index 'eta' and air on one side
+    pub fn dielectric(&self, cos_i: f64, eta: f64) -> Vec3f {
+        if eta >= 1.0 {
+            self.reflectance(cos_i, 1.0, Vec3f::from(eta))
+        } else {
+            self.reflectance(cos_i, 1.0 / eta, Vec3f::from(1.0))
+        }
+    }
+
+    /// Film evaluated at 'lambda' nanometers
+    pub fn at_wavelength(&self, lambda: f64) -> ThinFilm {
+        ThinFilm {
+            wavelength: Some(lambda),
+            ..*self
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::thin_film::*;
+
+    fn soap(thickness: f64) -> ThinFilm {
+        ThinFilm {
+            thickness,
+            ior: 1.33,
+            wavelength: None,
+        }
+    }
+
+    #[test]
+    fn test_thin_film() {
+        // a vanishing film leaves the plain interface
+        let plain = fresnel_dielectric(0.8, 1.5);
+        let r = soap(0.0).dielectric(0.8, 1.5);
+        assert!((r - Vec3f::from(plain)).length() < 1e-12);
+
+        // interference colors the reflection and shifts with the thickness
+        let thin = soap(300.0).reflectance(1.0, 1.0, Vec3f::from(1.0));
+        let thick = soap(400.0).reflectance(1.0, 1.0, Vec3f::from(1.0));
+        let spread =
+            |c: Vec3f| f64::max(c.x, f64::max(c.y, c.z)) - f64::min(c.x, f64::min(c.y, c.z));
+        assert!(spread(thin) > 0.02, "{:?}", thin);
+        assert!((thin - thick).length() > 0.02);
+    }
+
+    #[test]
+    fn test_thin_film_spectral() {
+        // the spectral evaluation averages to the color of the rgb evaluation
+        for thickness in [250.0, 400.0, 550.0] {
+            let film = soap(thickness);
+            let rgb = film.reflectance(0.9, 1.0, Vec3f::from(1.5));
+
+            let n = 4000;
+            let mut sum = Vec3f::from(0.0);
+            for i in 0..n {
+                let lambda = LAMBDA_MIN + (i as f64 + 0.5) / n as f64 * (LAMBDA_MAX - LAMBDA_MIN);
+                let r = film
+                    .at_wavelength(lambda)
+                    .reflectance(0.9, 1.0, Vec3f::from(1.5));
+                sum += spectrum_to_rgb(r.x, lambda);
+            }
+            let spectral = sum / n as f64;
+            assert!((spectral - rgb).length() < 0.03, "{:?} {:?}", spectral, rgb);
+        }
+    }
+}
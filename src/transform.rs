@@ -0,0 +1,263 @@
+use crate::ray::Ray;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use std::ops::Mul;
+
+/// Affine Transformation (linear part followed by translation)
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(try_from = "TransformDesc", into = "TransformDesc")]
+pub struct Transform {
+    matrix: Mat3f,
+    inverse: Mat3f,
+    translation: Vec3f,
+}
+
+/// Transform as written in the scene file
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum TransformDesc {
+    /// Row-major 3x3 matrix and translation
+    Matrix {
+        matrix: [f64; 3 * 3],
+        #[serde(default)]
+        translation: Vec3f,
+    },
+    /// Scale, then rotate around x, y and z (in degrees), then translate
+    Trs {
+        #[serde(default)]
+        translation: Vec3f,
+        #[serde(default)]
+        rotation: Vec3f,
+        #[serde(default = "Scale::one")]
+        scale: Scale,
+    },
+}
+
+/// Uniform or per-axis scale
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Scale {
+    Uniform(f64),
+    Axis(Vec3f),
+}
+
+impl Scale {
+    fn one() -> Self {
+        Scale::Uniform(1.0)
+    }
+
+    fn to_vec(self) -> Vec3f {
+        match self {
+            Scale::Uniform(s) => Vec3f::from(s),
+            Scale::Axis(v) => v,
+        }
+    }
+}
+
+impl TryFrom<TransformDesc> for Transform {
+    type Error = String;
+
+    fn try_from(desc: TransformDesc) -> Result<Self, Self::Error> {
+        let (matrix, translation) = match desc {
+            TransformDesc::Matrix {
+                matrix,
+                translation,
+            } => (Mat3f::from(matrix), translation),
+            TransformDesc::Trs {
+                translation,
+                rotation,
+                scale,
+            } => (rotation_scale(rotation, scale.to_vec()), translation),
+        };
+
+        Transform::try_new(matrix, translation)
+            .ok_or_else(|| format!("Transform {:?} is not invertible", matrix))
+    }
+}
+
+impl From<Transform> for TransformDesc {
+    fn from(transform: Transform) -> Self {
+        TransformDesc::Matrix {
+            matrix: transform.matrix.m,
+            translation: transform.translation,
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new(Mat3f::diagonal(1.0), Vec3f::from(0.0))
+    }
+}
+
+/// Scale, then rotate by Euler angles in degrees, applied in x, y, z order
+fn rotation_scale(rotation: Vec3f, scale: Vec3f) -> Mat3f {
+    let (sx, cx) = rotation.x.to_radians().sin_cos();
+    let (sy, cy) = rotation.y.to_radians().sin_cos();
+    let (sz, cz) = rotation.z.to_radians().sin_cos();
+
+    let rx = Mat3f::from([1.0, 0.0, 0.0, 0.0, cx, -sx, 0.0, sx, cx]);
+    let ry = Mat3f::from([cy, 0.0, sy, 0.0, 1.0, 0.0, -sy, 0.0, cy]);
+    let rz = Mat3f::from([cz, -sz, 0.0, sz, cz, 0.0, 0.0, 0.0, 1.0]);
+
+    let s = Mat3f::from([scale.x, 0.0, 0.0, 0.0, scale.y, 0.0, 0.0, 0.0, scale.z]);
+
+    rz * ry * rx * s
+}
+
+impl Transform {
+    /// Panics if 'matrix' is not invertible
+    pub fn new(matrix: Mat3f, translation: Vec3f) -> Self {
+        match Self::try_new(matrix, translation) {
+            Some(transform) => transform,
+            None => panic!("Transform {:?} is not invertible!", matrix),
+        }
+    }
+
+    /// None if 'matrix' is not invertible
+    pub fn try_new(matrix: Mat3f, translation: Vec3f) -> Option<Self> {
+        let inverse = matrix.inverse()?;
+
+        Some(Self {
+            matrix,
+            inverse,
+            translation,
+        })
+    }
+
+    /// 'rotation' - Euler angles in degrees, applied in x, y, z order
+    /// Panics if a component of 'scale' is zero
+    pub fn from_trs(translation: Vec3f, rotation: Vec3f, scale: Vec3f) -> Self {
+        Self::new(rotation_scale(rotation, scale), translation)
+    }
+
+    pub fn point(&self, p: Vec3f) -> Vec3f {
+        self.matrix * p + self.translation
+    }
+
+    pub fn vector(&self, v: Vec3f) -> Vec3f {
+        self.matrix * v
+    }
+
+    /// Transforms normal vector with the inverse transpose
+    pub fn normal(&self, n: Vec3f) -> Vec3f {
+        Vec3f::normalize(self.inverse.transpose() * n)
+    }
+
+    pub fn inverse_point(&self, p: Vec3f) -> Vec3f {
+        self.inverse * (p - self.translation)
+    }
+
+    pub fn inverse_vector(&self, v: Vec3f) -> Vec3f {
+        self.inverse * v
+    }
+
+    /// Transforms world space ray into local space
+    /// Direction is not normalized, so distances along the ray are preserved
+    pub fn inverse_ray(&self, ray: &Ray) -> Ray {
+        Ray::new(
+            self.inverse_point(ray.origin),
+            self.inverse_vector(ray.direction),
+        )
+    }
+}
+
+/// Composition, 'rhs' is applied first
+impl Mul for Transform {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            matrix: self.matrix * rhs.matrix,
+            inverse: rhs.inverse * self.inverse,
+            translation: self.point(rhs.translation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::transform::*;
+
+    fn assert_near(a: Vec3f, b: Vec3f) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let transform = Transform::from_trs(
+            Vec3f::new(1.0, 2.0, 3.0),
+            Vec3f::new(30.0, 45.0, 60.0),
+            Vec3f::new(2.0, 0.5, 1.0),
+        );
+        let p = Vec3f::new(-1.0, 4.0, 0.5);
+        assert_near(transform.inverse_point(transform.point(p)), p);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let transform = Transform::from_trs(
+            Vec3f::from(0.0),
+            Vec3f::new(0.0, 90.0, 0.0),
+            Vec3f::from(1.0),
+        );
+        let v = transform.vector(Vec3f::new(1.0, 0.0, 0.0));
+        assert_near(v, Vec3f::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_normal() {
+        let transform = Transform::from_trs(
+            Vec3f::from(0.0),
+            Vec3f::from(0.0),
+            Vec3f::new(4.0, 1.0, 1.0),
+        );
+        // normal of the plane x = y is skewed by non-uniform scale
+        let n = transform.normal(Vec3f::normalize(Vec3f::new(1.0, -1.0, 0.0)));
+        let tangent = transform.vector(Vec3f::new(1.0, 1.0, 0.0));
+        assert!(Vec3f::dot(n, tangent).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_compose() {
+        let parent = Transform::from_trs(
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 90.0, 0.0),
+            Vec3f::from(2.0),
+        );
+        let child = Transform::from_trs(
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(45.0, 0.0, 0.0),
+            Vec3f::new(1.0, 3.0, 1.0),
+        );
+        let transform = parent * child;
+
+        let p = Vec3f::new(0.5, -1.0, 2.0);
+        assert_near(transform.point(p), parent.point(child.point(p)));
+        assert_near(transform.inverse_point(transform.point(p)), p);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let json = r#"{ "translation": [0, 1, 0], "scale": 2 }"#;
+        let transform: Transform = serde_json::from_str(json).unwrap();
+        assert_near(
+            transform.point(Vec3f::new(1.0, 0.0, 0.0)),
+            Vec3f::new(2.0, 1.0, 0.0),
+        );
+
+        let json = r#"{ "matrix": [0, -1, 0, 1, 0, 0, 0, 0, 1] }"#;
+        let transform: Transform = serde_json::from_str(json).unwrap();
+        assert_near(
+            transform.point(Vec3f::new(1.0, 0.0, 0.0)),
+            Vec3f::new(0.0, 1.0, 0.0),
+        );
+
+        // singular transforms are load errors, not panics
+        let json = r#"{ "scale": 0 }"#;
+        assert!(serde_json::from_str::<Transform>(json).is_err());
+        let json = r#"{ "matrix": [1, 0, 0, 2, 0, 0, 0, 0, 1] }"#;
+        assert!(serde_json::from_str::<Transform>(json).is_err());
+    }
+}
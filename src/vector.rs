@@ -278,6 +278,18 @@ where
     }
 }
 
+impl Vec3<f64> {
+    /// Component-wise minimum
+    pub fn min(a: Self, b: Self) -> Self {
+        Self::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+    }
+
+    /// Component-wise maximum
+    pub fn max(a: Self, b: Self) -> Self {
+        Self::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+    }
+}
+
 pub trait Lerp<T> {
     fn lerp(a: Self, b: Self, t: T) -> Self;
 }
@@ -474,6 +486,52 @@ where
     }
 }
 
+impl<T> Mat3<T>
+where
+    T: Number,
+{
+    pub fn transpose(&self) -> Self {
+        let m = self.m;
+        Self::from([m[0], m[3], m[6], m[1], m[4], m[7], m[2], m[5], m[8]])
+    }
+
+    pub fn determinant(&self) -> T {
+        let m = self.m;
+        m[0] * (m[4] * m[8] - m[5] * m[7]) - m[1] * (m[3] * m[8] - m[5] * m[6])
+            + m[2] * (m[3] * m[7] - m[4] * m[6])
+    }
+}
+
+impl Mat3<f64> {
+    /// Returns inverse matrix, or None if matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        let m = self.m;
+
+        // relative to the largest determinant columns of these lengths can have,
+        // so uniformly scaled matrices are invertible at any scale
+        let column = |i: usize| f64::sqrt(m[i] * m[i] + m[i + 3] * m[i + 3] + m[i + 6] * m[i + 6]);
+        let bound = column(0) * column(1) * column(2);
+        if !det.is_finite() || det.abs() <= f64::EPSILON * bound {
+            return None;
+        }
+
+        let adjugate = Self::from([
+            m[4] * m[8] - m[5] * m[7],
+            m[2] * m[7] - m[1] * m[8],
+            m[1] * m[5] - m[2] * m[4],
+            m[5] * m[6] - m[3] * m[8],
+            m[0] * m[8] - m[2] * m[6],
+            m[2] * m[3] - m[0] * m[5],
+            m[3] * m[7] - m[4] * m[6],
+            m[1] * m[6] - m[0] * m[7],
+            m[0] * m[4] - m[1] * m[3],
+        ]);
+
+        Some(Self::from(adjugate.m.map(|value| value / det)))
+    }
+}
+
 impl<T> Default for Mat3<T>
 where
     T: Number + std::default::Default,
@@ -692,4 +750,25 @@ mod tests {
 
         assert_eq!(mat * v0, v1)
     }
+
+    #[test]
+    fn test_mat3_inverse() {
+        let a = Mat3::from([2.0, 0.0, 0.0, 0.0, 4.0, 0.0, 0.0, 0.0, 8.0]);
+        let b = Mat3::from([0.5, 0.0, 0.0, 0.0, 0.25, 0.0, 0.0, 0.0, 0.125]);
+        assert_eq!(a.inverse(), Some(b));
+
+        let c = Mat3::from([2.0, 7.0, 3.0, 1.0, 5.0, 8.0, 0.0, 4.0, 1.0]);
+        let identity = c * c.inverse().unwrap();
+        for (value, expected) in identity.m.iter().zip(Mat3::diagonal(1.0).m) {
+            assert!((value - expected).abs() < 1e-12);
+        }
+
+        assert_eq!(Mat3::from(1.0).inverse(), None);
+        let dependent = Mat3::from([1.0, 2.0, 0.0, 2.0, 4.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(dependent.inverse(), None);
+
+        let small = Mat3::diagonal(1e-6).inverse().unwrap();
+        assert!((small.m[0] - 1e6).abs() < 1e-3);
+        assert_eq!(Mat3::diagonal(f64::INFINITY).inverse(), None);
+    }
 }